@@ -255,6 +255,8 @@ checkdep lz4
 ### Default configuration for this script
 PEPTIDE_MIN_LENGTH=5 # What is the minimum length (inclusive) for tryptic peptides?"
 PEPTIDE_MAX_LENGTH=50 # What is the maximum length (inclusive) for tryptic peptides?"
+PROTEASE="trypsin" # Which protease (or Expasy PeptideCutter cleavage rule) should I digest the proteins with?
+EQUALIZATION="LI" # Which amino acid equivalence classes should the equalized sequences and their LCA's use (eg. "LI,KQ,ND,QE")?
 TABDIR="$OUTPUT_DIR" # Where should I store the final TSV files (large, single-write)?
 INTDIR="$TEMP_DIR/$UNIPEPT_TEMP_CONSTANT" # Where should I store intermediate TSV files (large, single-write, multiple-read?
 KMER_LENGTH=9 # What is the length (k) of the K-mer peptides?
@@ -426,9 +428,13 @@ download_and_convert_all_sources() {
     if [[ $DB_SOURCE == *xml.gz ]] || url_points_to_xml "$DB_SOURCE"
     then
       PARSER="xml-parser"
+      PARSER_ARGS=(-t "$DB_TYPE")
     elif [[ $DB_SOURCE == *dat.gz ]]
     then
+      # The DAT parser derives the status of every entry from its ID line. Entries with a sequence that doesn't match
+      # its SQ line are written to a reject file next to the index, instead of aborting the whole build.
       PARSER="dat-parser"
+      PARSER_ARGS=(--sequence-check error --skip-invalid "$DB_INDEX_OUTPUT/rejected.dat")
     else
       errorAndExit "No known parser available for provided UniProtKB file format. Only XML and DAT are available."
     fi
@@ -445,7 +451,7 @@ download_and_convert_all_sources() {
 
       reportProgress -1 "Downloading database index for $DB_TYPE." 3
 
-      curl --continue-at - --create-dirs "$DB_SOURCE" --silent | pigz -dc | $CURRENT_LOCATION/helper_scripts/$PARSER -t "$DB_TYPE" | $CURRENT_LOCATION/helper_scripts/write-to-chunk --output-dir "$DB_INDEX_OUTPUT"
+      curl --continue-at - --create-dirs "$DB_SOURCE" --silent | $CURRENT_LOCATION/helper_scripts/$PARSER "${PARSER_ARGS[@]}" | $CURRENT_LOCATION/helper_scripts/write-to-chunk --output-dir "$DB_INDEX_OUTPUT"
 
       # Now, compress the different chunks
       CHUNKS=$(find "$DB_INDEX_OUTPUT" -name "*.chunk")
@@ -489,7 +495,7 @@ download_and_convert_all_sources() {
 
         SIZE="$(curl -I "$DB_SOURCE" -s | grep -i content-length | tr -cd '[0-9]')"
 
-        curl --continue-at - --create-dirs "$DB_SOURCE" --silent | pv -i 5 -n -s "$SIZE" 2> >(reportProgress - "Downloading database index for $DB_TYPE." 3 >&2) | pigz -dc | $CURRENT_LOCATION/helper_scripts/$PARSER -t "$DB_TYPE" | $CURRENT_LOCATION/helper_scripts/write-to-chunk --output-dir "$DB_INDEX_OUTPUT"
+        curl --continue-at - --create-dirs "$DB_SOURCE" --silent | pv -i 5 -n -s "$SIZE" 2> >(reportProgress - "Downloading database index for $DB_TYPE." 3 >&2) | $CURRENT_LOCATION/helper_scripts/$PARSER "${PARSER_ARGS[@]}" | $CURRENT_LOCATION/helper_scripts/write-to-chunk --output-dir "$DB_INDEX_OUTPUT"
 
         # Now, compress the different chunks
         CHUNKS=$(find "$DB_INDEX_OUTPUT" -name "*.chunk")
@@ -564,7 +570,10 @@ create_most_tables() {
 		--uniprot-entries "$(lz "$OUTPUT_DIR/uniprot_entries.tsv.lz4")" \
 		--ec "$(lz "$OUTPUT_DIR/ec_cross_references.tsv.lz4")" \
 		--go "$(lz "$OUTPUT_DIR/go_cross_references.tsv.lz4")" \
-		--interpro "$(lz "$OUTPUT_DIR/interpro_cross_references.tsv.lz4")"
+		--interpro "$(lz "$OUTPUT_DIR/interpro_cross_references.tsv.lz4")" \
+		--protease "$PROTEASE" \
+		--equalize "$EQUALIZATION" \
+		--build-info "$OUTPUT_DIR/build_info.tsv"
 
   log "Started sorting peptides table"
 
@@ -0,0 +1,196 @@
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use unipept_database::dat_parser::entry::UniProtDATEntry;
+use unipept_database::dat_parser::sequential_parser::SequentialDATParser;
+use unipept_database::dat_parser::threaded_parser::{
+    ThreadedDATParser, DEFAULT_BATCH_SIZE, DEFAULT_BUFFER_SIZE,
+};
+
+mod legacy_entry;
+
+const RESIDUES: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Generate a synthetic unreviewed entry that resembles an average TrEMBL entry
+fn trembl_entry(index: usize) -> String {
+    let length = 200 + (index * 37) % 400;
+
+    // Deterministic pseudo-random sequence
+    let mut state = index as u64 + 1;
+    let sequence: Vec<u8> = (0..length)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            RESIDUES[(state >> 33) as usize % RESIDUES.len()]
+        })
+        .collect();
+
+    let mut entry = format!(
+        "ID   A0A{index:07}_9BACT        Unreviewed;       {length} AA.
+AC   A0A{index:07};
+DT   10-APR-2019, integrated into UniProtKB/TrEMBL.
+DT   10-APR-2019, sequence version 1.
+DT   08-NOV-2023, entry version 12.
+DE   SubName: Full=ABC transporter ATP-binding protein {{ECO:0000313|EMBL:QBH{index:05}.1}};
+DE            EC=7.6.2.{} {{ECO:0000313|EMBL:QBH{index:05}.1}};
+GN   ORFNames=E5K{index:05} {{ECO:0000313|EMBL:QBH{index:05}.1}};
+OS   Bacteroidetes bacterium.
+OC   Bacteria; Bacteroidota.
+OX   NCBI_TaxID=1898104 {{ECO:0000313|EMBL:QBH{index:05}.1, ECO:0000313|Proteomes:UP000292345}};
+DR   EMBL; CP036{index:03}; QBH{index:05}.1; -; Genomic_DNA.
+DR   AlphaFoldDB; A0A{index:07}; -.
+DR   GO; GO:0005524; F:ATP binding; IEA:UniProtKB-KW.
+DR   GO; GO:0016887; F:ATP hydrolysis activity; IEA:InterPro.
+DR   InterPro; IPR003593; AAA+_ATPase.
+DR   InterPro; IPR003439; ABC_transporter-like_ATP-bd.
+DR   Pfam; PF00005; ABC_tran; 1.
+DR   Proteomes; UP000292345; Chromosome.
+PE   4: Predicted;
+KW   ATP-binding {{ECO:0000313|EMBL:QBH{index:05}.1}}; Nucleotide-binding;
+KW   Reference proteome {{ECO:0000313|Proteomes:UP000292345}}.
+FT   DOMAIN          5..240
+FT                   /note=\"ABC transporter\"
+FT                   /evidence=\"ECO:0000259|PROSITE:PS50893\"
+SQ   SEQUENCE   {length} AA;  {} MW;  0000000000000000 CRC64;
+",
+        index % 100,
+        length * 110
+    );
+
+    for line in sequence.chunks(60) {
+        entry.push_str("    ");
+        for block in line.chunks(10) {
+            entry.push(' ');
+            entry.push_str(std::str::from_utf8(block).unwrap());
+        }
+        entry.push('\n');
+    }
+
+    entry.push_str("//\n");
+    entry
+}
+
+fn synthetic_input(entries: usize) -> Vec<u8> {
+    (0..entries)
+        .map(trembl_entry)
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Split the input into the raw chunks the threaded parser sends to its consumers,
+/// without the "\n//" terminator
+fn chunks(input: &[u8]) -> Vec<&[u8]> {
+    let mut result = Vec::new();
+    let mut start = 0;
+
+    for (i, window) in input.windows(4).enumerate() {
+        if window == b"\n//\n" {
+            result.push(&input[start..i]);
+            start = i + 4;
+        }
+    }
+
+    result
+}
+
+fn bench_entry_parsing(c: &mut Criterion) {
+    let input = synthetic_input(10_000);
+    let chunks = chunks(&input);
+
+    let mut group = c.benchmark_group("entry");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+
+    // The previous path: copy every line into its own String, and parse those with the previous parser
+    group.bench_function("legacy_from_lines", |b| {
+        b.iter(|| {
+            for chunk in &chunks {
+                let mut lines: Vec<String> = String::from_utf8_lossy(chunk)
+                    .split('\n')
+                    .map(|x| x.to_string())
+                    .collect();
+                black_box(legacy_entry::UniProtDATEntry::from_lines(&mut lines).unwrap());
+            }
+        })
+    });
+
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| {
+            for chunk in &chunks {
+                black_box(UniProtDATEntry::from_bytes(chunk).unwrap());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_sequential_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sequential");
+
+    for entries in [1_000, 10_000] {
+        let input = synthetic_input(entries);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(entries), &input, |b, input| {
+            b.iter(|| {
+                for entry in SequentialDATParser::new(Cursor::new(input)) {
+                    black_box(entry.unwrap());
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Throughput of the threaded parser when varying one setting, keeping the others at their default
+fn bench_threaded_parser(c: &mut Criterion) {
+    const THREADS: usize = 4;
+
+    let input = synthetic_input(10_000);
+    let parse = |input: &Vec<u8>, batch_size: usize, capacity: usize, buffer_size: usize| {
+        let parser = ThreadedDATParser::new(Cursor::new(input.clone()), THREADS)
+            .with_batch_size(batch_size)
+            .with_channel_capacity(capacity)
+            .with_buffer_size(buffer_size);
+        for entry in parser {
+            black_box(entry.unwrap());
+        }
+    };
+
+    let mut group = c.benchmark_group("threaded");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+
+    for batch_size in [1, 16, DEFAULT_BATCH_SIZE, 256] {
+        group.bench_with_input(
+            BenchmarkId::new("batch_size", batch_size),
+            &input,
+            |b, input| b.iter(|| parse(input, batch_size, THREADS * 2, DEFAULT_BUFFER_SIZE)),
+        );
+    }
+
+    for capacity in [1, THREADS * 2, 64] {
+        group.bench_with_input(
+            BenchmarkId::new("channel_capacity", capacity),
+            &input,
+            |b, input| b.iter(|| parse(input, DEFAULT_BATCH_SIZE, capacity, DEFAULT_BUFFER_SIZE)),
+        );
+    }
+
+    for buffer_size in [8 * 1024, DEFAULT_BUFFER_SIZE, 1024 * 1024] {
+        group.bench_with_input(
+            BenchmarkId::new("buffer_size", buffer_size),
+            &input,
+            |b, input| b.iter(|| parse(input, DEFAULT_BATCH_SIZE, THREADS * 2, buffer_size)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_entry_parsing,
+    bench_sequential_parser,
+    bench_threaded_parser
+);
+criterion_main!(benches);
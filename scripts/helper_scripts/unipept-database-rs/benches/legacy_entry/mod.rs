@@ -0,0 +1,317 @@
+//! Copy of the line-based entry parser that `UniProtDATEntry::from_bytes` replaced,
+//! so the benchmark can compare both implementations
+//! It is kept as it was, apart from the output functions
+
+#![allow(clippy::ptr_arg)]
+
+use anyhow::Context;
+use std::collections::HashSet;
+
+// Constants to aid in parsing
+const COMMON_PREFIX_LEN: usize = "ID   ".len();
+
+const ORGANISM_RECOMMENDED_NAME_PREFIX_LEN: usize = "RecName: Full=".len();
+const ORGANISM_RECOMMENDED_NAME_EC_PREFIX_LEN: usize = "EC=".len();
+const ORGANISM_TAXON_ID_PREFIX_LEN: usize = "OX   NCBI_TaxID=".len();
+const VERSION_STRING_FULL_PREFIX_LEN: usize = "DT   08-NOV-2023, entry version ".len();
+
+/// The minimal data we want from an entry out of the UniProtKB datasets
+// The fields are only kept to be benchmarked, never read
+#[allow(dead_code)]
+pub struct UniProtDATEntry {
+    accession_number: String,
+    name: String,
+    sequence: String,
+    version: String,
+    ec_references: Vec<String>,
+    go_references: Vec<String>,
+    ip_references: Vec<String>,
+    taxon_id: String,
+}
+
+impl UniProtDATEntry {
+    /// Parse an entry out of the lines of a DAT file
+    pub fn from_lines(data: &mut Vec<String>) -> anyhow::Result<Self> {
+        let mut current_index: usize = 0;
+
+        let accession_number = parse_ac_number(data).context("Error parsing accession number")?;
+        let version = parse_version(data, &mut current_index);
+        let (name, ec_references) = parse_name_and_ec(data, &mut current_index);
+        let taxon_id = parse_taxon_id(data, &mut current_index);
+        let (go_references, ip_references) = parse_db_references(data, &mut current_index);
+        let sequence = parse_sequence(data, current_index);
+
+        Ok(Self {
+            accession_number,
+            name,
+            sequence,
+            version,
+            ec_references,
+            go_references,
+            ip_references,
+            taxon_id,
+        })
+    }
+}
+
+// Functions to parse an Entry out of a Vec<String>
+
+/// Find the first AC number
+fn parse_ac_number(data: &mut [String]) -> anyhow::Result<String> {
+    // The AC number is always the second element
+    let line = &mut data[1];
+    line.drain(..COMMON_PREFIX_LEN);
+    let (pre, _) = line
+        .split_once(';')
+        .with_context(|| format!("Unable to split \"{line}\" on ';'"))?;
+    Ok(pre.to_string())
+}
+
+/// Find the version of this entry
+fn parse_version(data: &[String], index: &mut usize) -> String {
+    let mut last_field: usize = 2;
+
+    // Skip past previous fields to get to the dates
+    while !data[last_field].starts_with("DT") {
+        last_field += 1;
+    }
+
+    // The date fields are always the third-n elements
+    // The version is always the last one
+    while data[last_field + 1].starts_with("DT") {
+        last_field += 1;
+    }
+
+    // Get entry version (has prefix of constant length and ends with a dot)
+    let version_end = data[last_field].len() - 1;
+    *index = last_field + 1;
+    data[last_field][VERSION_STRING_FULL_PREFIX_LEN..version_end].to_string()
+}
+
+/// Parse the name and EC numbers of an entry out of all available DE fields
+/// In order of preference:
+/// - Last recommended name of protein components
+/// - Last recommended name of protein domains
+/// - Recommended name of protein itself
+/// - Last submitted name of protein components
+/// - Last submitted name of protein domains
+/// - Submitted name of protein itself
+fn parse_name_and_ec(data: &mut [String], index: &mut usize) -> (String, Vec<String>) {
+    // Find where the info starts and ends
+    while !data[*index].starts_with("DE") {
+        *index += 1;
+    }
+
+    let mut name = String::new();
+    let mut ec_references = Vec::new();
+    let mut ec_reference_set = HashSet::new();
+    let mut end_index = *index;
+
+    // Track all names in order of preference
+    let mut name_indices: [usize; 6] = [usize::MAX; 6];
+    const LAST_COMPONENT_RECOMMENDED_IDX: usize = 0;
+    const LAST_COMPONENT_SUBMITTED_IDX: usize = 3;
+    const LAST_DOMAIN_RECOMMENDED_IDX: usize = 1;
+    const LAST_DOMAIN_SUBMITTED_IDX: usize = 4;
+    const LAST_PROTEIN_RECOMMENDED_IDX: usize = 2;
+    const LAST_PROTEIN_SUBMITTED_IDX: usize = 5;
+
+    // Keep track of which block we are currently in
+    // Order in DAT file is always protein -> components -> domain
+    let mut inside_domain = false;
+    let mut inside_component = false;
+
+    while data[end_index].starts_with("DE") {
+        let line = &mut data[end_index];
+        line.drain(..COMMON_PREFIX_LEN);
+
+        // Marks the start of a Component
+        if line == "Contains:" {
+            inside_component = true;
+            end_index += 1;
+            continue;
+        }
+
+        // Marks the start of a Domain
+        if line == "Includes:" {
+            inside_domain = true;
+            end_index += 1;
+            continue;
+        }
+
+        // Remove all other spaces (consecutive lines have leading spaces we don't care for)
+        drain_leading_spaces(line);
+
+        // Keep track of the last recommended name
+        if line.starts_with("RecName: Full=") {
+            if inside_domain {
+                name_indices[LAST_DOMAIN_RECOMMENDED_IDX] = end_index;
+            } else if inside_component {
+                name_indices[LAST_COMPONENT_RECOMMENDED_IDX] = end_index;
+            } else {
+                name_indices[LAST_PROTEIN_RECOMMENDED_IDX] = end_index;
+            }
+        }
+        // Find EC numbers
+        else if line.starts_with("EC=") {
+            let ec_target = read_until_metadata(line, ORGANISM_RECOMMENDED_NAME_EC_PREFIX_LEN);
+
+            // EC numbers sometimes appear multiple times, so use a set to track which ones
+            // we've seen before
+            if !ec_reference_set.contains(&ec_target) {
+                ec_reference_set.insert(ec_target.clone());
+                ec_references.push(ec_target);
+            }
+        }
+        // Keep track of the last submitted name
+        else if line.starts_with("SubName: Full=") {
+            if inside_domain {
+                name_indices[LAST_DOMAIN_SUBMITTED_IDX] = end_index;
+            } else if inside_component {
+                name_indices[LAST_COMPONENT_SUBMITTED_IDX] = end_index;
+            } else {
+                name_indices[LAST_PROTEIN_SUBMITTED_IDX] = end_index;
+            }
+        }
+
+        end_index += 1;
+    }
+
+    // Choose a name from the ones we encountered
+    // Use the first name that we managed to find, in order
+    for idx in name_indices {
+        if idx != usize::MAX {
+            let line = &mut data[idx];
+            *index = end_index;
+            name = read_until_metadata(line, ORGANISM_RECOMMENDED_NAME_PREFIX_LEN);
+            return (name, ec_references);
+        }
+    }
+
+    (name, ec_references)
+}
+
+/// Find the first NCBI_TaxID of this entry
+fn parse_taxon_id(data: &mut [String], index: &mut usize) -> String {
+    while !data[*index].starts_with("OX   NCBI_TaxID=") {
+        *index += 1;
+    }
+
+    let line = &mut data[*index];
+    let taxon_id = read_until_metadata(line, ORGANISM_TAXON_ID_PREFIX_LEN);
+
+    while data[*index].starts_with("OX") {
+        *index += 1;
+    }
+
+    taxon_id
+}
+
+/// Parse GO and InterPro DB references
+fn parse_db_references(data: &mut Vec<String>, index: &mut usize) -> (Vec<String>, Vec<String>) {
+    let mut go_references = Vec::new();
+    let mut ip_references = Vec::new();
+    let original_idx = *index;
+    let length = data.len();
+
+    // Find where references start
+    while !data[*index].starts_with("DR") {
+        *index += 1;
+
+        // No references present in this entry
+        if *index == length {
+            *index = original_idx;
+            return (go_references, ip_references);
+        }
+    }
+
+    // Parse all references
+    while data[*index].starts_with("DR") {
+        let line = &mut data[*index];
+        line.drain(..COMMON_PREFIX_LEN);
+
+        parse_db_reference(line, &mut go_references, &mut ip_references);
+
+        *index += 1;
+    }
+
+    (go_references, ip_references)
+}
+
+/// Parse a single GO or InterPro DB reference
+fn parse_db_reference(
+    line: &mut str,
+    go_references: &mut Vec<String>,
+    ip_references: &mut Vec<String>,
+) {
+    if line.starts_with("GO;") {
+        let substr = &line[4..14];
+        go_references.push(substr.to_string());
+    } else if line.starts_with("InterPro;") {
+        let substr = &line[10..19];
+        ip_references.push(substr.to_string());
+    }
+}
+
+/// Parse the peptide sequence for this entry
+fn parse_sequence(data: &mut [String], mut index: usize) -> String {
+    // Find the beginning of the sequence
+    // optionally skip over some fields we don't care for
+    while !data[index].starts_with("SQ") {
+        index += 1;
+    }
+
+    // First line of the sequence contains some metadata we don't care for
+    index += 1;
+
+    let mut sequence = String::new();
+
+    // Combine all remaining lines
+    for line in data.iter_mut().skip(index) {
+        line.drain(..COMMON_PREFIX_LEN);
+        sequence.push_str(&line.replace(' ', ""));
+    }
+
+    sequence
+}
+
+/// Read a line until additional metadata starts
+/// Some lines end with {blocks between curly brackets} that we don't care for.
+fn read_until_metadata(line: &mut String, prefix_len: usize) -> String {
+    line.drain(..prefix_len);
+
+    // The line either contains some metadata, or just ends with a semicolon
+    // In the latter case, move the position to the end of the string,
+    // so we can pretend it is at a bracket and cut the semicolon out
+    // If it contains metadata, this wrapped in curly braces after a space
+    // (sometimes there are curly braces inside of the name itself, so just a curly is not enough)
+    let mut bracket_index = 0;
+    let mut previous_char = '\0';
+
+    for (i, c) in line.chars().enumerate() {
+        if c == '{' && previous_char == ' ' {
+            bracket_index = i;
+            break;
+        }
+
+        previous_char = c;
+    }
+
+    if bracket_index == 0 {
+        bracket_index = line.len();
+    }
+
+    line[..bracket_index - 1].to_string()
+}
+
+/// Remove all leading spaces from a line
+/// Internally this just moves a pointer forward so this is very efficient
+fn drain_leading_spaces(line: &mut String) {
+    // Find the first index that is not a space, and remove everything before
+    for (idx, c) in line.chars().enumerate() {
+        if c != ' ' {
+            line.drain(..idx);
+            break;
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use clap::Parser;
+use strum_macros::{Display, EnumString};
+use uniprot::uniparc::UniParc;
+use uniprot::uniref::UniRef;
+
+use unipept_database::calculate_lcas::taxonomy::Taxonomy;
+use unipept_database::cluster_parser::{uniparc, uniref, write_records, ClusterRows};
+use unipept_database::dat_parser::sink::{open_sink, OutputFormat};
+use unipept_database::dat_parser::utils::OutputOptions;
+use unipept_database::utils::files::open_input;
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    let options = OutputOptions {
+        db_type_override: args.db_type.clone(),
+        ..OutputOptions::default()
+    };
+
+    // UniRef clusters list their common taxon, for UniParc entries it is calculated from the lineages
+    let taxonomy = match &args.lineages {
+        Some(lineages) => Some(Taxonomy::build(lineages).context("Unable to build taxonomy")?),
+        None if args.database == ClusterDatabase::UniParc
+            && args.rows == ClusterRows::Representative =>
+        {
+            return Err(Error::msg(
+                "--lineages is required to calculate the common taxon of UniParc entries",
+            ));
+        }
+        None => None,
+    };
+
+    let mut sink = open_sink(args.format, args.output.as_ref(), &options)?;
+
+    // Read from stdin if no input files were given
+    let inputs: Vec<Option<&PathBuf>> = if args.inputs.is_empty() {
+        vec![None]
+    } else {
+        args.inputs.iter().map(Some).collect()
+    };
+
+    for input in inputs {
+        let reader = open_input(input, args.threads)?;
+
+        match args.database {
+            ClusterDatabase::UniRef => write_records::<UniRef, _>(
+                reader,
+                args.threads,
+                args.rows,
+                taxonomy.as_ref(),
+                &options,
+                &mut sink,
+                uniref::records,
+            ),
+            ClusterDatabase::UniParc => write_records::<UniParc, _>(
+                reader,
+                args.threads,
+                args.rows,
+                taxonomy.as_ref(),
+                &options,
+                &mut sink,
+                uniparc::records,
+            ),
+        }
+        .with_context(|| match input {
+            Some(pb) => format!("Error parsing \"{}\"", pb.display()),
+            None => "Error parsing stdin".to_string(),
+        })?;
+    }
+
+    sink.finish().context("Error writing output")
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+enum ClusterDatabase {
+    #[strum(serialize = "uniref")]
+    UniRef,
+    #[strum(serialize = "uniparc")]
+    UniParc,
+}
+
+// Parse UniRef (100, 90 or 50) or UniParc XML files into the same TSV-file as the DAT parser
+// Clusters have no EC numbers or GO terms, UniParc entries get the InterPro entries of their signature matches
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Database the XML files belong to: uniref or uniparc
+    #[clap(long)]
+    database: ClusterDatabase,
+    /// XML files to parse, which can be compressed with gzip, bgzip, zstd or lz4. Reads from stdin if omitted
+    inputs: Vec<PathBuf>,
+    /// Rows to write for every cluster: "representative" writes one row with the common taxon of the cluster,
+    /// "members" writes one row per member with the taxon of that member, which is only supported for UniRef100
+    /// as the members of UniRef90 and UniRef50 clusters don't share the sequence of the representative
+    #[clap(long, default_value_t = ClusterRows::Representative)]
+    rows: ClusterRows,
+    /// Lineages TSV file, used to calculate the common taxon (lowest common ancestor) of the members of a UniParc entry
+    /// Required for representative rows of UniParc entries
+    #[clap(long)]
+    lineages: Option<PathBuf>,
+    /// Format to write the entries in: tsv, jsonl, arrow or parquet
+    #[clap(long, default_value_t = OutputFormat::Tsv)]
+    format: OutputFormat,
+    /// File to write the entries to, instead of stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Value for the "Status" column, "trembl" by default
+    #[clap(short = 't', long)]
+    db_type: Option<String>,
+    #[clap(long, default_value_t = 0)]
+    threads: usize,
+}
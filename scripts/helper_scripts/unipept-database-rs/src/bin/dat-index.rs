@@ -0,0 +1,88 @@
+use std::io::{stdout, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error, Result};
+use clap::{Parser, Subcommand};
+use unipept_database::dat_parser::index::{build_index, DatIndex};
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    match args.command {
+        Command::Build {
+            inputs,
+            output,
+            threads,
+        } => {
+            let count = build_index(&inputs, &output, threads)
+                .with_context(|| format!("Error building index \"{}\"", output.display()))?;
+            eprintln!("Indexed {} entries in \"{}\"", count, output.display());
+        }
+        Command::Lookup { index, accessions } => lookup(&index, &accessions)?,
+    }
+
+    Ok(())
+}
+
+/// Write the raw DAT entries of all accessions to stdout
+fn lookup(index: &Path, accessions: &[String]) -> Result<()> {
+    let mut index = DatIndex::open(index)
+        .with_context(|| format!("Error opening index \"{}\"", index.display()))?;
+    let mut writer = BufWriter::new(stdout().lock());
+    let mut missing: u64 = 0;
+
+    for accession in accessions {
+        match index
+            .fetch_raw(accession)
+            .with_context(|| format!("Error reading entry {}", accession))?
+        {
+            Some(data) => {
+                writer.write_all(&data)?;
+                writer.write_all(b"\n")?;
+            }
+            None => {
+                eprintln!("Entry {} not found in the index", accession);
+                missing += 1;
+            }
+        }
+    }
+
+    writer.flush().context("Error writing entries")?;
+
+    if missing > 0 {
+        return Err(Error::msg(format!("{} entries were not found", missing)));
+    }
+
+    Ok(())
+}
+
+// Index UniProtKB DAT files to look up single entries without parsing the whole file
+#[derive(Parser, Debug)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build an index for one or more DAT files, which have to be uncompressed or compressed with bgzip
+    Build {
+        /// DAT files to index
+        #[clap(required = true)]
+        inputs: Vec<PathBuf>,
+        /// File to write the index to
+        #[clap(short, long)]
+        output: PathBuf,
+        #[clap(long, default_value_t = 0)]
+        threads: usize,
+    },
+    /// Write the DAT entries of accession numbers to stdout
+    Lookup {
+        /// Index created with the "build" command
+        #[clap(short, long)]
+        index: PathBuf,
+        /// Primary accession numbers of the entries
+        #[clap(required = true)]
+        accessions: Vec<String>,
+    },
+}
@@ -1,30 +1,199 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
 use clap::Parser;
-use unipept_database::dat_parser::uniprot_dat_parser;
-use unipept_database::dat_parser::utils::write_header;
-
-use unipept_database::utils::files::open_sin;
+use unipept_database::dat_parser::entry::UniProtDATEntry;
+use unipept_database::dat_parser::error::InvalidEntryError;
+use unipept_database::dat_parser::filter::{EntryFilter, FilterArgs};
+use unipept_database::dat_parser::isoforms::expand_isoforms;
+use unipept_database::dat_parser::sequence_check::SequenceCheck;
+use unipept_database::dat_parser::sink::{open_sink, EntrySink, OutputFormat};
+use unipept_database::dat_parser::uniprot_dat_file_parser;
+use unipept_database::dat_parser::utils::{
+    unique_xrefs, warn_contradicting_status, GoEvidenceFilter, OutputOptions, DEFAULT_XREFS,
+};
+use unipept_database::dat_parser::variants::VariantKind;
 
 fn main() -> Result<()> {
     let args = Cli::parse();
-    let reader = open_sin();
 
-    write_header();
-    let parser = uniprot_dat_parser(reader, args.threads);
+    let mut rejects = match &args.skip_invalid {
+        Some(pb) => Some(BufWriter::new(File::create(pb).with_context(|| {
+            format!("Unable to create reject file \"{}\"", pb.display())
+        })?)),
+        None => None,
+    };
+    let mut rejected: u64 = 0;
+    let mut contradicting: u64 = 0;
+
+    let options = OutputOptions {
+        db_type_override: args.db_type.clone(),
+        xrefs: unique_xrefs(&args.xref),
+        go_evidence: GoEvidenceFilter::new(
+            args.go_evidence_include.clone(),
+            args.go_evidence_exclude.clone(),
+        ),
+        go_evidence_column: args.go_evidence_column,
+        variants: args.variants.clone(),
+    };
+
+    let filter = args.filter.clone().into_filter()?;
+    let mut sink = open_sink(args.format, args.output.as_ref(), &options)?;
+
+    // Read from stdin if no input files were given
+    let inputs: Vec<Option<&PathBuf>> = if args.inputs.is_empty() {
+        vec![None]
+    } else {
+        args.inputs.iter().map(Some).collect()
+    };
+
+    for input in inputs {
+        let parser =
+            uniprot_dat_file_parser(input, args.threads, args.ordered, args.sequence_check)?;
 
-    for entry in parser {
-        entry
-            .context("Error parsing DAT entry")?
-            .write(&args.db_type);
+        for entry in parser {
+            let error = match entry {
+                Ok(entry) if !filter.matches(&entry) => continue,
+                Ok(entry) => {
+                    contradicting +=
+                        entry.contradicts_db_type(options.db_type_override.as_deref()) as u64;
+                    sink.write(&entry.record(&options))
+                        .context("Error writing entry")?;
+                    if args.isoforms {
+                        write_isoforms(&entry, &filter, &options, &mut sink)?;
+                    }
+                    continue;
+                }
+                Err(e) => e,
+            };
+
+            // Only invalid entries can be skipped, other errors (eg. failing to read the input) are fatal
+            let (Some(writer), Some(invalid)) =
+                (rejects.as_mut(), error.downcast_ref::<InvalidEntryError>())
+            else {
+                return Err(error.context(format!(
+                    "Error parsing DAT entry from {}",
+                    input_name(input)
+                )));
+            };
+
+            eprintln!(
+                "Skipping invalid entry in {}: {:#}",
+                input_name(input),
+                error
+            );
+            write_reject(writer, invalid).context("Error writing to reject file")?;
+            rejected += 1;
+        }
+    }
+
+    sink.finish().context("Error writing output")?;
+    warn_contradicting_status(contradicting, &options);
+
+    if let (Some(mut writer), Some(pb)) = (rejects, &args.skip_invalid) {
+        writer.flush().context("Error writing to reject file")?;
+        eprintln!(
+            "Skipped {} invalid entries, written to \"{}\"",
+            rejected,
+            pb.display()
+        );
     }
 
     Ok(())
 }
 
+/// Write the alternative isoforms of an entry, isoforms that can't be created are only logged
+fn write_isoforms(
+    entry: &UniProtDATEntry,
+    filter: &EntryFilter,
+    options: &OutputOptions,
+    sink: &mut Box<dyn EntrySink>,
+) -> Result<()> {
+    let isoforms = match expand_isoforms(entry) {
+        Ok(isoforms) => isoforms,
+        Err(e) => {
+            eprintln!("Warning: {:#}", e);
+            return Ok(());
+        }
+    };
+
+    for isoform in isoforms {
+        match isoform {
+            Ok(isoform) if !filter.matches(&isoform) => {}
+            Ok(isoform) => sink
+                .write(&isoform.record(options))
+                .context("Error writing isoform")?,
+            Err(e) => eprintln!("Warning: {:#}", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn input_name(input: Option<&PathBuf>) -> String {
+    match input {
+        Some(pb) => format!("\"{}\"", pb.display()),
+        None => "stdin".to_string(),
+    }
+}
+
+/// Write the raw data of an invalid entry, so the reject file is a DAT file itself
+fn write_reject(writer: &mut BufWriter<File>, invalid: &InvalidEntryError) -> Result<()> {
+    writer.write_all(invalid.data())?;
+    writer.write_all(b"\n//\n")?;
+    Ok(())
+}
+
 #[derive(Parser, Debug)]
 struct Cli {
-    #[clap(short = 't', long, default_value = "swissprot")]
-    db_type: String,
+    /// DAT files to parse, which can be compressed with gzip, bgzip, zstd or lz4. Reads from stdin if omitted
+    inputs: Vec<PathBuf>,
+    /// Format to write the entries in: tsv, jsonl, arrow or parquet
+    #[clap(long, default_value_t = OutputFormat::Tsv)]
+    format: OutputFormat,
+    /// File to write the entries to, instead of stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Override the "Status" column, which is derived from the ID line of every entry by default
+    #[clap(short = 't', long)]
+    db_type: Option<String>,
     #[clap(long, default_value_t = 0)]
     threads: usize,
+    /// Write the entries in the same order as the input, regardless of the amount of threads
+    #[clap(long, default_value_t = false)]
+    ordered: bool,
+    /// Check every sequence against the length and CRC64 checksum on its SQ line, to catch truncated input
+    /// "error" treats mismatches as invalid entries, "warn" only logs them and "off" disables the check
+    /// Unlike the library default ("off"), this defaults to "error", since the input is usually a fresh download
+    #[clap(long, default_value_t = SequenceCheck::Error)]
+    sequence_check: SequenceCheck,
+    /// Databases to extract cross-references (DR lines) for
+    /// GO and InterPro fill their default columns, other databases are written to extra columns at the end
+    #[clap(long, value_delimiter = ',', default_values_t = DEFAULT_XREFS.map(String::from))]
+    xref: Vec<String>,
+    /// Only keep GO annotations with one of these evidence codes (eg. "EXP,IDA,IMP")
+    #[clap(long, value_delimiter = ',')]
+    go_evidence_include: Vec<String>,
+    /// Drop GO annotations with one of these evidence codes (eg. "IEA")
+    #[clap(long, value_delimiter = ',')]
+    go_evidence_exclude: Vec<String>,
+    /// Write the evidence codes of the GO annotations to an extra "Gene ontology evidence" column
+    #[clap(long, default_value_t = false)]
+    go_evidence_column: bool,
+    /// Skip entries that can't be parsed instead of stopping, and write them to this file
+    #[clap(long, value_name = "REJECT_FILE")]
+    skip_invalid: Option<PathBuf>,
+    /// Write the sequence variants described by these features to an extra "Sequence variants" column,
+    /// which can be used by taxons-uniprots-tables to generate variant peptides (eg. "VARIANT,CONFLICT,MUTAGEN")
+    /// INIT_MET features are used by taxons-uniprots-tables --init-met-excision instead
+    #[clap(long, value_delimiter = ',')]
+    variants: Vec<VariantKind>,
+    /// Also write an entry for every alternative isoform in the ALTERNATIVE PRODUCTS comment (eg. "P12345-2"),
+    /// by applying its VAR_SEQ features to the canonical sequence
+    #[clap(long, default_value_t = false)]
+    isoforms: bool,
+    #[command(flatten)]
+    filter: FilterArgs,
 }
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use unipept_database::dat_parser::sink::{open_sink, OutputFormat};
+use unipept_database::dat_parser::utils::{warn_contradicting_status, OutputOptions};
+use unipept_database::fasta_parser::parser::FastaParser;
+
+use unipept_database::utils::files::open_input;
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    let options = OutputOptions {
+        db_type_override: args.db_type.clone(),
+        ..OutputOptions::default()
+    };
+
+    let mut sink = open_sink(args.format, args.output.as_ref(), &options)?;
+    let mut contradicting: u64 = 0;
+
+    // Read from stdin if no input files were given
+    let inputs: Vec<Option<&PathBuf>> = if args.inputs.is_empty() {
+        vec![None]
+    } else {
+        args.inputs.iter().map(Some).collect()
+    };
+
+    for input in inputs {
+        let reader = open_input(input, 0)?;
+
+        for entry in FastaParser::new(reader) {
+            let entry = entry.with_context(|| {
+                format!(
+                    "Error parsing FASTA record from {}",
+                    input.map_or("stdin".to_string(), |pb| format!("\"{}\"", pb.display()))
+                )
+            })?;
+
+            contradicting += entry.contradicts_db_type(options.db_type_override.as_deref()) as u64;
+            sink.write(&entry.record(&options))
+                .context("Error writing entry")?;
+        }
+    }
+
+    sink.finish().context("Error writing output")?;
+    warn_contradicting_status(contradicting, &options);
+
+    Ok(())
+}
+
+// Parse UniProt-style FASTA files into the same TSV-file as the DAT and XML parsers
+// Annotations that are not part of the FASTA headers (EC numbers, GO terms and InterPro entries) are left empty
+#[derive(Parser, Debug)]
+struct Cli {
+    /// FASTA files to parse, which can be compressed with gzip, bgzip, zstd or lz4. Reads from stdin if omitted
+    inputs: Vec<PathBuf>,
+    /// Format to write the entries in: tsv, jsonl, arrow or parquet
+    #[clap(long, default_value_t = OutputFormat::Tsv)]
+    format: OutputFormat,
+    /// File to write the entries to, instead of stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Override the "Status" column, which is "swissprot" for "sp" headers and "trembl" otherwise
+    #[clap(short = 't', long)]
+    db_type: Option<String>,
+}
@@ -85,7 +85,7 @@ fn main() -> Result<()> {
 
         done += 1;
 
-        if done % 1000000 == 0 {
+        if done.is_multiple_of(1000000) {
             println!("FA {} rows", done);
         }
     }
@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use strum_macros::{Display, EnumString};
+use uniprot::uniprot::UniProt;
+
+use unipept_database::dat_parser::diff::{Change, ReleaseDiff, ReleaseEntry};
+use unipept_database::dat_parser::sequence_check::SequenceCheck;
+use unipept_database::dat_parser::uniprot_dat_parser;
+use unipept_database::utils::files::open_input;
+use unipept_database::utils::xml::xml_parser;
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+    let mut diff = ReleaseDiff::new(args.full_comparison);
+
+    for input in &args.old {
+        read_release(input, &args, |entry| diff.add_old(entry))
+            .with_context(|| format!("Error parsing \"{}\"", input.display()))?;
+    }
+
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(pb) => Box::new(BufWriter::new(File::create(pb).with_context(|| {
+            format!("Failed to create output file \"{}\"", pb.display())
+        })?)),
+        None => Box::new(BufWriter::new(stdout())),
+    };
+
+    for input in &args.new {
+        read_release(input, &args, |entry| match diff.compare(entry)? {
+            Some(change) => write_change(&mut writer, &change),
+            None => Ok(()),
+        })
+        .with_context(|| format!("Error parsing \"{}\"", input.display()))?;
+    }
+
+    // Entries whose compared fields changed need their old entry to tell what changed
+    if diff.needs_old() {
+        for input in &args.old {
+            read_release(input, &args, |entry| match diff.compare_old(entry)? {
+                Some(change) => write_change(&mut writer, &change),
+                None => Ok(()),
+            })
+            .with_context(|| format!("Error parsing \"{}\"", input.display()))?;
+        }
+    }
+
+    let (removed, summary) = diff.finish()?;
+    for change in &removed {
+        write_change(&mut writer, change)?;
+    }
+    writer.flush().context("Error writing changelog")?;
+
+    eprintln!("Unchanged entries: {}", summary.unchanged);
+    eprintln!("Added entries: {}", summary.added);
+    eprintln!("Removed entries: {}", summary.removed);
+    eprintln!("Modified entries: {}", summary.modified);
+    eprintln!("  with a new sequence: {}", summary.sequence);
+    eprintln!("  with a new taxon: {}", summary.taxon);
+    eprintln!("  with new GO annotations: {}", summary.go);
+    eprintln!("  with new EC numbers: {}", summary.ec);
+    eprintln!("  with new InterPro entries: {}", summary.interpro);
+
+    Ok(())
+}
+
+/// Call `f` for every entry of a (possibly compressed) release file
+fn read_release(
+    input: &PathBuf,
+    args: &Cli,
+    mut f: impl FnMut(&dyn ReleaseEntry) -> Result<()>,
+) -> Result<()> {
+    let reader = open_input(Some(input), args.threads)?;
+
+    match args.format {
+        ReleaseFormat::Dat => {
+            for entry in uniprot_dat_parser(reader, args.threads, false, args.sequence_check) {
+                f(&entry.context("Error parsing DAT entry")?)?;
+            }
+        }
+        ReleaseFormat::Xml => {
+            for entry in xml_parser::<UniProt, _>(reader, args.threads)? {
+                f(&entry.context("Error parsing XML entry")?)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_change(writer: &mut impl Write, change: &Change) -> Result<()> {
+    serde_json::to_writer(&mut *writer, change).context("Error serializing change")?;
+    writeln!(writer).context("Error writing changelog")
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+enum ReleaseFormat {
+    #[strum(serialize = "dat")]
+    Dat,
+    #[strum(serialize = "xml")]
+    Xml,
+}
+
+// Compare two UniProtKB releases by accession number, and write the differences as a JSON lines changelog
+// with one line per added, removed or modified entry. Only a fingerprint of every old entry is kept in memory,
+// so the old release is read a second time for the entries whose compared fields changed. These are written
+// after the other changes of the new release, and removed entries are written last.
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Files of the old release, which can be compressed with gzip, bgzip, zstd or lz4
+    #[clap(long, required = true, num_args = 1..)]
+    old: Vec<PathBuf>,
+    /// Files of the new release, which can be compressed with gzip, bgzip, zstd or lz4
+    #[clap(long, required = true, num_args = 1..)]
+    new: Vec<PathBuf>,
+    /// Format of the release files: dat or xml
+    #[clap(long, default_value_t = ReleaseFormat::Dat)]
+    format: ReleaseFormat,
+    /// File to write the changelog to, instead of stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Compare all fields of entries that have the same version in both releases,
+    /// instead of considering them unchanged
+    #[clap(long, default_value_t = false)]
+    full_comparison: bool,
+    /// Check every DAT sequence against the length and CRC64 checksum on its SQ line, like dat-parser does
+    /// "error" stops at the first mismatch, "warn" only logs them and "off" disables the check
+    #[clap(long, default_value_t = SequenceCheck::Error)]
+    sequence_check: SequenceCheck,
+    /// Amount of threads to parse and decompress the releases with, 0 uses all available cores
+    #[clap(long, default_value_t = 0)]
+    threads: usize,
+}
@@ -1,11 +1,41 @@
 use anyhow::{Context, Result};
 use clap::Parser;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
+use unipept_database::taxons_uniprots_tables::equalization::{Equalization, DEFAULT_EQUALIZATION};
+use unipept_database::taxons_uniprots_tables::models::DigestionMode;
+use unipept_database::taxons_uniprots_tables::protease::parse_protease;
 use unipept_database::taxons_uniprots_tables::tab_parser::TabParser;
 use unipept_database::taxons_uniprots_tables::table_writer::TableWriter;
+use unipept_database::taxons_uniprots_tables::utils::now_str;
 
 fn main() -> Result<()> {
     let args = Cli::parse();
+
+    let protease = parse_protease(&args.protease)?;
+    eprintln!(
+        "[{}]\tDigesting sequences with {}",
+        now_str(),
+        protease.name()
+    );
+
+    if let Some(pb) = &args.build_info {
+        write_build_info(
+            pb,
+            &[
+                ("protease", protease.name()),
+                ("peptide_min", args.peptide_min.to_string()),
+                ("peptide_max", args.peptide_max.to_string()),
+                ("digestion", args.digestion.to_string()),
+                ("missed_cleavages", args.missed_cleavages.to_string()),
+                ("init_met_excision", args.init_met_excision.to_string()),
+                ("equalization", args.equalize.name().to_string()),
+            ],
+        )
+        .with_context(|| format!("Unable to write build info to \"{}\"", pb.display()))?;
+    }
+
     let mut writer = TableWriter::new(
         &args.taxons,
         &args.peptides,
@@ -14,7 +44,13 @@ fn main() -> Result<()> {
         &args.ec,
         &args.interpro,
     )
-    .context("Unable to instantiate TableWriter")?;
+    .context("Unable to instantiate TableWriter")?
+    .with_protease(protease)
+    .with_digestion_mode(args.digestion)
+    .with_missed_cleavages(args.missed_cleavages)
+    .with_init_met_excision(args.init_met_excision)
+    .with_variant_peptides(args.variant_peptides)
+    .with_equalization(args.equalize);
 
     let parser = TabParser::new(args.peptide_min, args.peptide_max, args.verbose)
         .context("Unable to instantiate TabParser")?;
@@ -28,6 +64,17 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Write the settings the tables were built with, as one tab-separated key and value per line
+fn write_build_info(pb: &PathBuf, settings: &[(&str, String)]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(pb)?);
+    for (key, value) in settings {
+        writeln!(writer, "{key}\t{value}")?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
 #[derive(Parser, Debug)]
 pub struct Cli {
     /// Minimum peptide length
@@ -62,6 +109,43 @@ pub struct Cli {
     #[clap(long)]
     interpro: PathBuf,
 
+    /// Protease to digest the sequences with: trypsin, lys-c, arg-c, glu-c, asp-n or chymotrypsin,
+    /// or a custom cleavage rule in Expasy PeptideCutter notation (eg. "[FWY]|{P},[L]|{P}")
+    #[clap(long, default_value = "trypsin")]
+    protease: String,
+
+    /// Which ends of the peptides have to be at a cleavage site: specific (both), semi-specific (at least one)
+    /// or non-specific (neither, every subsequence within the length bounds is a peptide)
+    #[clap(long, default_value_t = DigestionMode::Specific)]
+    digestion: DigestionMode,
+
+    /// Also write peptides with up to this many missed cleavages, which are concatenations of adjacent cleavage products
+    /// If this is not 0, the amount of missed cleavages is added as an extra column to the peptides file,
+    /// before the column of --variant-peptides
+    #[clap(long, default_value_t = 0)]
+    missed_cleavages: usize,
+
+    /// Also write the N-terminal peptides without the initiator methionine, for entries that remove it
+    /// according to their INIT_MET variant (see dat-parser --variants), or else when the next residue is small (A, C, G, P, S, T or V)
+    /// The prediction is not used for reviewed entries if the input has a "Sequence variants" column, which should then include INIT_MET
+    /// These peptides are marked with a 1 in an extra column of the peptides file, after the column of --missed-cleavages
+    #[clap(long, default_value_t = false)]
+    init_met_excision: bool,
+
+    /// Amino acid equivalence classes to equalize the peptides with, every residue of a class is replaced
+    /// by the first residue of that class (eg. "LI,KQ,ND,QE"). B, Z and J join the class of the residues they stand for
+    #[clap(long, default_value = DEFAULT_EQUALIZATION)]
+    equalize: Equalization,
+
+    /// File to record the settings of this build in, such as the protease
+    #[clap(long)]
+    build_info: Option<PathBuf>,
+
+    /// Also write the peptides that carry one of the variants in the "Sequence variants" column of the input,
+    /// tagged with that variant in an extra column of the peptides file
+    #[clap(long, default_value_t = false)]
+    variant_peptides: bool,
+
     /// Enable verbose mode
     #[clap(short, long, default_value_t = false)]
     verbose: bool,
@@ -1,178 +1,147 @@
-use std::io::{BufReader, Stdin};
-use std::num::NonZeroUsize;
+use std::path::PathBuf;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use smartstring::{LazyCompact, SmartString};
-use uniprot::uniprot::{SequentialParser, ThreadedParser};
+use uniprot::uniprot::UniProt;
 
-use unipept_database::utils::files::open_sin;
+use unipept_database::dat_parser::entry::UniProtDATEntry;
+use unipept_database::dat_parser::filter::{EntryFilter, FilterArgs};
+use unipept_database::dat_parser::sink::{open_sink, EntrySink, OutputFormat};
+use unipept_database::dat_parser::utils::{
+    unique_xrefs, warn_contradicting_status, GoEvidenceFilter, OutputOptions, DEFAULT_XREFS,
+};
+use unipept_database::utils::files::open_input;
+use unipept_database::utils::xml::xml_parser;
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
-    let reader = open_sin();
-
-    write_header();
-
-    // Create a different parser based on the amount of threads requested
-    match args.threads {
-        1 => {
-            for r in SequentialParser::new(reader) {
-                let entry = r.context("Error reading UniProt entry from SequentialParser")?;
-                write_entry(&entry, &args.uniprot_type, args.verbose);
-            }
-        }
-        n => {
-            let parser: ThreadedParser<BufReader<Stdin>> = if n == 0 {
-                ThreadedParser::new(reader)
-            } else {
-                ThreadedParser::with_threads(
-                    reader,
-                    NonZeroUsize::new(n as usize)
-                        .context("Error parsing number of threads as usize")?,
-                )
-            };
-
-            for r in parser {
-                let entry = r.context("Error reading UniProt entry from ThreadedParser")?;
-                write_entry(&entry, &args.uniprot_type, args.verbose);
-            }
+    let options = args.output_options();
+    let filter = args.filter.clone().into_filter()?;
+    let mut sink = open_sink(args.format, args.output.as_ref(), &options)?;
+    let mut contradicting: u64 = 0;
+
+    // Read from stdin if no input files were given
+    if args.inputs.is_empty() {
+        parse_input(
+            None,
+            &args,
+            &options,
+            &filter,
+            &mut sink,
+            &mut contradicting,
+        )?;
+    } else {
+        for input in &args.inputs {
+            parse_input(
+                Some(input),
+                &args,
+                &options,
+                &filter,
+                &mut sink,
+                &mut contradicting,
+            )
+            .with_context(|| format!("Error parsing \"{}\"", input.display()))?;
         }
     }
 
+    sink.finish().context("Error writing output")?;
+    warn_contradicting_status(contradicting, &options);
+
     Ok(())
 }
 
-type SmartStr = SmartString<LazyCompact>;
+/// Parse a single (possibly compressed) input file, or stdin if no file is given
+fn parse_input(
+    input: Option<&PathBuf>,
+    args: &Cli,
+    options: &OutputOptions,
+    filter: &EntryFilter,
+    sink: &mut Box<dyn EntrySink>,
+    contradicting: &mut u64,
+) -> Result<()> {
+    let reader = open_input(input, args.threads as usize)?;
+
+    for r in xml_parser::<UniProt, _>(reader, args.threads as usize)? {
+        let entry = r.context("Error reading UniProt entry from XML parser")?;
+        write_entry(&entry, args, options, filter, sink, contradicting)
+            .context("Error writing entry")?;
+    }
 
-// Parse a Uniprot XML file and convert it into a TSV-file
+    Ok(())
+}
+
+// Parse a Uniprot XML file and convert it into a TSV-file (or one of the other output formats)
 #[derive(Parser, Debug)]
 struct Cli {
-    #[clap(short = 't', long, default_value = "swissprot")]
-    uniprot_type: String,
+    /// XML files to parse, which can be compressed with gzip, bgzip, zstd or lz4. Reads from stdin if omitted
+    inputs: Vec<PathBuf>,
+    /// Format to write the entries in: tsv, jsonl, arrow or parquet
+    #[clap(long, default_value_t = OutputFormat::Tsv)]
+    format: OutputFormat,
+    /// File to write the entries to, instead of stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Override the "Status" column, which is derived from the dataset of every entry by default
+    #[clap(short = 't', long)]
+    uniprot_type: Option<String>,
     #[clap(long, default_value_t = 0)]
     threads: u32,
     #[clap(short, long, default_value_t = false)]
     verbose: bool,
+    /// Databases to extract cross-references for
+    /// GO and InterPro fill their default columns, other databases are written to extra columns at the end
+    #[clap(long, value_delimiter = ',', default_values_t = DEFAULT_XREFS.map(String::from))]
+    xref: Vec<String>,
+    /// Only keep GO annotations with one of these evidence codes (eg. "EXP,IDA,IMP")
+    #[clap(long, value_delimiter = ',')]
+    go_evidence_include: Vec<String>,
+    /// Drop GO annotations with one of these evidence codes (eg. "IEA")
+    #[clap(long, value_delimiter = ',')]
+    go_evidence_exclude: Vec<String>,
+    /// Write the evidence codes of the GO annotations to an extra "Gene ontology evidence" column
+    #[clap(long, default_value_t = false)]
+    go_evidence_column: bool,
+    #[command(flatten)]
+    filter: FilterArgs,
 }
 
-/// Write the header line to stdout
-fn write_header() {
-    let fields: [&str; 9] = [
-        "Entry",
-        "Sequence",
-        "Protein names",
-        "Version (entry)",
-        "EC number",
-        "Gene ontology IDs",
-        "Cross-reference (InterPro)",
-        "Status",
-        "Organism ID",
-    ];
-
-    let result_string = fields.join("\t");
-    println!("{}", result_string);
-}
-
-/// Resolve the name of a single entry
-fn parse_name(entry: &uniprot::uniprot::Entry) -> SmartStr {
-    let mut submitted_name: SmartStr = SmartStr::new();
-
-    // Check the last "recommended" name from a protein's components,
-    // otherwise store the last "submitted" name of these components for later
-    for component in entry.protein.components.iter().rev() {
-        if let Some(n) = &component.recommended {
-            return n.full.clone();
-        }
-
-        if submitted_name.is_empty() {
-            if let Some(n) = component.submitted.last() {
-                submitted_name = n.full.clone();
-            }
+impl Cli {
+    fn output_options(&self) -> OutputOptions {
+        OutputOptions {
+            db_type_override: self.uniprot_type.clone(),
+            xrefs: unique_xrefs(&self.xref),
+            go_evidence: GoEvidenceFilter::new(
+                self.go_evidence_include.clone(),
+                self.go_evidence_exclude.clone(),
+            ),
+            go_evidence_column: self.go_evidence_column,
+            ..OutputOptions::default()
         }
     }
-
-    // Do the same thing for the domains
-    for domain in entry.protein.domains.iter().rev() {
-        if let Some(n) = &domain.recommended {
-            return n.full.clone();
-        }
-
-        if submitted_name.is_empty() {
-            if let Some(n) = domain.submitted.last() {
-                submitted_name = n.full.clone();
-            }
-        }
-    }
-
-    // First check the protein's own recommended name,
-    // otherwise return the submitted name from above if there was one,
-    // otherwise the last submitted name from the protein itself
-    if let Some(n) = &entry.protein.name.recommended {
-        n.full.clone()
-    } else if !submitted_name.is_empty() {
-        submitted_name
-    } else if let Some(n) = entry.protein.name.submitted.last() {
-        n.full.clone()
-    } else {
-        eprintln!("Could not find a name for entry {}", entry.accessions[0]);
-        SmartStr::new()
-    }
 }
 
-/// Write a single UniProt entry to stdout
-fn write_entry(entry: &uniprot::uniprot::Entry, db_type: &str, verbose: bool) {
-    let accession_number: SmartStr = entry.accessions[0].clone();
-    let sequence: SmartStr = entry.sequence.value.clone();
-
-    let name: SmartStr = parse_name(entry);
-
-    let version: SmartStr = SmartStr::from(entry.version.to_string());
-
-    let mut ec_references: Vec<&str> = Vec::new();
-    let mut go_references: Vec<&str> = Vec::new();
-    let mut ip_references: Vec<&str> = Vec::new();
-    let mut taxon_id: SmartStr = SmartStr::new();
-
-    // Find the taxon id in the organism
-    for reference in &entry.organism.db_references {
-        if reference.ty == "NCBI Taxonomy" {
-            taxon_id = reference.id.clone();
-        }
+/// Convert a single UniProt entry into the same entry type as the DAT parser,
+/// and write it to the sink if it passes the filter
+fn write_entry(
+    entry: &uniprot::uniprot::Entry,
+    args: &Cli,
+    options: &OutputOptions,
+    filter: &EntryFilter,
+    sink: &mut Box<dyn EntrySink>,
+    contradicting: &mut u64,
+) -> Result<()> {
+    let entry = UniProtDATEntry::from_xml(entry)?;
+    if !filter.matches(&entry) {
+        return Ok(());
     }
 
-    // Find the EC, GO and InterPro references in the entry itself
-    for reference in &entry.db_references {
-        let vector: Option<&mut Vec<&str>> = match reference.ty.as_str() {
-            "EC" => Some(&mut ec_references),
-            "GO" => Some(&mut go_references),
-            "InterPro" => Some(&mut ip_references),
-            _ => None,
-        };
-
-        if let Some(v) = vector {
-            v.push(&reference.id);
-        }
-    }
+    *contradicting += entry.contradicts_db_type(options.db_type_override.as_deref()) as u64;
+    let record = entry.record(options);
 
-    let fields: [SmartStr; 9] = [
-        accession_number,
-        sequence,
-        name,
-        version,
-        SmartStr::from(ec_references.join(";")),
-        SmartStr::from(go_references.join(";")),
-        SmartStr::from(ip_references.join(";")),
-        SmartStr::from(db_type),
-        taxon_id,
-    ];
-
-    let line = fields.join("\t");
-
-    if verbose {
-        eprintln!("INFO VERBOSE: Writing tabular line: {}", line);
+    if args.verbose {
+        eprintln!("INFO VERBOSE: Writing entry: {:?}", record);
     }
 
-    println!("{}", line);
+    sink.write(&record)
 }
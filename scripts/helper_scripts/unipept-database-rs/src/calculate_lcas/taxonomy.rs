@@ -90,12 +90,13 @@ impl Taxonomy {
         Ok(())
     }
 
-    fn calculate_lca(&self, taxa: &[i32]) -> i32 {
+    /// The lowest common ancestor of a set of taxa, taxa that are not in the lineages file are ignored
+    pub fn calculate_lca(&self, taxa: &[i32]) -> i32 {
         let mut lca = 1;
 
         let lineages: Vec<&Vec<i32>> = taxa
             .iter()
-            .map(|x| &self.taxonomy[*x as usize])
+            .filter_map(|&x| self.taxonomy.get(usize::try_from(x).ok()?))
             .filter(|x| !x.is_empty())
             .collect();
 
@@ -0,0 +1,109 @@
+use std::io::BufRead;
+
+use anyhow::{Context, Result};
+use strum_macros::{Display, EnumString};
+use uniprot::parser::UniprotDatabase;
+
+use crate::calculate_lcas::taxonomy::Taxonomy;
+use crate::dat_parser::sink::{EntryRecord, EntrySink};
+use crate::dat_parser::utils::{extra_xrefs, OutputOptions};
+use crate::utils::xml::xml_parser;
+
+pub mod uniparc;
+pub mod uniref;
+
+/// Status that is written when it is not overridden, as clusters are not reviewed
+pub const DEFAULT_STATUS: &str = "trembl";
+
+/// Which rows to write for every cluster
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Display, EnumString)]
+pub enum ClusterRows {
+    /// One row for the representative, with the common taxon of the cluster
+    /// This is the lowest common ancestor of the members, which needs the lineages for UniParc entries
+    #[default]
+    #[strum(serialize = "representative")]
+    Representative,
+    /// One row for every member, with the taxon of that member
+    /// UniRef members have no sequence of their own, so this is only supported for UniRef100
+    #[strum(serialize = "members")]
+    Members,
+}
+
+/// Function that creates the rows for a single entry, see `uniref::records` and `uniparc::records`
+pub type RecordsFn<E> = for<'a> fn(
+    &'a E,
+    ClusterRows,
+    Option<&Taxonomy>,
+    &'a OutputOptions,
+) -> Result<Vec<EntryRecord<'a>>>;
+
+/// Parse all entries of a UniRef or UniParc XML file, and write their rows to `sink`
+/// `taxonomy` is used to calculate the common taxon of clusters that don't list it themselves
+pub fn write_records<D: UniprotDatabase + 'static, B: BufRead + Send + 'static>(
+    reader: B,
+    threads: usize,
+    rows: ClusterRows,
+    taxonomy: Option<&Taxonomy>,
+    options: &OutputOptions,
+    sink: &mut Box<dyn EntrySink>,
+    records: RecordsFn<D::Entry>,
+) -> Result<()> {
+    for entry in xml_parser::<D, B>(reader, threads)? {
+        let entry = entry.context("Error reading entry from XML parser")?;
+
+        for record in records(&entry, rows, taxonomy, options)? {
+            sink.write(&record).context("Error writing entry")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create a row for the TSV contract of the DAT parser, without EC numbers and GO terms
+fn cluster_record<'a>(
+    accession: &'a str,
+    sequence: &'a str,
+    name: &'a str,
+    version: u32,
+    taxon_id: u32,
+    interpro_ids: Vec<&'a str>,
+    options: &'a OutputOptions,
+) -> EntryRecord<'a> {
+    let interpro_enabled = options.xrefs.iter().any(|x| x == "InterPro");
+
+    EntryRecord {
+        accession,
+        sequence,
+        name,
+        version,
+        ec_numbers: Vec::new(),
+        go_ids: Vec::new(),
+        interpro_ids: if interpro_enabled {
+            interpro_ids
+        } else {
+            Vec::new()
+        },
+        status: options
+            .db_type_override
+            .as_deref()
+            .unwrap_or(DEFAULT_STATUS),
+        taxon_id: Some(taxon_id),
+        extra_xrefs: extra_xrefs(&options.xrefs).map(|_| Vec::new()).collect(),
+        go_evidence: options.go_evidence_column.then(Vec::new),
+        variants: (!options.variants.is_empty()).then(Vec::new),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cluster_rows() {
+        assert_eq!(
+            "members".parse::<ClusterRows>().unwrap(),
+            ClusterRows::Members
+        );
+        assert_eq!(ClusterRows::default(), ClusterRows::Representative);
+    }
+}
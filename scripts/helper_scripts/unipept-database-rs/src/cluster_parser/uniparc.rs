@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use uniprot::uniparc::{DbReference, Entry};
+
+use crate::calculate_lcas::taxonomy::Taxonomy;
+use crate::cluster_parser::{cluster_record, ClusterRows};
+use crate::dat_parser::sink::EntryRecord;
+use crate::dat_parser::utils::OutputOptions;
+
+/// UniParc entries are not versioned, only their cross-references are
+const UNIPARC_VERSION: u32 = 1;
+
+/// The rows to write for a UniParc entry, whose members are its active cross-references
+/// The representative row uses the UniParc id, members use the id in their own database
+/// UniParc entries don't list a common taxon, so the representative row needs `taxonomy` to calculate it
+pub fn records<'a>(
+    entry: &'a Entry,
+    rows: ClusterRows,
+    taxonomy: Option<&Taxonomy>,
+    options: &'a OutputOptions,
+) -> Result<Vec<EntryRecord<'a>>> {
+    let sequence = entry.sequence.sequence.as_str();
+    let interpro_ids = || -> Vec<&'a str> {
+        let mut ids: Vec<&str> = Vec::new();
+        for id in entry
+            .signature_sequence_matches
+            .iter()
+            .map(|m| m.interpro.id.as_str())
+        {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids
+    };
+
+    let members = entry
+        .db_references
+        .iter()
+        .filter(|r| r.active == "Y")
+        .filter_map(|r| Some((r, taxon_id(r)?)));
+
+    match rows {
+        ClusterRows::Representative => {
+            let taxonomy = taxonomy.with_context(|| {
+                format!(
+                    "The lineages are needed to calculate the common taxon of UniParc entry {}",
+                    entry.accession
+                )
+            })?;
+            let taxa: Vec<i32> = members.filter_map(|(_, t)| i32::try_from(t).ok()).collect();
+
+            // Prefer the name of a UniProtKB entry, as the other databases often only have generic names
+            let name = entry
+                .db_references
+                .iter()
+                .filter(|r| r.active == "Y")
+                .filter(|r| r.ty.starts_with("UniProtKB"))
+                .chain(entry.db_references.iter())
+                .find_map(|r| property(r, "protein_name"))
+                .unwrap_or_default();
+
+            Ok(vec![cluster_record(
+                &entry.accession,
+                sequence,
+                name,
+                UNIPARC_VERSION,
+                taxonomy.calculate_lca(&taxa) as u32,
+                interpro_ids(),
+                options,
+            )])
+        }
+        ClusterRows::Members => Ok(members
+            .map(|(reference, taxon_id)| {
+                cluster_record(
+                    &reference.id,
+                    sequence,
+                    property(reference, "protein_name").unwrap_or_default(),
+                    reference.version.map_or(UNIPARC_VERSION, |v| v as u32),
+                    taxon_id,
+                    interpro_ids(),
+                    options,
+                )
+            })
+            .collect()),
+    }
+}
+
+fn property<'a>(reference: &'a DbReference, ty: &str) -> Option<&'a str> {
+    reference
+        .properties
+        .iter()
+        .find(|p| p.ty == ty)
+        .map(|p| p.value.as_str())
+}
+
+fn taxon_id(reference: &DbReference) -> Option<u32> {
+    property(reference, "NCBI_taxonomy_id")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use uniprot::uniparc::UniParc;
+
+    use crate::utils::xml::xml_parser;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<uniparc xmlns="http://uniprot.org/uniparc">
+<entry dataset="uniparc">
+<accession>UPI0000000001</accession>
+<dbReference type="EMBL" id="AAA00001" version_i="1" active="Y" version="2" created="2000-05-01" last="2020-01-01">
+<property type="NCBI_taxonomy_id" value="9606"/>
+<property type="protein_name" value="hypothetical protein"/>
+</dbReference>
+<dbReference type="UniProtKB/TrEMBL" id="Q9XYZ1" version_i="1" active="Y" version="1" created="2000-05-01" last="2020-01-01">
+<property type="NCBI_taxonomy_id" value="9606"/>
+<property type="protein_name" value="Kinase"/>
+</dbReference>
+<dbReference type="UniProtKB/TrEMBL" id="Q9XYZ2" version_i="1" active="N" created="2000-05-01" last="2010-01-01">
+<property type="NCBI_taxonomy_id" value="10090"/>
+</dbReference>
+<signatureSequenceMatch database="Pfam" id="PF00069">
+<ipr name="Protein kinase domain" id="IPR000719"/>
+<lcn start="1" end="10"/>
+</signatureSequenceMatch>
+<signatureSequenceMatch database="SMART" id="SM00220">
+<ipr name="Protein kinase domain" id="IPR000719"/>
+<lcn start="1" end="10"/>
+</signatureSequenceMatch>
+<sequence length="10" checksum="0000000000000000">MKLVAAGEKK</sequence>
+</entry>
+</uniparc>
+"#;
+
+    fn parse(xml: &str) -> Vec<Entry> {
+        xml_parser::<UniParc, _>(Cursor::new(xml.to_string()), 1)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    /// Human and mouse, which share the class Mammalia (40674)
+    fn taxonomy() -> Taxonomy {
+        let lineage = |species: &str| {
+            let mut ranks = vec!["\\N"; 27];
+            ranks[0] = "2759";
+            ranks[8] = "40674";
+            ranks[22] = species;
+            format!("{species}\t{}\n", ranks.join("\t"))
+        };
+
+        let path = std::env::temp_dir().join("unipept_test_uniparc_lineages.tsv");
+        std::fs::write(&path, format!("{}{}", lineage("9606"), lineage("10090"))).unwrap();
+        let taxonomy = Taxonomy::build(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        taxonomy
+    }
+
+    #[test]
+    fn test_representative_records() {
+        let entries = parse(XML);
+        let options = OutputOptions::default();
+        let taxonomy = taxonomy();
+        let rows = records(
+            &entries[0],
+            ClusterRows::Representative,
+            Some(&taxonomy),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].accession, "UPI0000000001");
+        assert_eq!(rows[0].sequence, "MKLVAAGEKK");
+        assert_eq!(rows[0].name, "Kinase");
+        assert_eq!(rows[0].taxon_id, Some(9606));
+        assert_eq!(rows[0].interpro_ids, vec!["IPR000719"]);
+
+        // The representative needs the lineages to calculate the common taxon
+        assert!(records(&entries[0], ClusterRows::Representative, None, &options).is_err());
+    }
+
+    #[test]
+    fn test_representative_lca() {
+        let xml = XML.replace(
+            "9606\"/>\n<property type=\"protein_name\" value=\"Kinase\"",
+            "10090\"/>\n<property type=\"protein_name\" value=\"Kinase\"",
+        );
+        let entries = parse(&xml);
+        let options = OutputOptions::default();
+        let taxonomy = taxonomy();
+        let rows = records(
+            &entries[0],
+            ClusterRows::Representative,
+            Some(&taxonomy),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(rows[0].taxon_id, Some(40674));
+    }
+
+    #[test]
+    fn test_member_records() {
+        let entries = parse(XML);
+        let options = OutputOptions::default();
+        let records = records(&entries[0], ClusterRows::Members, None, &options).unwrap();
+
+        let got: Vec<(&str, &str, u32, Option<u32>)> = records
+            .iter()
+            .map(|r| (r.accession, r.name, r.version, r.taxon_id))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("AAA00001", "hypothetical protein", 2, Some(9606)),
+                ("Q9XYZ1", "Kinase", 1, Some(9606)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::{Context, Error, Result};
+use uniprot::uniref::{Entry, Member};
+
+use crate::calculate_lcas::taxonomy::Taxonomy;
+use crate::cluster_parser::{cluster_record, ClusterRows};
+use crate::dat_parser::sink::EntryRecord;
+use crate::dat_parser::utils::OutputOptions;
+
+/// UniRef clusters are not versioned
+const UNIREF_VERSION: u32 = 1;
+
+/// The rows to write for a UniRef cluster
+/// Only the representative has a sequence in the XML files, so member rows use the representative sequence.
+/// This is only correct for UniRef100, the members of UniRef90 and UniRef50 clusters have different sequences.
+pub fn records<'a>(
+    entry: &'a Entry,
+    rows: ClusterRows,
+    _taxonomy: Option<&Taxonomy>,
+    options: &'a OutputOptions,
+) -> Result<Vec<EntryRecord<'a>>> {
+    let representative = &entry.representative_member;
+    let sequence = representative
+        .sequence
+        .as_ref()
+        .map(|s| s.sequence.as_str())
+        .with_context(|| format!("Missing representative sequence in cluster {}", entry.id))?;
+
+    match rows {
+        ClusterRows::Representative => {
+            let taxon_id = property(&entry.properties, "common taxon ID")
+                .with_context(|| format!("Missing common taxon in cluster {}", entry.id))?;
+            let taxon_id = parse_taxon_id(taxon_id)?;
+
+            // The cluster name is the name of its representative, prefixed with "Cluster: "
+            let name = entry.name.strip_prefix("Cluster: ").unwrap_or(&entry.name);
+
+            Ok(vec![cluster_record(
+                accession(representative),
+                sequence,
+                name,
+                UNIREF_VERSION,
+                taxon_id,
+                Vec::new(),
+                options,
+            )])
+        }
+        ClusterRows::Members if !entry.id.starts_with("UniRef100_") => Err(Error::msg(format!(
+            "Member rows are only supported for UniRef100, the members of cluster {} don't share its sequence",
+            entry.id
+        ))),
+        ClusterRows::Members => std::iter::once(representative)
+            .chain(entry.members.iter())
+            .map(|member| {
+                let properties = &member.db_reference.properties;
+                let taxon_id = property(properties, "NCBI taxonomy").with_context(|| {
+                    format!(
+                        "Missing taxon of member {} in cluster {}",
+                        member.db_reference.id, entry.id
+                    )
+                })?;
+
+                Ok(cluster_record(
+                    accession(member),
+                    sequence,
+                    property(properties, "protein name").unwrap_or_default(),
+                    UNIREF_VERSION,
+                    parse_taxon_id(taxon_id)?,
+                    Vec::new(),
+                    options,
+                ))
+            })
+            .collect(),
+    }
+}
+
+/// The UniProtKB accession of a member, or its UniParc id if it is not part of UniProtKB
+fn accession(member: &Member) -> &str {
+    property(&member.db_reference.properties, "UniProtKB accession")
+        .unwrap_or(&member.db_reference.id)
+}
+
+fn property<'a>(properties: &'a [uniprot::uniref::Property], ty: &str) -> Option<&'a str> {
+    properties
+        .iter()
+        .find(|p| p.ty == ty)
+        .map(|p| p.value.as_str())
+}
+
+fn parse_taxon_id(taxon_id: &str) -> Result<u32> {
+    taxon_id
+        .parse()
+        .with_context(|| format!("Unable to parse taxon id \"{taxon_id}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use uniprot::uniref::UniRef;
+
+    use crate::utils::xml::xml_parser;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="ISO-8859-1" ?>
+<UniRef50 xmlns="http://uniprot.org/uniref" releaseDate="2024-01-24" version="2024_01">
+<entry id="UniRef50_Q6GZX4" updated="2019-12-11">
+<name>Cluster: Putative transcription factor 001R</name>
+<property type="member count" value="3"/>
+<property type="common taxon" value="Ranavirus"/>
+<property type="common taxon ID" value="10492"/>
+<representativeMember>
+<dbReference type="UniProtKB ID" id="001R_FRG3G">
+<property type="UniProtKB accession" value="Q6GZX4"/>
+<property type="protein name" value="Putative transcription factor 001R"/>
+<property type="NCBI taxonomy" value="654924"/>
+</dbReference>
+<sequence length="5" checksum="0000000000000000">MAFSA</sequence>
+</representativeMember>
+<member>
+<dbReference type="UniProtKB ID" id="001R_FRG3H">
+<property type="UniProtKB accession" value="Q6GZX5"/>
+<property type="protein name" value="Transcription factor"/>
+<property type="NCBI taxonomy" value="10493"/>
+</dbReference>
+</member>
+<member>
+<dbReference type="UniParc ID" id="UPI0000000001">
+<property type="NCBI taxonomy" value="10494"/>
+</dbReference>
+</member>
+</entry>
+</UniRef50>
+"#;
+
+    fn parse(xml: &str) -> Vec<Entry> {
+        xml_parser::<UniRef, _>(Cursor::new(xml.to_string()), 1)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_representative_records() {
+        let entries = parse(XML);
+        let options = OutputOptions::default();
+        let records = records(&entries[0], ClusterRows::Representative, None, &options).unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].accession, "Q6GZX4");
+        assert_eq!(records[0].sequence, "MAFSA");
+        assert_eq!(records[0].name, "Putative transcription factor 001R");
+        assert_eq!(records[0].taxon_id, Some(10492));
+        assert_eq!(records[0].status, "trembl");
+    }
+
+    #[test]
+    fn test_member_records() {
+        let options = OutputOptions::default();
+
+        // Members of UniRef50 clusters have different sequences
+        let entries = parse(XML);
+        assert!(records(&entries[0], ClusterRows::Members, None, &options).is_err());
+
+        let entries = parse(&XML.replace("UniRef50", "UniRef100"));
+        let records = records(&entries[0], ClusterRows::Members, None, &options).unwrap();
+
+        let got: Vec<(&str, &str, Option<u32>)> = records
+            .iter()
+            .map(|r| (r.accession, r.name, r.taxon_id))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("Q6GZX4", "Putative transcription factor 001R", Some(654924)),
+                ("Q6GZX5", "Transcription factor", Some(10493)),
+                ("UPI0000000001", "", Some(10494)),
+            ]
+        );
+        assert!(records.iter().all(|r| r.sequence == "MAFSA"));
+    }
+}
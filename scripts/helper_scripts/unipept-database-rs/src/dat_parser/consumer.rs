@@ -1,11 +1,17 @@
 use crate::dat_parser::entry::UniProtDATEntry;
-use anyhow::{Context, Result};
+use crate::dat_parser::error::InvalidEntryError;
+use crate::dat_parser::producer::RawBatch;
+use crate::dat_parser::sequence_check::SequenceCheck;
+use anyhow::{Error, Result};
 use crossbeam_channel::{Receiver, Sender};
 use std::thread;
 use std::thread::JoinHandle;
 
-/// A Consumer runs in a thread and constantly listens to a Receiver channel for raw data,
-/// publishing parsed `UniProtDatEntry`s to a Sender channel
+/// Parsed entries of a `RawBatch`, along with the index of that batch
+pub type ParsedBatch = (usize, Vec<Result<UniProtDATEntry>>);
+
+/// A Consumer runs in a thread and constantly listens to a Receiver channel for batches of raw data,
+/// publishing batches of parsed `UniProtDatEntry`s to a Sender channel
 pub struct Consumer {
     handle: Option<JoinHandle<()>>,
 }
@@ -15,30 +21,50 @@ impl Consumer {
         Self { handle: None }
     }
 
-    pub fn start(&mut self, receiver: Receiver<Vec<u8>>, sender: Sender<Result<UniProtDATEntry>>) {
+    /// Parsed entries are sent along with the index of the batch they were parsed from
+    pub fn start(
+        &mut self,
+        receiver: Receiver<RawBatch>,
+        sender: Sender<ParsedBatch>,
+        check: SequenceCheck,
+    ) {
         self.handle = Some(thread::spawn(move || {
-            for data in receiver {
-                // Cut out the \n// at the end
-                let data_slice = &data[..data.len() - 3];
-                let mut lines: Vec<String> = String::from_utf8_lossy(data_slice)
-                    .split('\n')
-                    .map(|x| x.to_string())
+            for batch in receiver {
+                let entries = batch
+                    .entries
+                    .into_iter()
+                    .map(|raw| {
+                        let data = &batch.data[raw.range];
+                        if raw.terminated {
+                            UniProtDATEntry::from_bytes_at(data, raw.offset, check)
+                        } else {
+                            Err(Error::new(InvalidEntryError::new(
+                                raw.offset,
+                                data.to_vec(),
+                                Error::msg(
+                                    "Reached the end of the input before the \"//\" terminator",
+                                ),
+                            )))
+                        }
+                    })
                     .collect();
 
-                let entry =
-                    UniProtDATEntry::from_lines(&mut lines).context("Error parsing DAT entry");
-                sender
-                    .send(entry)
-                    .context("Error sending parsed DAT entry to receiver channel")
-                    .unwrap();
+                // The receiver only disconnects when the parser was dropped,
+                // in which case there is no use in parsing any further
+                if sender.send((batch.index, entries)).is_err() {
+                    break;
+                }
             }
         }));
     }
 
-    pub fn join(&mut self) {
+    /// Wait for the consumer to finish
+    pub fn join(&mut self) -> Result<()> {
         if let Some(h) = self.handle.take() {
-            h.join().unwrap();
-            self.handle = None;
+            h.join()
+                .map_err(|_| Error::msg("Consumer thread panicked"))?;
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,465 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String as Accession;
+
+use crate::dat_parser::entry::UniProtDATEntry;
+use crate::dat_parser::sequence_check::crc64;
+
+/// Entries that can be compared between two releases, from either the DAT or the XML files
+pub trait ReleaseEntry {
+    fn accession(&self) -> &str;
+    fn version(&self) -> u32;
+    fn snapshot(&self) -> Result<EntrySnapshot>;
+}
+
+/// The fields of an entry that are compared between releases
+/// Sequences are only stored as a checksum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySnapshot {
+    pub version: u32,
+    pub sequence_crc64: String,
+    pub taxon_id: Option<u32>,
+    pub go_ids: BTreeSet<String>,
+    pub ec_numbers: BTreeSet<String>,
+    pub interpro_ids: BTreeSet<String>,
+}
+
+impl EntrySnapshot {
+    pub fn new<'a>(
+        version: u32,
+        sequence: &str,
+        taxon_id: Option<u32>,
+        go_ids: impl IntoIterator<Item = &'a str>,
+        ec_numbers: impl IntoIterator<Item = &'a str>,
+        interpro_ids: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        EntrySnapshot {
+            version,
+            sequence_crc64: crc64(sequence),
+            taxon_id,
+            go_ids: go_ids.into_iter().map(String::from).collect(),
+            ec_numbers: ec_numbers.into_iter().map(String::from).collect(),
+            interpro_ids: interpro_ids.into_iter().map(String::from).collect(),
+        }
+    }
+
+    /// A hash of the compared fields, without the version
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.sequence_crc64.hash(&mut hasher);
+        self.taxon_id.hash(&mut hasher);
+        self.go_ids.hash(&mut hasher);
+        self.ec_numbers.hash(&mut hasher);
+        self.interpro_ids.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl ReleaseEntry for UniProtDATEntry {
+    fn accession(&self) -> &str {
+        self.accession_number()
+    }
+
+    fn version(&self) -> u32 {
+        UniProtDATEntry::version(self)
+    }
+
+    fn snapshot(&self) -> Result<EntrySnapshot> {
+        Ok(EntrySnapshot::new(
+            UniProtDATEntry::version(self),
+            self.sequence(),
+            Some(self.taxon_id()),
+            self.go_references(),
+            self.ec_numbers(),
+            self.interpro_references(),
+        ))
+    }
+}
+
+impl ReleaseEntry for uniprot::uniprot::Entry {
+    fn accession(&self) -> &str {
+        &self.accessions[0]
+    }
+
+    fn version(&self) -> u32 {
+        self.version as u32
+    }
+
+    /// Converted into the same entry type as the DAT files, so EC numbers are resolved the same way
+    fn snapshot(&self) -> Result<EntrySnapshot> {
+        UniProtDATEntry::from_xml(self)?.snapshot()
+    }
+}
+
+/// A single line of the changelog
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub accession: String,
+    #[serde(flatten)]
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added {
+        version: u32,
+    },
+    Removed {
+        version: u32,
+    },
+    /// The entry version changed, with the compared fields that changed along with it
+    /// All fields are empty if the entry changed in a way that is not compared (eg. its name)
+    Modified {
+        old_version: u32,
+        new_version: u32,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        sequence: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        taxon: Option<TaxonChange>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        go: Option<AnnotationChange>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ec: Option<AnnotationChange>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interpro: Option<AnnotationChange>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaxonChange {
+    pub old: Option<u32>,
+    pub new: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnnotationChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl AnnotationChange {
+    /// The difference between two sets of annotations, or `None` if they are the same
+    fn between(old: &BTreeSet<String>, new: &BTreeSet<String>) -> Option<Self> {
+        (old != new).then(|| AnnotationChange {
+            added: new.difference(old).cloned().collect(),
+            removed: old.difference(new).cloned().collect(),
+        })
+    }
+}
+
+/// Amount of entries per kind of change
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub unchanged: u64,
+    pub added: u64,
+    pub removed: u64,
+    pub modified: u64,
+    pub sequence: u64,
+    pub taxon: u64,
+    pub go: u64,
+    pub ec: u64,
+    pub interpro: u64,
+}
+
+/// What is kept of an entry of the old release, which is small enough to keep a complete release in memory
+#[derive(Debug, Clone, Copy)]
+struct OldEntry {
+    version: u32,
+    fingerprint: u64,
+}
+
+/// Compares the entries of a new release to those of an old release, keyed by accession number
+/// Only the version and a fingerprint of every old entry are kept in memory, the new release is streamed through `compare`.
+/// Entries whose compared fields changed are reported by a second pass over the old release with `compare_old`,
+/// since the old fields are needed to tell what changed.
+pub struct ReleaseDiff {
+    old: HashMap<Accession, OldEntry>,
+    /// New entries whose fingerprint differs from the old one, until the second pass finds their old entry
+    pending: HashMap<Accession, EntrySnapshot>,
+    full_comparison: bool,
+    summary: DiffSummary,
+}
+
+impl ReleaseDiff {
+    /// Entries with the same version in both releases are considered unchanged, unless `full_comparison` is set
+    pub fn new(full_comparison: bool) -> Self {
+        ReleaseDiff {
+            old: HashMap::new(),
+            pending: HashMap::new(),
+            full_comparison,
+            summary: DiffSummary::default(),
+        }
+    }
+
+    pub fn add_old<E: ReleaseEntry + ?Sized>(&mut self, entry: &E) -> Result<()> {
+        let snapshot = entry.snapshot()?;
+        self.old.insert(
+            entry.accession().into(),
+            OldEntry {
+                version: snapshot.version,
+                fingerprint: snapshot.fingerprint(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Compare an entry of the new release to the old release, returns `None` if it is unchanged
+    /// Changes of the compared fields are not returned here, but by `compare_old`
+    pub fn compare<E: ReleaseEntry + ?Sized>(&mut self, entry: &E) -> Result<Option<Change>> {
+        let accession = entry.accession();
+
+        let Some(old) = self.old.remove(accession) else {
+            self.summary.added += 1;
+            return Ok(Some(Change {
+                accession: accession.to_string(),
+                kind: ChangeKind::Added {
+                    version: entry.version(),
+                },
+            }));
+        };
+
+        // The entry version is increased for every change, so the other fields don't have to be compared
+        if !self.full_comparison && old.version == entry.version() {
+            self.summary.unchanged += 1;
+            return Ok(None);
+        }
+
+        let new = entry.snapshot()?;
+        if old.fingerprint != new.fingerprint() {
+            self.pending.insert(accession.into(), new);
+            return Ok(None);
+        }
+
+        if old.version == new.version {
+            self.summary.unchanged += 1;
+            return Ok(None);
+        }
+
+        // Only fields that are not compared changed
+        self.summary.modified += 1;
+        Ok(Some(Change {
+            accession: accession.to_string(),
+            kind: ChangeKind::Modified {
+                old_version: old.version,
+                new_version: new.version,
+                sequence: false,
+                taxon: None,
+                go: None,
+                ec: None,
+                interpro: None,
+            },
+        }))
+    }
+
+    /// Whether `compare_old` has to be called for the entries of the old release
+    pub fn needs_old(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Compare an entry of the old release to the new release, after all new entries were passed to `compare`
+    /// Returns the changes of the compared fields, for entries whose fingerprint changed
+    pub fn compare_old<E: ReleaseEntry + ?Sized>(&mut self, entry: &E) -> Result<Option<Change>> {
+        let Some((accession, new)) = self.pending.remove_entry(entry.accession()) else {
+            return Ok(None);
+        };
+        let old = entry.snapshot()?;
+
+        let sequence = old.sequence_crc64 != new.sequence_crc64;
+        let taxon = (old.taxon_id != new.taxon_id).then_some(TaxonChange {
+            old: old.taxon_id,
+            new: new.taxon_id,
+        });
+        let go = AnnotationChange::between(&old.go_ids, &new.go_ids);
+        let ec = AnnotationChange::between(&old.ec_numbers, &new.ec_numbers);
+        let interpro = AnnotationChange::between(&old.interpro_ids, &new.interpro_ids);
+
+        self.summary.modified += 1;
+        self.summary.sequence += sequence as u64;
+        self.summary.taxon += taxon.is_some() as u64;
+        self.summary.go += go.is_some() as u64;
+        self.summary.ec += ec.is_some() as u64;
+        self.summary.interpro += interpro.is_some() as u64;
+
+        Ok(Some(Change {
+            accession: accession.to_string(),
+            kind: ChangeKind::Modified {
+                old_version: old.version,
+                new_version: new.version,
+                sequence,
+                taxon,
+                go,
+                ec,
+                interpro,
+            },
+        }))
+    }
+
+    /// The entries of the old release that were not in the new release, sorted by accession number
+    pub fn finish(mut self) -> Result<(Vec<Change>, DiffSummary)> {
+        if let Some(accession) = self.pending.keys().next() {
+            return Err(Error::msg(format!(
+                "Entry {accession} was not found in the second pass over the old release"
+            )));
+        }
+
+        let mut removed: Vec<Change> = self
+            .old
+            .into_iter()
+            .map(|(accession, old)| Change {
+                accession: accession.to_string(),
+                kind: ChangeKind::Removed {
+                    version: old.version,
+                },
+            })
+            .collect();
+        removed.sort_unstable_by(|a, b| a.accession.cmp(&b.accession));
+
+        self.summary.removed = removed.len() as u64;
+        Ok((removed, self.summary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = "ID   TEST_HUMAN   Reviewed;   5 AA.
+AC   P12345;
+DT   28-JUN-2011, integrated into UniProtKB/Swiss-Prot.
+DT   19-JUL-2004, sequence version 1.
+DT   08-NOV-2023, entry version 3.
+DE   RecName: Full=Protein;
+DE            EC=1.1.1.1;
+OX   NCBI_TaxID=9606;
+DR   GO; GO:0005737; C:cytoplasm; IDA:UniProtKB.
+DR   InterPro; IPR000001; Kringle.
+PE   1: Evidence at protein level;
+SQ   SEQUENCE   5 AA;  1 MW;  0000000000000000 CRC64;
+     MAFSA";
+
+    fn entry(data: &str) -> UniProtDATEntry {
+        UniProtDATEntry::from_bytes(data.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_version_fast_path() {
+        let mut diff = ReleaseDiff::new(false);
+        diff.add_old(&entry(ENTRY)).unwrap();
+
+        // Changes without a new version are not detected, unless the comparison is forced
+        let changed = entry(&ENTRY.replace("9606", "10090"));
+        assert_eq!(diff.compare(&changed).unwrap(), None);
+
+        let mut diff = ReleaseDiff::new(true);
+        diff.add_old(&entry(ENTRY)).unwrap();
+        assert_eq!(diff.compare(&changed).unwrap(), None);
+        assert!(diff.needs_old());
+        assert!(diff.compare_old(&entry(ENTRY)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_release_diff() {
+        let mut diff = ReleaseDiff::new(false);
+        diff.add_old(&entry(ENTRY)).unwrap();
+        diff.add_old(&entry(&ENTRY.replace("P12345", "P54321")))
+            .unwrap();
+
+        let new = ENTRY
+            .replace("entry version 3", "entry version 4")
+            .replace("MAFSA", "MAFSE")
+            .replace("IPR000001", "IPR000002");
+        let added = ENTRY.replace("P12345", "Q00001");
+
+        // Changes of the compared fields are only found in the second pass over the old release
+        assert_eq!(diff.compare(&entry(&new)).unwrap(), None);
+        assert_eq!(
+            diff.compare(&entry(&added)).unwrap().unwrap().kind,
+            ChangeKind::Added { version: 3 }
+        );
+        assert!(diff.needs_old());
+
+        assert_eq!(
+            diff.compare_old(&entry(&ENTRY.replace("P12345", "P54321")))
+                .unwrap(),
+            None
+        );
+        let change = diff.compare_old(&entry(ENTRY)).unwrap().unwrap();
+        assert!(!diff.needs_old());
+        assert_eq!(
+            change.kind,
+            ChangeKind::Modified {
+                old_version: 3,
+                new_version: 4,
+                sequence: true,
+                taxon: None,
+                go: None,
+                ec: None,
+                interpro: Some(AnnotationChange {
+                    added: vec!["IPR000002".to_string()],
+                    removed: vec!["IPR000001".to_string()],
+                }),
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&change).unwrap(),
+            r#"{"accession":"P12345","change":"modified","old_version":3,"new_version":4,"sequence":true,"interpro":{"added":["IPR000002"],"removed":["IPR000001"]}}"#
+        );
+
+        let (removed, summary) = diff.finish().unwrap();
+        assert_eq!(
+            removed,
+            vec![Change {
+                accession: "P54321".to_string(),
+                kind: ChangeKind::Removed { version: 3 },
+            }]
+        );
+        assert_eq!(
+            summary,
+            DiffSummary {
+                added: 1,
+                removed: 1,
+                modified: 1,
+                sequence: 1,
+                interpro: 1,
+                ..DiffSummary::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_uncompared_change() {
+        let mut diff = ReleaseDiff::new(false);
+        diff.add_old(&entry(ENTRY)).unwrap();
+
+        // A new version with the same compared fields doesn't need the old entry
+        let renamed = ENTRY
+            .replace("entry version 3", "entry version 4")
+            .replace("Full=Protein", "Full=Kinase");
+        assert_eq!(
+            diff.compare(&entry(&renamed)).unwrap().unwrap().kind,
+            ChangeKind::Modified {
+                old_version: 3,
+                new_version: 4,
+                sequence: false,
+                taxon: None,
+                go: None,
+                ec: None,
+                interpro: None,
+            }
+        );
+        assert!(!diff.needs_old());
+    }
+
+    #[test]
+    fn test_parse_change() {
+        let change: Change =
+            serde_json::from_str(r#"{"accession":"P12345","change":"removed","version":2}"#)
+                .unwrap();
+        assert_eq!(change.kind, ChangeKind::Removed { version: 2 });
+    }
+}
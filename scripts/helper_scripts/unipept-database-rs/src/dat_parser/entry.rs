@@ -1,346 +1,1349 @@
-use anyhow::Context;
-use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::{Context, Error, Result};
+use chrono::NaiveDate;
+use memchr::memmem;
+use uniprot::uniprot::gene::{Gene as XmlGene, NameType as XmlGeneNameType};
+use uniprot::uniprot::organism::NameType as XmlOrganismNameType;
+use uniprot::uniprot::protein::{
+    Name as XmlProteinName, Nomenclature, ProteinExistence as XmlProteinExistence,
+};
+use uniprot::uniprot::{Dataset, DbReference as XmlDbReference, Entry as XmlEntry, FragmentType};
+
+use crate::dat_parser::error::InvalidEntryError;
+use crate::dat_parser::models::{
+    go_evidence_from_eco, DbReference, EntryDates, Feature, Gene, GoAnnotation, GoAspect, Isoform,
+    IsoformSequence, Organism, OrganismHost, ProteinDescription, ProteinExistence, ProteinName,
+    ReviewStatus, SequenceInfo,
+};
+use crate::dat_parser::sequence_check::{
+    crc64, molecular_weight, validate_sequence, SequenceCheck,
+};
+use crate::dat_parser::sink::EntryRecord;
+use crate::dat_parser::utils::{extra_xrefs, GoEvidenceFilter, OutputOptions};
+use crate::dat_parser::variants::sequence_variants;
+use crate::fasta_parser::header::FastaHeader;
 
 // Constants to aid in parsing
 const COMMON_PREFIX_LEN: usize = "ID   ".len();
-
-const ORGANISM_RECOMMENDED_NAME_PREFIX_LEN: usize = "RecName: Full=".len();
-const ORGANISM_RECOMMENDED_NAME_EC_PREFIX_LEN: usize = "EC=".len();
-const ORGANISM_TAXON_ID_PREFIX_LEN: usize = "OX   NCBI_TaxID=".len();
-const VERSION_STRING_FULL_PREFIX_LEN: usize = "DT   08-NOV-2023, entry version ".len();
-
-/// The minimal data we want from an entry out of the UniProtKB datasets
+const LINE_CODE_LEN: usize = "ID".len();
+const SEQUENCE_LINE_CODE: &str = "  ";
+const NCBI_TAXON_ID_PREFIX: &str = "NCBI_TaxID=";
+const DATE_FORMAT: &str = "%d-%b-%Y";
+const ALTERNATIVE_PRODUCTS_TOPIC: &str = "-!- ALTERNATIVE PRODUCTS:";
+
+/// A single entry out of the UniProtKB DAT files
+/// Entries from the XML and FASTA files are converted into this type as well,
+/// so the columns of the output are resolved the same way for every input format
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UniProtDATEntry {
-    accession_number: String,
-    name: String,
+    entry_name: String,
+    status: ReviewStatus,
+    length: u32,
+    accessions: Vec<String>,
+    /// Entries from FASTA files have no dates
+    dates: Option<EntryDates>,
+    sequence_version: u32,
+    description: ProteinDescription,
+    genes: Vec<Gene>,
+    organism: Organism,
+    keywords: Vec<String>,
+    protein_existence: Option<ProteinExistence>,
+    db_references: Vec<DbReference>,
+    go_annotations: Vec<GoAnnotation>,
+    features: Vec<Feature>,
+    /// The ALTERNATIVE PRODUCTS comment, which is only parsed into isoforms when they are requested
+    alternative_products: Option<String>,
+    sequence_info: SequenceInfo,
     sequence: String,
-    version: String,
-    ec_references: Vec<String>,
-    go_references: Vec<String>,
-    ip_references: Vec<String>,
-    taxon_id: String,
 }
 
 impl UniProtDATEntry {
+    /// Parse an entry out of the raw bytes of a DAT file
+    /// The data is borrowed for the duration of parsing, and only the fields that are kept are copied
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        // This only allocates if the data contains invalid UTF-8
+        let text = String::from_utf8_lossy(data);
+
+        let mut id_line = None;
+        let mut accessions = Vec::new();
+        let mut dates = None;
+        let mut description = ProteinDescription::default();
+        let mut genes = Vec::new();
+        let mut organism = Organism::default();
+        let mut taxon_id = None;
+        let mut keywords = Vec::new();
+        let mut protein_existence = None;
+        let mut db_references = Vec::new();
+        let mut features = Vec::new();
+        let mut alternative_products = None;
+        let mut sequence_info: Option<SequenceInfo> = None;
+        let mut sequence = String::new();
+
+        // Every type of line appears in one consecutive block,
+        // so we can handle them one block at a time
+        for (code, block) in Blocks::new(&text) {
+            match code {
+                "ID" => id_line = Some(parse_id_line(block).context("Error parsing ID line")?),
+                "AC" => accessions = parse_accessions(block),
+                "DT" => dates = Some(parse_dates(block).context("Error parsing DT lines")?),
+                "DE" => description = parse_description(block),
+                "GN" => genes = parse_genes(block),
+                "OS" => organism.name = join_lines(block).trim_end_matches('.').to_string(),
+                "OG" => organism.organelles = parse_list(block),
+                "OC" => organism.lineage = parse_list(block),
+                "OX" => {
+                    taxon_id =
+                        Some(parse_taxon_id(line_content(block)).context("Error parsing OX line")?)
+                }
+                "OH" => {
+                    organism.hosts = block
+                        .lines()
+                        .map(parse_organism_host)
+                        .collect::<Result<Vec<OrganismHost>>>()
+                        .context("Error parsing OH lines")?
+                }
+                "KW" => keywords = parse_list(block),
+                "PE" => {
+                    protein_existence =
+                        Some(parse_protein_existence(block).context("Error parsing PE line")?)
+                }
+                "DR" => {
+                    db_references = block
+                        .lines()
+                        .map(|l| parse_db_reference(line_content(l)))
+                        .collect::<Result<Vec<DbReference>>>()
+                        .context("Error parsing DR lines")?
+                }
+                "CC" => alternative_products = find_alternative_products(block),
+                "FT" => features = parse_features(block).context("Error parsing FT lines")?,
+                "SQ" => {
+                    sequence_info =
+                        Some(parse_sequence_info(block).context("Error parsing SQ line")?)
+                }
+                SEQUENCE_LINE_CODE => {
+                    let capacity = sequence_info.as_ref().map_or(0, |i| i.length as usize);
+                    sequence = parse_sequence(block, capacity)
+                }
+                // References, comments and the "//" terminator are not part of our model
+                _ => {}
+            }
+        }
+
+        let (entry_name, status, length) = id_line.context("Missing ID line")?;
+        if accessions.is_empty() {
+            return Err(Error::msg(format!(
+                "Missing accession number for entry {entry_name}"
+            )));
+        }
+
+        organism.taxon_id = taxon_id.context("Missing OX line")?;
+
+        let go_annotations = db_references
+            .iter()
+            .filter(|r| r.database == "GO")
+            .map(parse_go_annotation)
+            .collect::<Result<Vec<GoAnnotation>>>()
+            .context("Error parsing GO cross-references")?;
+
+        let dates = dates.context("Missing DT lines")?;
+
+        Ok(Self {
+            entry_name,
+            status,
+            length,
+            accessions,
+            sequence_version: dates.sequence_version,
+            dates: Some(dates),
+            description,
+            genes,
+            organism,
+            keywords,
+            protein_existence: Some(protein_existence.context("Missing PE line")?),
+            db_references,
+            go_annotations,
+            features,
+            alternative_products,
+            sequence_info: sequence_info.context("Missing SQ line")?,
+            sequence,
+        })
+    }
+
+    /// Parse an entry that starts at byte `offset` of the input, and validate its sequence according to `check`
+    /// Errors are wrapped in an `InvalidEntryError`, which carries the accession number, offset and raw data
+    pub fn from_bytes_at(data: &[u8], offset: u64, check: SequenceCheck) -> Result<Self> {
+        Self::from_bytes(data)
+            .and_then(|entry| check.apply(entry))
+            .map_err(|e| Error::new(InvalidEntryError::new(offset, data.to_vec(), e)))
+    }
+
     /// Parse an entry out of the lines of a DAT file
-    pub fn from_lines(data: &mut Vec<String>) -> anyhow::Result<Self> {
-        let mut current_index: usize = 0;
+    /// Prefer `from_bytes`, which avoids allocating every line separately
+    pub fn from_lines(data: &[String]) -> Result<Self> {
+        Self::from_bytes(data.join("\n").as_bytes())
+    }
 
-        let accession_number = parse_ac_number(data).context("Error parsing accession number")?;
-        let version = parse_version(data, &mut current_index);
-        let (name, ec_references) = parse_name_and_ec(data, &mut current_index);
-        let taxon_id = parse_taxon_id(data, &mut current_index);
-        let (go_references, ip_references) = parse_db_references(data, &mut current_index);
-        let sequence = parse_sequence(data, current_index);
+    /// The mnemonic name of this entry, eg. "001R_FRG3G"
+    pub fn entry_name(&self) -> &str {
+        &self.entry_name
+    }
+
+    pub fn status(&self) -> ReviewStatus {
+        self.status
+    }
+
+    /// The length of the sequence, as declared on the ID line
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// The primary accession number
+    pub fn accession_number(&self) -> &str {
+        &self.accessions[0]
+    }
+
+    /// All accession numbers, starting with the primary one
+    pub fn accessions(&self) -> &[String] {
+        &self.accessions
+    }
+
+    pub fn secondary_accessions(&self) -> &[String] {
+        &self.accessions[1..]
+    }
+
+    /// Create an entry out of a UniProt FASTA record
+    /// Everything that is not part of the header is left empty, and the SQ line is derived from the sequence
+    pub fn from_fasta(header: FastaHeader, sequence: String) -> Self {
+        let status = header.status();
+
+        let mut description = ProteinDescription::default();
+        let (name, fragment) = header.name_and_fragment();
+        if fragment {
+            description.flags.push("Fragment".to_string());
+        }
+        if !name.is_empty() {
+            let name = ProteinName {
+                full: name.to_string(),
+                ..ProteinName::default()
+            };
+            match status {
+                ReviewStatus::Reviewed => description.recommended = Some(name),
+                ReviewStatus::Unreviewed => description.submitted.push(name),
+            }
+        }
+
+        let genes = header
+            .gene
+            .map(|name| Gene {
+                name: Some(name),
+                ..Gene::default()
+            })
+            .into_iter()
+            .collect();
+
+        let organism = Organism {
+            name: header.organism_name.unwrap_or_default(),
+            taxon_id: header.taxon_id,
+            ..Organism::default()
+        };
+
+        let sequence_info = SequenceInfo {
+            length: sequence.len() as u32,
+            molecular_weight: molecular_weight(&sequence),
+            crc64: crc64(&sequence),
+        };
+
+        Self {
+            entry_name: header.entry_name,
+            status,
+            length: sequence_info.length,
+            accessions: vec![header.accession],
+            dates: None,
+            sequence_version: header.sequence_version.unwrap_or(1),
+            description,
+            genes,
+            organism,
+            keywords: Vec::new(),
+            protein_existence: header.protein_existence,
+            db_references: Vec::new(),
+            go_annotations: Vec::new(),
+            features: Vec::new(),
+            alternative_products: None,
+            sequence_info,
+            sequence,
+        }
+    }
+
+    /// Create an entry out of an entry from the UniProtKB XML files
+    /// Features and comments are not converted, as the XML files describe them differently
+    pub fn from_xml(entry: &XmlEntry) -> Result<Self> {
+        let (status, integrated_into) = match entry.dataset {
+            Dataset::SwissProt => (ReviewStatus::Reviewed, "UniProtKB/Swiss-Prot"),
+            Dataset::TrEmbl => (ReviewStatus::Unreviewed, "UniProtKB/TrEMBL"),
+        };
+
+        let dates = EntryDates {
+            integrated: *entry.created.as_ref(),
+            integrated_into: integrated_into.to_string(),
+            sequence_date: *entry.sequence.modified.as_ref(),
+            sequence_version: entry.sequence.version as u32,
+            entry_date: *entry.modified.as_ref(),
+            entry_version: entry.version as u32,
+        };
+
+        let mut description = convert_nomenclature(&entry.protein.name);
+        description.domains = entry
+            .protein
+            .domains
+            .iter()
+            .map(convert_nomenclature)
+            .collect();
+        description.components = entry
+            .protein
+            .components
+            .iter()
+            .map(convert_nomenclature)
+            .collect();
+        if entry.sequence.precursor == Some(true) {
+            description.flags.push("Precursor".to_string());
+        }
+        match entry.sequence.fragment {
+            Some(FragmentType::Single) => description.flags.push("Fragment".to_string()),
+            Some(FragmentType::Multiple) => description.flags.push("Fragments".to_string()),
+            None => {}
+        }
+
+        let organism = Organism {
+            name: organism_name(&entry.organism).unwrap_or_default(),
+            organelles: Vec::new(),
+            lineage: entry
+                .organism
+                .lineages
+                .first()
+                .map(|l| l.taxons.iter().map(|t| t.to_string()).collect())
+                .unwrap_or_default(),
+            taxon_id: xml_taxon_id(&entry.organism).with_context(|| {
+                format!(
+                    "Missing NCBI taxonomy reference in entry {}",
+                    entry
+                        .accessions
+                        .first()
+                        .map_or("without accession", |a| a.as_str())
+                )
+            })?,
+            hosts: entry
+                .organism_hosts
+                .iter()
+                .filter_map(|host| {
+                    Some(OrganismHost {
+                        taxon_id: xml_taxon_id(host)?,
+                        name: organism_name(host).unwrap_or_default(),
+                    })
+                })
+                .collect(),
+        };
+
+        let db_references: Vec<DbReference> = entry
+            .db_references
+            .iter()
+            .map(convert_db_reference)
+            .collect();
+        let go_annotations = entry
+            .db_references
+            .iter()
+            .filter(|r| r.ty == "GO")
+            .filter_map(convert_go_annotation)
+            .collect();
+
+        let sequence = entry.sequence.value.to_string();
+        let sequence_info = SequenceInfo {
+            length: entry.sequence.length as u32,
+            molecular_weight: entry.sequence.mass as u32,
+            crc64: format!("{:016X}", entry.sequence.checksum),
+        };
 
         Ok(Self {
-            accession_number,
-            name,
+            entry_name: entry
+                .names
+                .first()
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+            status,
+            length: sequence_info.length,
+            accessions: entry.accessions.iter().map(|a| a.to_string()).collect(),
+            dates: Some(dates),
+            sequence_version: entry.sequence.version as u32,
+            description,
+            genes: entry.genes.iter().map(convert_gene).collect(),
+            organism,
+            keywords: entry.keywords.iter().map(|k| k.value.to_string()).collect(),
+            protein_existence: Some(match entry.protein_existence {
+                XmlProteinExistence::ProteinLevelEvidence => ProteinExistence::ProteinLevel,
+                XmlProteinExistence::TranscriptLevelEvidence => ProteinExistence::TranscriptLevel,
+                XmlProteinExistence::HomologyInferred => ProteinExistence::Homology,
+                XmlProteinExistence::Predicted => ProteinExistence::Predicted,
+                XmlProteinExistence::Uncertain => ProteinExistence::Uncertain,
+            }),
+            db_references,
+            go_annotations,
+            features: Vec::new(),
+            alternative_products: None,
+            sequence_info,
             sequence,
-            version,
-            ec_references,
-            go_references,
-            ip_references,
-            taxon_id,
         })
     }
 
-    /// Write an entry to stdout
-    pub fn write(&self, db_type: &str) {
-        if self.name.is_empty() {
+    /// Create an entry for an isoform of this entry, which only differs in its accession and sequence
+    /// Features and isoforms are dropped, as their positions refer to the canonical sequence
+    pub fn isoform(&self, accession: &str, sequence: String) -> Self {
+        let sequence_info = SequenceInfo {
+            length: sequence.len() as u32,
+            molecular_weight: molecular_weight(&sequence),
+            crc64: crc64(&sequence),
+        };
+
+        Self {
+            length: sequence_info.length,
+            accessions: vec![accession.to_string()],
+            features: Vec::new(),
+            alternative_products: None,
+            sequence_info,
+            sequence,
+            ..self.clone()
+        }
+    }
+
+    /// The dates on the DT lines, which are unknown for entries from FASTA files
+    pub fn dates(&self) -> Option<&EntryDates> {
+        self.dates.as_ref()
+    }
+
+    /// The entry version, or the sequence version for entries from FASTA files,
+    /// which don't have an entry version
+    pub fn version(&self) -> u32 {
+        self.dates
+            .as_ref()
+            .map_or(self.sequence_version, |d| d.entry_version)
+    }
+
+    pub fn sequence_version(&self) -> u32 {
+        self.sequence_version
+    }
+
+    pub fn description(&self) -> &ProteinDescription {
+        &self.description
+    }
+
+    /// The preferred name of this protein, see `ProteinDescription::resolve_name`
+    pub fn name(&self) -> &str {
+        self.description.resolve_name().unwrap_or_default()
+    }
+
+    pub fn ec_numbers(&self) -> Vec<&str> {
+        self.description.ec_numbers()
+    }
+
+    pub fn genes(&self) -> &[Gene] {
+        &self.genes
+    }
+
+    pub fn organism(&self) -> &Organism {
+        &self.organism
+    }
+
+    pub fn taxon_id(&self) -> u32 {
+        self.organism.taxon_id
+    }
+
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// The evidence level on the PE line, which can be missing in FASTA files
+    pub fn protein_existence(&self) -> Option<ProteinExistence> {
+        self.protein_existence
+    }
+
+    pub fn db_references(&self) -> &[DbReference] {
+        &self.db_references
+    }
+
+    /// The ids of all references to a single database
+    pub fn db_reference_ids(&self, database: &str) -> Vec<&str> {
+        self.db_references
+            .iter()
+            .filter(|r| r.database == database)
+            .map(|r| r.id.as_str())
+            .collect()
+    }
+
+    pub fn go_references(&self) -> Vec<&str> {
+        self.db_reference_ids("GO")
+    }
+
+    /// The GO annotations of this entry, including their aspect and evidence
+    pub fn go_annotations(&self) -> &[GoAnnotation] {
+        &self.go_annotations
+    }
+
+    pub fn interpro_references(&self) -> Vec<&str> {
+        self.db_reference_ids("InterPro")
+    }
+
+    pub fn features(&self) -> &[Feature] {
+        &self.features
+    }
+
+    /// The isoforms described in the ALTERNATIVE PRODUCTS comment, which is parsed on every call
+    pub fn isoforms(&self) -> Result<Vec<Isoform>> {
+        self.alternative_products
+            .as_deref()
+            .map_or(Ok(Vec::new()), parse_alternative_products)
+            .with_context(|| {
+                format!(
+                    "Error parsing the ALTERNATIVE PRODUCTS comment of entry AC-{}",
+                    self.accession_number()
+                )
+            })
+    }
+
+    pub fn sequence_info(&self) -> &SequenceInfo {
+        &self.sequence_info
+    }
+
+    pub fn sequence(&self) -> &str {
+        &self.sequence
+    }
+
+    /// The molecular weight declared on the SQ line, in Dalton
+    pub fn molecular_weight(&self) -> u32 {
+        self.sequence_info.molecular_weight
+    }
+
+    /// The CRC64 checksum declared on the SQ line
+    pub fn crc64(&self) -> &str {
+        &self.sequence_info.crc64
+    }
+
+    /// Check the sequence against the length on the ID and SQ lines, and the CRC64 checksum on the SQ line
+    pub fn validate_sequence(&self) -> Result<()> {
+        if self.length != self.sequence_info.length {
+            return Err(Error::msg(format!(
+                "ID line declares length {}, but SQ line declares {}",
+                self.length, self.sequence_info.length
+            )));
+        }
+
+        validate_sequence(
+            &self.sequence,
+            self.sequence_info.length,
+            &self.sequence_info.crc64,
+        )
+    }
+
+    /// The value for the "Status" column, derived from the ID line unless it is overridden
+    pub fn db_type<'a>(&self, db_type_override: Option<&'a str>) -> &'a str {
+        db_type_override.unwrap_or(self.status.db_type())
+    }
+
+    /// Whether `db_type_override` is a known UniProtKB section that contradicts the ID line
+    pub fn contradicts_db_type(&self, db_type_override: Option<&str>) -> bool {
+        db_type_override
+            .and_then(ReviewStatus::from_db_type)
+            .is_some_and(|s| s != self.status)
+    }
+
+    /// The GO annotations with an evidence code that passes `filter`
+    pub fn filtered_go_annotations<'a>(
+        &'a self,
+        filter: &'a GoEvidenceFilter,
+    ) -> impl Iterator<Item = &'a GoAnnotation> {
+        self.go_annotations
+            .iter()
+            .filter(|a| filter.allows(&a.evidence))
+    }
+
+    /// The values of all output columns for this entry, to be written to an `EntrySink`
+    /// The "Status" column is derived from the ID line, unless it is overridden in `options`
+    /// Cross-references are only included for the configured databases (see `header_columns`)
+    /// Sequence variants are only included for the configured feature kinds
+    pub fn record<'a>(&'a self, options: &'a OutputOptions) -> EntryRecord<'a> {
+        let name = self.name();
+
+        if name.is_empty() {
             eprintln!(
                 "Could not find a name for entry AC-{}",
-                self.accession_number
+                self.accession_number()
             );
         }
 
-        println!(
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
-            self.accession_number,
-            self.sequence,
-            self.name,
-            self.version,
-            self.ec_references.join(";"),
-            self.go_references.join(";"),
-            self.ip_references.join(";"),
-            db_type,
-            self.taxon_id
-        )
+        let xref_enabled = |database: &str| options.xrefs.iter().any(|x| x == database);
+
+        let go_annotations: Vec<&GoAnnotation> = if xref_enabled("GO") {
+            self.filtered_go_annotations(&options.go_evidence).collect()
+        } else {
+            Vec::new()
+        };
+
+        EntryRecord {
+            accession: self.accession_number(),
+            sequence: &self.sequence,
+            name,
+            version: self.version(),
+            ec_numbers: self.ec_numbers(),
+            go_ids: go_annotations.iter().map(|a| a.id.as_str()).collect(),
+            interpro_ids: if xref_enabled("InterPro") {
+                self.interpro_references()
+            } else {
+                Vec::new()
+            },
+            status: self.db_type(options.db_type_override.as_deref()),
+            taxon_id: Some(self.taxon_id()),
+            extra_xrefs: extra_xrefs(&options.xrefs)
+                .map(|database| self.db_reference_ids(database))
+                .collect(),
+            go_evidence: options
+                .go_evidence_column
+                .then(|| go_annotations.iter().map(|a| a.evidence.as_str()).collect()),
+            variants: (!options.variants.is_empty()).then(|| {
+                sequence_variants(self, &options.variants)
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect()
+            }),
+        }
     }
 }
 
-// Functions to parse an Entry out of a Vec<String>
+// Functions to parse an Entry out of borrowed blocks of lines
 
-/// Find the first AC number
-fn parse_ac_number(data: &mut [String]) -> anyhow::Result<String> {
-    // The AC number is always the second element
-    let line = &mut data[1];
-    line.drain(..COMMON_PREFIX_LEN);
-    let (pre, _) = line
-        .split_once(';')
-        .with_context(|| format!("Unable to split \"{line}\" on ';'"))?;
-    Ok(pre.to_string())
+/// Iterator over the blocks of consecutive lines that share the same line code
+/// Every block is a slice of the original text, so no data is copied
+struct Blocks<'a> {
+    text: &'a str,
 }
 
-/// Find the version of this entry
-fn parse_version(data: &[String], index: &mut usize) -> String {
-    let mut last_field: usize = 2;
-
-    // Skip past previous fields to get to the dates
-    while !data[last_field].starts_with("DT") {
-        last_field += 1;
+impl<'a> Blocks<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text }
     }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.text.is_empty() {
+            return None;
+        }
 
-    // The date fields are always the third-n elements
-    // The version is always the last one
-    while data[last_field + 1].starts_with("DT") {
-        last_field += 1;
+        let code = line_code(self.text);
+        let mut end_index = 0;
+
+        // Advance line by line until we find a line with a different code
+        loop {
+            match self.text[end_index..].find('\n') {
+                Some(newline_index) => {
+                    end_index += newline_index + 1;
+
+                    if end_index == self.text.len() || line_code(&self.text[end_index..]) != code {
+                        break;
+                    }
+                }
+                None => {
+                    end_index = self.text.len();
+                    break;
+                }
+            }
+        }
+
+        let block = &self.text[..end_index];
+        self.text = &self.text[end_index..];
+
+        Some((code, block.trim_end_matches('\n')))
     }
+}
 
-    // Get entry version (has prefix of constant length and ends with a dot)
-    let version_end = data[last_field].len() - 1;
-    *index = last_field + 1;
-    data[last_field][VERSION_STRING_FULL_PREFIX_LEN..version_end].to_string()
+/// The two-letter code at the start of a line (two spaces for sequence data)
+fn line_code(line: &str) -> &str {
+    line.get(..LINE_CODE_LEN).unwrap_or(line)
 }
 
-/// Parse the name and EC numbers of an entry out of all available DE fields
-/// In order of preference:
-/// - Last recommended name of protein components
-/// - Last recommended name of protein domains
-/// - Recommended name of protein itself
-/// - Last submitted name of protein components
-/// - Last submitted name of protein domains
-/// - Submitted name of protein itself
-fn parse_name_and_ec(data: &mut [String], index: &mut usize) -> (String, Vec<String>) {
-    // Find where the info starts and ends
-    while !data[*index].starts_with("DE") {
-        *index += 1;
+/// The content of a line, without its code
+fn line_content(line: &str) -> &str {
+    line.get(COMMON_PREFIX_LEN..).unwrap_or_default()
+}
+
+/// Join the content of a block of lines that continue each other
+fn join_lines(block: &str) -> String {
+    block
+        .lines()
+        .map(|l| line_content(l).trim())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Parse a block of lines that form one list separated by semicolons and ending with a dot
+fn parse_list(block: &str) -> Vec<String> {
+    let joined = join_lines(block);
+
+    split_outside_braces(joined.trim_end_matches('.'), ';')
+        .into_iter()
+        .map(strip_metadata)
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// Parse the entry name, review status and sequence length out of the ID line
+fn parse_id_line(line: &str) -> Result<(String, ReviewStatus, u32)> {
+    let mut fields = line_content(line).split_whitespace();
+
+    let entry_name = fields.next().context("Missing entry name")?;
+    let status = fields.next().context("Missing review status")?;
+    let status = ReviewStatus::from_str(status.trim_end_matches(';'))
+        .with_context(|| format!("Unknown review status \"{status}\""))?;
+    let length = fields.next().context("Missing sequence length")?;
+    let length = length
+        .parse()
+        .with_context(|| format!("Unable to parse sequence length \"{length}\""))?;
+
+    Ok((entry_name.to_string(), status, length))
+}
+
+/// Find all AC numbers, the first one is the primary accession number
+fn parse_accessions(block: &str) -> Vec<String> {
+    block
+        .lines()
+        .flat_map(|l| line_content(l).split(';'))
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// Parse the integration date, sequence version and entry version
+fn parse_dates(block: &str) -> Result<EntryDates> {
+    let mut integrated = None;
+    let mut sequence = None;
+    let mut entry = None;
+
+    for line in block.lines() {
+        let content = line_content(line);
+        let (date, event) = content
+            .split_once(", ")
+            .with_context(|| format!("Unable to split \"{content}\" on ', '"))?;
+        let date = NaiveDate::parse_from_str(date, DATE_FORMAT)
+            .with_context(|| format!("Unable to parse date \"{date}\""))?;
+        let event = event.trim_end_matches('.');
+
+        if let Some(database) = event.strip_prefix("integrated into ") {
+            integrated = Some((date, database.to_string()));
+        } else if let Some(version) = event.strip_prefix("sequence version ") {
+            sequence = Some((date, parse_version_number(version)?));
+        } else if let Some(version) = event.strip_prefix("entry version ") {
+            entry = Some((date, parse_version_number(version)?));
+        }
     }
 
-    let mut name = String::new();
-    let mut ec_references = Vec::new();
-    let mut ec_reference_set = HashSet::new();
-    let mut end_index = *index;
+    let (integrated, integrated_into) = integrated.context("Missing integration date")?;
+    let (sequence_date, sequence_version) = sequence.context("Missing sequence version")?;
+    let (entry_date, entry_version) = entry.context("Missing entry version")?;
+
+    Ok(EntryDates {
+        integrated,
+        integrated_into,
+        sequence_date,
+        sequence_version,
+        entry_date,
+        entry_version,
+    })
+}
+
+fn parse_version_number(version: &str) -> Result<u32> {
+    version
+        .parse()
+        .with_context(|| format!("Unable to parse version \"{version}\""))
+}
 
-    // Track all names in order of preference
-    let mut name_indices: [usize; 6] = [usize::MAX; 6];
-    const LAST_COMPONENT_RECOMMENDED_IDX: usize = 0;
-    const LAST_COMPONENT_SUBMITTED_IDX: usize = 3;
-    const LAST_DOMAIN_RECOMMENDED_IDX: usize = 1;
-    const LAST_DOMAIN_SUBMITTED_IDX: usize = 4;
-    const LAST_PROTEIN_RECOMMENDED_IDX: usize = 2;
-    const LAST_PROTEIN_SUBMITTED_IDX: usize = 5;
+/// The kind of name that is currently being described in a DE block
+#[derive(Clone, Copy)]
+enum NameKind {
+    Recommended,
+    Alternative,
+    Submitted,
+}
+
+/// Parse all names and EC numbers of an entry out of the DE lines
+/// Protein information is given at the top level, followed by one block per domain ("Includes:")
+/// and one block per component ("Contains:")
+fn parse_description(block: &str) -> ProteinDescription {
+    let mut top = ProteinDescription::default();
+    let mut domains = Vec::new();
+    let mut components = Vec::new();
+    let mut flags = Vec::new();
 
     // Keep track of which block we are currently in
-    // Order in DAT file is always protein -> components -> domain
-    let mut inside_domain = false;
-    let mut inside_component = false;
-
-    while data[end_index].starts_with("DE") {
-        let line = &mut data[end_index];
-        line.drain(..COMMON_PREFIX_LEN);
-
-        // Marks the start of a Component
-        if line == "Contains:" {
-            inside_component = true;
-            end_index += 1;
+    let mut current = &mut top;
+    let mut kind = None;
+
+    for line in block.lines() {
+        // Consecutive lines and nested blocks have leading spaces we don't care for
+        let content = line_content(line).trim();
+
+        if content == "Includes:" {
+            domains.push(ProteinDescription::default());
+            current = domains.last_mut().unwrap();
+            kind = None;
             continue;
         }
 
-        // Marks the start of a Domain
-        if line == "Includes:" {
-            inside_domain = true;
-            end_index += 1;
+        if content == "Contains:" {
+            components.push(ProteinDescription::default());
+            current = components.last_mut().unwrap();
+            kind = None;
             continue;
         }
 
-        // Remove all other spaces (consecutive lines have leading spaces we don't care for)
-        drain_leading_spaces(line);
-
-        // Keep track of the last recommended name
-        if line.starts_with("RecName: Full=") {
-            if inside_domain {
-                name_indices[LAST_DOMAIN_RECOMMENDED_IDX] = end_index;
-            } else if inside_component {
-                name_indices[LAST_COMPONENT_RECOMMENDED_IDX] = end_index;
-            } else {
-                name_indices[LAST_PROTEIN_RECOMMENDED_IDX] = end_index;
-            }
+        // Flags always apply to the protein itself, but are listed at the very end
+        if let Some(f) = content.strip_prefix("Flags:") {
+            flags.extend(
+                f.split(';')
+                    .map(strip_metadata)
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.to_string()),
+            );
+            continue;
         }
-        // Find EC numbers
-        else if line.starts_with("EC=") {
-            let ec_target = read_until_metadata(line, ORGANISM_RECOMMENDED_NAME_EC_PREFIX_LEN);
 
-            // EC numbers sometimes appear multiple times, so use a set to track which ones
-            // we've seen before
-            if !ec_reference_set.contains(&ec_target) {
-                ec_reference_set.insert(ec_target.clone());
-                ec_references.push(ec_target);
+        // Check if this line starts a new name
+        let mut new_name = true;
+        let token = if let Some(t) = content.strip_prefix("RecName:") {
+            kind = Some(NameKind::Recommended);
+            t
+        } else if let Some(t) = content.strip_prefix("AltName:") {
+            kind = Some(NameKind::Alternative);
+            t
+        } else if let Some(t) = content.strip_prefix("SubName:") {
+            kind = Some(NameKind::Submitted);
+            t
+        } else {
+            new_name = false;
+            content
+        };
+
+        let Some((key, value)) = token.trim().split_once('=') else {
+            continue;
+        };
+        let value = strip_metadata(value).to_string();
+
+        // Special alternative names are not actual protein names
+        match key {
+            "Allergen" => current.allergen = Some(value),
+            "Biotech" => current.biotech = Some(value),
+            "CD_antigen" => current.cd_antigens.push(value),
+            "INN" => current.inns.push(value),
+            _ => {
+                let Some(kind) = kind else {
+                    continue;
+                };
+
+                let name = current_name(current, kind, new_name);
+                match key {
+                    "Full" => name.full = value,
+                    "Short" => name.short.push(value),
+                    "EC" => name.ec_numbers.push(value),
+                    _ => {}
+                }
             }
         }
-        // Keep track of the last submitted name
-        else if line.starts_with("SubName: Full=") {
-            if inside_domain {
-                name_indices[LAST_DOMAIN_SUBMITTED_IDX] = end_index;
-            } else if inside_component {
-                name_indices[LAST_COMPONENT_SUBMITTED_IDX] = end_index;
-            } else {
-                name_indices[LAST_PROTEIN_SUBMITTED_IDX] = end_index;
-            }
+    }
+
+    top.domains = domains;
+    top.components = components;
+    top.flags = flags;
+    top
+}
+
+/// Get the name that is currently being described, optionally creating a new one first
+fn current_name(
+    description: &mut ProteinDescription,
+    kind: NameKind,
+    new_name: bool,
+) -> &mut ProteinName {
+    let names = match kind {
+        NameKind::Recommended => {
+            return description
+                .recommended
+                .get_or_insert_with(ProteinName::default)
         }
+        NameKind::Alternative => &mut description.alternative,
+        NameKind::Submitted => &mut description.submitted,
+    };
 
-        end_index += 1;
+    if new_name || names.is_empty() {
+        names.push(ProteinName::default());
     }
 
-    // Choose a name from the ones we encountered
-    // Use the first name that we managed to find, in order
-    for idx in name_indices {
-        if idx != usize::MAX {
-            let line = &mut data[idx];
-            *index = end_index;
-            name = read_until_metadata(line, ORGANISM_RECOMMENDED_NAME_PREFIX_LEN);
-            return (name, ec_references);
+    names.last_mut().unwrap()
+}
+
+/// Parse all genes out of the GN lines
+/// Multiple genes are separated by a line containing only "and"
+fn parse_genes(block: &str) -> Vec<Gene> {
+    let mut genes = Vec::new();
+
+    // Tokens can wrap around to the next line, so join all lines of a gene first
+    let mut joined = String::new();
+
+    for line in block.lines() {
+        let content = line_content(line).trim();
+
+        if content == "and" {
+            genes.push(parse_gene(&joined));
+            joined.clear();
+            continue;
+        }
+
+        if !joined.is_empty() {
+            joined.push(' ');
         }
+
+        joined.push_str(content);
     }
 
-    (name, ec_references)
+    genes.push(parse_gene(&joined));
+    genes
 }
 
-/// Find the first NCBI_TaxID of this entry
-fn parse_taxon_id(data: &mut [String], index: &mut usize) -> String {
-    while !data[*index].starts_with("OX   NCBI_TaxID=") {
-        *index += 1;
+/// Parse a single gene, eg. "Name=Jon99Cii; Synonyms=SER1, SER5; ORFNames=CG7877;"
+fn parse_gene(joined: &str) -> Gene {
+    let mut gene = Gene::default();
+
+    for token in split_outside_braces(joined, ';') {
+        let Some((key, value)) = token.trim().split_once('=') else {
+            continue;
+        };
+
+        let values = split_outside_braces(value, ',')
+            .into_iter()
+            .map(strip_metadata)
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string());
+
+        match key {
+            "Name" => gene.name = values.into_iter().next(),
+            "Synonyms" => gene.synonyms.extend(values),
+            "OrderedLocusNames" => gene.ordered_locus_names.extend(values),
+            "ORFNames" => gene.orf_names.extend(values),
+            _ => {}
+        }
     }
 
-    let line = &mut data[*index];
-    let taxon_id = read_until_metadata(line, ORGANISM_TAXON_ID_PREFIX_LEN);
+    gene
+}
 
-    while data[*index].starts_with("OX") {
-        *index += 1;
-    }
+/// Parse an NCBI taxon id, eg. "NCBI_TaxID=654924;"
+fn parse_taxon_id(content: &str) -> Result<u32> {
+    let taxon_id = content
+        .strip_prefix(NCBI_TAXON_ID_PREFIX)
+        .with_context(|| format!("Missing \"{NCBI_TAXON_ID_PREFIX}\" in \"{content}\""))?;
+    let taxon_id = strip_metadata(taxon_id);
 
     taxon_id
+        .parse()
+        .with_context(|| format!("Unable to parse taxon id \"{taxon_id}\""))
 }
 
-/// Parse GO and InterPro DB references
-fn parse_db_references(data: &mut Vec<String>, index: &mut usize) -> (Vec<String>, Vec<String>) {
-    let mut go_references = Vec::new();
-    let mut ip_references = Vec::new();
-    let original_idx = *index;
-    let length = data.len();
+/// Parse a single host organism, eg. "NCBI_TaxID=8404; Lithobates pipiens (Northern leopard frog)."
+fn parse_organism_host(line: &str) -> Result<OrganismHost> {
+    let (taxon_id, name) = line_content(line)
+        .split_once(';')
+        .with_context(|| format!("Unable to split \"{line}\" on ';'"))?;
 
-    // Find where references start
-    while !data[*index].starts_with("DR") {
-        *index += 1;
+    Ok(OrganismHost {
+        taxon_id: parse_taxon_id(taxon_id)?,
+        name: name.trim().trim_end_matches('.').to_string(),
+    })
+}
 
-        // No references present in this entry
-        if *index == length {
-            *index = original_idx;
-            return (go_references, ip_references);
+/// Parse the protein existence level, eg. "4: Predicted;"
+fn parse_protein_existence(line: &str) -> Result<ProteinExistence> {
+    let content = line_content(line);
+    let (level, _) = content
+        .split_once(':')
+        .with_context(|| format!("Unable to split \"{content}\" on ':'"))?;
+
+    level
+        .parse()
+        .ok()
+        .and_then(ProteinExistence::from_level)
+        .with_context(|| format!("Unknown protein existence level \"{level}\""))
+}
+
+/// Parse a single DB reference, eg. "GO; GO:0046782; P:regulation of viral transcription; IEA:InterPro."
+/// References that only apply to one isoform end with the isoform between square brackets
+fn parse_db_reference(line: &str) -> Result<DbReference> {
+    let mut line = line.trim_end();
+    let mut isoform = None;
+
+    if let Some(without_bracket) = line.strip_suffix(']') {
+        if let Some((rest, iso)) = without_bracket.rsplit_once(" [") {
+            isoform = Some(iso.to_string());
+            line = rest;
         }
     }
 
-    // Parse all references
-    while data[*index].starts_with("DR") {
-        let line = &mut data[*index];
-        line.drain(..COMMON_PREFIX_LEN);
+    let line = line.strip_suffix('.').unwrap_or(line);
+    let mut fields = line.split("; ");
+
+    let database = fields.next().filter(|x| !x.is_empty());
+    let database = database.with_context(|| format!("Missing database in \"{line}\""))?;
+    let id = fields
+        .next()
+        .with_context(|| format!("Missing id in \"{line}\""))?;
+
+    Ok(DbReference {
+        database: database.to_string(),
+        id: id.to_string(),
+        properties: fields.map(|x| x.to_string()).collect(),
+        isoform,
+    })
+}
+
+/// Parse the aspect, term and evidence out of the properties of a GO cross-reference
+/// eg. ["P:regulation of viral transcription", "IEA:InterPro"]
+fn parse_go_annotation(reference: &DbReference) -> Result<GoAnnotation> {
+    let (term, evidence) = match reference.properties.as_slice() {
+        [term, evidence, ..] => (term, evidence),
+        _ => {
+            return Err(Error::msg(format!(
+                "Missing term or evidence for {}",
+                reference.id
+            )))
+        }
+    };
+
+    let (aspect, term) = term
+        .split_once(':')
+        .with_context(|| format!("Missing aspect in GO term \"{term}\""))?;
+    let aspect =
+        GoAspect::from_code(aspect).with_context(|| format!("Unknown GO aspect \"{aspect}\""))?;
+    let (evidence, source) = evidence.split_once(':').unwrap_or((evidence, ""));
+
+    Ok(GoAnnotation {
+        id: reference.id.clone(),
+        aspect,
+        term: term.to_string(),
+        evidence: evidence.to_string(),
+        source: source.to_string(),
+    })
+}
 
-        parse_db_reference(line, &mut go_references, &mut ip_references);
+/// Find the content of the ALTERNATIVE PRODUCTS topic of the CC lines, joined into a single line
+fn find_alternative_products(block: &str) -> Option<String> {
+    let start = memmem::find(block.as_bytes(), ALTERNATIVE_PRODUCTS_TOPIC.as_bytes())?;
 
-        *index += 1;
+    let mut content = String::new();
+    for line in block[start..].lines().map(line_content).skip(1) {
+        if line.starts_with("-!-") || line.starts_with("---") {
+            break;
+        }
+        content.push(' ');
+        content.push_str(line.trim());
     }
 
-    (go_references, ip_references)
+    Some(content)
 }
 
-/// Parse a single GO or InterPro DB reference
-fn parse_db_reference(
-    line: &mut str,
-    go_references: &mut Vec<String>,
-    ip_references: &mut Vec<String>,
-) {
-    if line.starts_with("GO;") {
-        let substr = &line[4..14];
-        go_references.push(substr.to_string());
-    } else if line.starts_with("InterPro;") {
-        let substr = &line[10..19];
-        ip_references.push(substr.to_string());
+/// Parse the isoforms out of the content of the ALTERNATIVE PRODUCTS topic
+/// Every isoform starts with a "Name=" token, followed by its "IsoId=" and "Sequence=" tokens
+fn parse_alternative_products(content: &str) -> Result<Vec<Isoform>> {
+    let mut isoforms: Vec<Isoform> = Vec::new();
+
+    for token in split_outside_braces(content, ';') {
+        let Some((key, value)) = token.split_once('=') else {
+            continue;
+        };
+
+        let value = strip_metadata(value);
+        match key.trim() {
+            "Name" => isoforms.push(Isoform {
+                name: value.to_string(),
+                ids: Vec::new(),
+                sequence: IsoformSequence::NotDescribed,
+            }),
+            "IsoId" => {
+                let isoform = isoforms
+                    .last_mut()
+                    .with_context(|| format!("Found IsoId \"{value}\" before a Name"))?;
+                isoform.ids = value.split(',').map(|id| id.trim().to_string()).collect();
+            }
+            "Sequence" => {
+                let isoform = isoforms
+                    .last_mut()
+                    .with_context(|| format!("Found Sequence \"{value}\" before a Name"))?;
+                isoform.sequence = match value {
+                    "Displayed" => IsoformSequence::Displayed,
+                    "External" => IsoformSequence::External,
+                    "Not described" => IsoformSequence::NotDescribed,
+                    _ => IsoformSequence::Variations(
+                        value.split(',').map(|id| id.trim().to_string()).collect(),
+                    ),
+                };
+            }
+            _ => {}
+        }
     }
+
+    Ok(isoforms)
 }
 
-/// Parse the peptide sequence for this entry
-fn parse_sequence(data: &mut [String], mut index: usize) -> String {
-    // Find the beginning of the sequence
-    // optionally skip over some fields we don't care for
-    while !data[index].starts_with("SQ") {
-        index += 1;
+/// Parse all features out of the FT lines
+/// A feature starts with its key and location, followed by qualifiers on the next lines:
+/// FT   CHAIN           1..256
+/// FT                   /note="Putative transcription factor 001R"
+fn parse_features(block: &str) -> Result<Vec<Feature>> {
+    let mut features: Vec<Feature> = Vec::new();
+
+    // Qualifier values between quotes can span multiple lines
+    let mut inside_quotes = false;
+
+    for line in block.lines() {
+        let content = line_content(line);
+
+        if !content.starts_with(' ') {
+            let (key, location) = content.split_once(' ').unwrap_or((content, ""));
+            features.push(Feature {
+                key: key.to_string(),
+                location: location.trim().to_string(),
+                qualifiers: Vec::new(),
+            });
+            inside_quotes = false;
+            continue;
+        }
+
+        let feature = features
+            .last_mut()
+            .with_context(|| format!("Found qualifier \"{content}\" outside of a feature"))?;
+        let content = content.trim();
+
+        if !inside_quotes {
+            if let Some(qualifier) = content.strip_prefix('/') {
+                let (name, value) = qualifier.split_once('=').unwrap_or((qualifier, ""));
+                inside_quotes =
+                    value.starts_with('"') && (value.len() == 1 || !value.ends_with('"'));
+                feature
+                    .qualifiers
+                    .push((name.to_string(), value.to_string()));
+                continue;
+            }
+        }
+
+        let (_, value) = feature
+            .qualifiers
+            .last_mut()
+            .with_context(|| format!("Found continuation \"{content}\" without a qualifier"))?;
+        value.push(' ');
+        value.push_str(content);
+        inside_quotes = inside_quotes && !content.ends_with('"');
+    }
+
+    for feature in features.iter_mut() {
+        for (_, value) in feature.qualifiers.iter_mut() {
+            if let Some(unquoted) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                *value = unquoted.to_string();
+            }
+        }
     }
 
-    // First line of the sequence contains some metadata we don't care for
-    index += 1;
+    Ok(features)
+}
 
-    let mut sequence = String::new();
+/// Parse the sequence metadata, eg. "SEQUENCE   256 AA;  29735 MW;  B4840739BF7D4121 CRC64;"
+fn parse_sequence_info(line: &str) -> Result<SequenceInfo> {
+    let fields: Vec<&str> = line_content(line).split_whitespace().collect();
 
-    // Combine all remaining lines
-    for line in data.iter_mut().skip(index) {
-        line.drain(..COMMON_PREFIX_LEN);
-        sequence.push_str(&line.replace(' ', ""));
+    if fields.len() < 7 || fields[0] != "SEQUENCE" {
+        return Err(Error::msg(format!("Malformed sequence header \"{line}\"")));
+    }
+
+    Ok(SequenceInfo {
+        length: fields[1]
+            .parse()
+            .with_context(|| format!("Unable to parse sequence length \"{}\"", fields[1]))?,
+        molecular_weight: fields[3]
+            .parse()
+            .with_context(|| format!("Unable to parse molecular weight \"{}\"", fields[3]))?,
+        crc64: fields[5].to_string(),
+    })
+}
+
+/// Parse the peptide sequence for this entry
+fn parse_sequence(block: &str, capacity: usize) -> String {
+    let mut sequence = String::with_capacity(capacity);
+
+    // Combine all lines, removing the spaces between blocks of residues
+    for line in block.lines() {
+        for residues in line.split(' ') {
+            sequence.push_str(residues);
+        }
     }
 
     sequence
 }
 
-/// Read a line until additional metadata starts
-/// Some lines end with {blocks between curly brackets} that we don't care for.
-fn read_until_metadata(line: &mut String, prefix_len: usize) -> String {
-    line.drain(..prefix_len);
+/// Split a string on a separator, ignoring separators inside of {metadata blocks}
+fn split_outside_braces(line: &str, separator: char) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut depth: usize = 0;
+    let mut start = 0;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            c if c == separator && depth == 0 => {
+                result.push(&line[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    result.push(&line[start..]);
+    result
+}
+
+/// Read a value until additional metadata starts
+/// Some values end with {blocks between curly brackets} that we don't care for.
+fn strip_metadata(value: &str) -> &str {
+    let value = value.trim().trim_end_matches(';');
 
-    // The line either contains some metadata, or just ends with a semicolon
-    // In the latter case, move the position to the end of the string,
-    // so we can pretend it is at a bracket and cut the semicolon out
-    // If it contains metadata, this wrapped in curly braces after a space
+    // If it contains metadata, this is wrapped in curly braces after a space
     // (sometimes there are curly braces inside of the name itself, so just a curly is not enough)
-    let mut bracket_index = 0;
-    let mut previous_char = '\0';
+    match value.find(" {") {
+        Some(bracket_index) => &value[..bracket_index],
+        None => value,
+    }
+}
 
-    for (i, c) in line.chars().enumerate() {
-        if c == '{' && previous_char == ' ' {
-            bracket_index = i;
-            break;
-        }
+// Functions to convert an Entry out of the UniProtKB XML model
+
+fn convert_protein_name(name: &XmlProteinName) -> ProteinName {
+    ProteinName {
+        full: name.full.to_string(),
+        short: name.short.iter().map(|n| n.to_string()).collect(),
+        ec_numbers: name.ec_number.iter().map(|n| n.to_string()).collect(),
+    }
+}
 
-        previous_char = c;
+/// Convert the names of a protein, domain or component, which don't have domains or components themselves
+fn convert_nomenclature(nomenclature: &Nomenclature) -> ProteinDescription {
+    ProteinDescription {
+        recommended: nomenclature.recommended.as_ref().map(convert_protein_name),
+        alternative: nomenclature
+            .alternative
+            .iter()
+            .map(convert_protein_name)
+            .collect(),
+        submitted: nomenclature
+            .submitted
+            .iter()
+            .map(convert_protein_name)
+            .collect(),
+        allergen: nomenclature.allergen.as_ref().map(|n| n.to_string()),
+        biotech: nomenclature.biotech.as_ref().map(|n| n.to_string()),
+        cd_antigens: nomenclature
+            .cd_antigen
+            .iter()
+            .map(|n| n.to_string())
+            .collect(),
+        inns: nomenclature.inn.iter().map(|n| n.to_string()).collect(),
+        ..ProteinDescription::default()
     }
+}
 
-    if bracket_index == 0 {
-        bracket_index = line.len();
+fn convert_gene(gene: &XmlGene) -> Gene {
+    let names = |ty: XmlGeneNameType| {
+        gene.names
+            .iter()
+            .filter(move |n| n.ty == ty)
+            .map(|n| n.value.to_string())
+    };
+
+    Gene {
+        name: names(XmlGeneNameType::Primary).next(),
+        synonyms: names(XmlGeneNameType::Synonym).collect(),
+        ordered_locus_names: names(XmlGeneNameType::OrderedLocus).collect(),
+        orf_names: names(XmlGeneNameType::Orf).collect(),
     }
+}
 
-    line[..bracket_index - 1].to_string()
+fn organism_name(organism: &uniprot::uniprot::organism::Organism) -> Option<String> {
+    organism
+        .names
+        .iter()
+        .find(|n| n.ty == XmlOrganismNameType::Scientific)
+        .map(|n| n.value.to_string())
 }
 
-/// Remove all leading spaces from a line
-/// Internally this just moves a pointer forward so this is very efficient
-fn drain_leading_spaces(line: &mut String) {
-    // Find the first index that is not a space, and remove everything before
-    for (idx, c) in line.chars().enumerate() {
-        if c != ' ' {
-            line.drain(..idx);
-            break;
-        }
+fn xml_taxon_id(organism: &uniprot::uniprot::organism::Organism) -> Option<u32> {
+    organism
+        .db_references
+        .iter()
+        .find(|r| r.ty == "NCBI Taxonomy")
+        .and_then(|r| r.id.parse().ok())
+}
+
+/// Convert a cross-reference, its properties are kept in the order of the XML file
+fn convert_db_reference(reference: &XmlDbReference) -> DbReference {
+    DbReference {
+        database: reference.ty.to_string(),
+        id: reference.id.to_string(),
+        properties: reference
+            .property
+            .iter()
+            .map(|p| p.value.to_string())
+            .collect(),
+        isoform: match &reference.molecule {
+            Some(uniprot::uniprot::Molecule::Id(id)) => Some(id.to_string()),
+            _ => None,
+        },
     }
 }
 
+/// Convert a GO cross-reference, of which the evidence is stored as an ECO code
+/// Codes that can't be translated into a GO evidence code are kept as-is
+fn convert_go_annotation(reference: &XmlDbReference) -> Option<GoAnnotation> {
+    let property = |ty: &str| {
+        reference
+            .property
+            .iter()
+            .find(|p| p.ty == ty)
+            .map_or("", |p| p.value.as_str())
+    };
+
+    let (aspect, term) = property("term").split_once(':')?;
+    let eco = property("evidence");
+
+    Some(GoAnnotation {
+        id: reference.id.to_string(),
+        aspect: GoAspect::from_code(aspect)?,
+        term: term.to_string(),
+        evidence: go_evidence_from_eco(eco).unwrap_or(eco).to_string(),
+        source: property("project").to_string(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dat_parser::sequential_parser::SequentialDATParser;
+    use crate::dat_parser::sink::{EntrySink, TsvSink};
 
-    fn _raw_str_to_strings(v: Vec<&str>) -> Vec<String> {
-        v.iter().map(|x| x.to_string()).collect()
-    }
-
-    fn get_example_entry() -> Vec<String> {
-        let v = vec![
+    fn get_example_entry() -> Vec<&'static str> {
+        vec![
             "ID   001R_FRG3G              Reviewed;         256 AA.",
             "AC   P9WPY2; L0TBI1; P0A4Z2; P95014;",
             "DT   28-JUN-2011, integrated into UniProtKB/Swiss-Prot.",
@@ -387,126 +1390,490 @@ mod tests {
             "SQ   SEQUENCE   256 AA;  29735 MW;  B4840739BF7D4121 CRC64;",
             "     MAFSAEDVLK EYDRRRRMEA LLLSLYYPND RKLLDYKEWS PPRVQVECPK APVEWNNPPS",
             "     EKGLIVGHFS GIKYKGEKAQ ASEVDVNKMC CWVSKFKDAM RRYQGIQTCK IPGKVLSDLD",
-        ];
+        ]
+    }
+
+    #[test]
+    fn test_parse_id_line() {
+        let lines = get_example_entry();
+        let (name, status, length) = parse_id_line(lines[0]).unwrap();
 
-        _raw_str_to_strings(v)
+        assert_eq!(name, "001R_FRG3G");
+        assert_eq!(status, ReviewStatus::Reviewed);
+        assert_eq!(length, 256);
     }
 
     #[test]
-    fn test_parse_ac_number() {
-        let want = "P9WPY2";
-        let mut lines = get_example_entry();
-        let got = parse_ac_number(&mut lines).unwrap();
+    fn test_parse_id_line_unreviewed() {
+        let line = "ID   A0A023GPI8_CANAL        Unreviewed;       110 AA.";
+        let (name, status, length) = parse_id_line(line).unwrap();
 
-        assert_eq!(got, want);
+        assert_eq!(name, "A0A023GPI8_CANAL");
+        assert_eq!(status, ReviewStatus::Unreviewed);
+        assert_eq!(length, 110);
     }
 
     #[test]
-    fn test_parse_version() {
-        let want = "44";
-        let mut index = 0;
-        let mut lines = get_example_entry();
-        let got = parse_version(&mut lines, &mut index);
+    fn test_db_type() {
+        let data = get_example_entry().join("\n");
+        let entry = UniProtDATEntry::from_bytes(data.as_bytes()).unwrap();
+
+        assert_eq!(entry.db_type(None), "swissprot");
+        assert_eq!(entry.db_type(Some("trembl")), "trembl");
+        assert_eq!(entry.db_type(Some("custom")), "custom");
+
+        assert!(!entry.contradicts_db_type(None));
+        assert!(!entry.contradicts_db_type(Some("swissprot")));
+        assert!(entry.contradicts_db_type(Some("trembl")));
+        assert!(!entry.contradicts_db_type(Some("custom")));
+    }
+
+    #[test]
+    fn test_parse_accessions() {
+        let want = vec!["P9WPY2", "L0TBI1", "P0A4Z2", "P95014"];
+        let lines = get_example_entry();
+        let got = parse_accessions(lines[1]);
 
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn test_parse_dates() {
+        let lines = get_example_entry();
+        let got = parse_dates(&lines[2..5].join("\n")).unwrap();
+
+        assert_eq!(
+            got.integrated,
+            NaiveDate::from_ymd_opt(2011, 6, 28).unwrap()
+        );
+        assert_eq!(got.integrated_into, "UniProtKB/Swiss-Prot");
+        assert_eq!(got.sequence_version, 1);
+        assert_eq!(
+            got.entry_date,
+            NaiveDate::from_ymd_opt(2023, 11, 8).unwrap()
+        );
+        assert_eq!(got.entry_version, 44);
+    }
+
+    #[test]
+    fn test_parse_description() {
+        let lines = [
+            "DE   RecName: Full=Arginine biosynthesis bifunctional protein ArgJ {ECO:0000255|HAMAP-Rule:MF_01106};",
+            "DE   AltName: Allergen=Bla g 1;",
+            "DE   Includes:",
+            "DE     RecName: Full=Glutamate N-acetyltransferase {ECO:0000255|HAMAP-Rule:MF_01106};",
+            "DE              EC=2.3.1.35 {ECO:0000255|HAMAP-Rule:MF_01106};",
+            "DE     AltName: Full=Ornithine acetyltransferase;",
+            "DE              Short=OATase;",
+            "DE   Contains:",
+            "DE     RecName: Full=Arginine biosynthesis bifunctional protein ArgJ alpha chain;",
+            "DE              EC=2.3.1.1;",
+            "DE              EC=2.3.1.35;",
+            "DE   Flags: Precursor; Fragment;",
+        ];
+        let got = parse_description(&lines.join("\n"));
+
+        assert_eq!(
+            got.recommended.as_ref().unwrap().full,
+            "Arginine biosynthesis bifunctional protein ArgJ"
+        );
+        assert_eq!(got.allergen.as_deref(), Some("Bla g 1"));
+        assert_eq!(got.domains.len(), 1);
+        assert_eq!(got.domains[0].alternative[0].short, vec!["OATase"]);
+        assert_eq!(got.components.len(), 1);
+        assert_eq!(
+            got.resolve_name(),
+            Some("Arginine biosynthesis bifunctional protein ArgJ alpha chain")
+        );
+        assert_eq!(got.ec_numbers(), vec!["2.3.1.35", "2.3.1.1"]);
+        assert_eq!(got.flags, vec!["Precursor", "Fragment"]);
+        assert!(got.is_fragment());
+    }
+
+    #[test]
+    fn test_parse_description_submitted() {
+        let lines = [
+            "DE   SubName: Full=Uncharacterized protein {ECO:0000313|EMBL:AAA};",
+            "DE   SubName: Full=Putative kinase {ECO:0000313|EMBL:BBB};",
+            "DE            EC=2.7.11.1 {ECO:0000313|EMBL:BBB};",
+        ];
+        let got = parse_description(&lines.join("\n"));
+
+        assert!(got.recommended.is_none());
+        assert_eq!(got.submitted.len(), 2);
+        assert_eq!(got.resolve_name(), Some("Putative kinase"));
+        assert_eq!(got.ec_numbers(), vec!["2.7.11.1"]);
+    }
+
+    #[test]
+    fn test_parse_genes() {
+        let lines = [
+            "GN   Name=Jon99Cii {ECO:0000312|FlyBase:FBgn0001297}; Synonyms=SER1, SER5,",
+            "GN   Ser99Da {ECO:0000303|PubMed:1, ECO:0000303|PubMed:2}; ORFNames=CG7877;",
+            "GN   and",
+            "GN   Name=Jon99Ciii; OrderedLocusNames=At1g01010;",
+        ];
+        let got = parse_genes(&lines.join("\n"));
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].name.as_deref(), Some("Jon99Cii"));
+        assert_eq!(got[0].synonyms, vec!["SER1", "SER5", "Ser99Da"]);
+        assert_eq!(got[0].orf_names, vec!["CG7877"]);
+        assert_eq!(got[1].name.as_deref(), Some("Jon99Ciii"));
+        assert_eq!(got[1].ordered_locus_names, vec!["At1g01010"]);
+    }
+
     #[test]
     fn test_parse_taxon_id() {
-        let want = "654924";
-        let mut lines = get_example_entry();
-        let mut index: usize = 8;
-        let got = parse_taxon_id(&mut lines, &mut index);
+        let want = 654924;
+        let lines = get_example_entry();
+        let got = parse_taxon_id(line_content(lines[10])).unwrap();
 
         assert_eq!(got, want);
     }
 
     #[test]
-    fn test_parse_db_references() {
-        let want_go = vec![String::from("GO:0046782"), String::from("GO:0016743")];
-        let want_ip = vec![String::from("IPR007031"), String::from("IPR000308")];
-        let mut lines = get_example_entry();
-        let mut index: usize = 0;
-        let (got_go, got_ip) = parse_db_references(&mut lines, &mut index);
+    fn test_parse_taxon_id_with_metadata() {
+        let got = parse_taxon_id("NCBI_TaxID=9606 {ECO:0000313|EMBL:AAA};").unwrap();
+        assert_eq!(got, 9606);
+    }
+
+    #[test]
+    fn test_parse_organism_host() {
+        let lines = get_example_entry();
+        let got = parse_organism_host(lines[12]).unwrap();
+
+        assert_eq!(got.taxon_id, 8404);
+        assert_eq!(
+            got.name,
+            "Lithobates pipiens (Northern leopard frog) (Rana pipiens)"
+        );
+    }
+
+    #[test]
+    fn test_parse_protein_existence() {
+        let lines = get_example_entry();
+        let got = parse_protein_existence(lines[38]).unwrap();
 
-        assert_eq!(got_go, want_go);
-        assert_eq!(got_ip, want_ip);
+        assert_eq!(got, ProteinExistence::Predicted);
+        assert_eq!(got.level(), 4);
     }
 
     #[test]
     fn test_parse_db_reference_go() {
-        let want = vec![String::from("GO:0046782")];
-        let mut line =
-            String::from("GO; GO:0046782; P:regulation of viral transcription; IEA:InterPro.");
-        let mut target = Vec::new();
-        let mut _dummy = Vec::new();
-        parse_db_reference(&mut line, &mut target, &mut _dummy);
+        let got = parse_db_reference(
+            "GO; GO:0046782; P:regulation of viral transcription; IEA:InterPro.",
+        )
+        .unwrap();
+
+        assert_eq!(got.database, "GO");
+        assert_eq!(got.id, "GO:0046782");
+        assert_eq!(
+            got.properties,
+            vec!["P:regulation of viral transcription", "IEA:InterPro"]
+        );
+        assert!(got.isoform.is_none());
+    }
+
+    #[test]
+    fn test_parse_go_annotation() {
+        let reference = parse_db_reference(
+            "GO; GO:0016743; F:carboxyl- or carbamoyltransferase activity; IEA:UniProtKB-UniRule.",
+        )
+        .unwrap();
+        let got = parse_go_annotation(&reference).unwrap();
+
+        assert_eq!(got.id, "GO:0016743");
+        assert_eq!(got.aspect, GoAspect::MolecularFunction);
+        assert_eq!(got.term, "carboxyl- or carbamoyltransferase activity");
+        assert_eq!(got.evidence, "IEA");
+        assert_eq!(got.source, "UniProtKB-UniRule");
 
-        assert_eq!(target, want);
-        assert!(_dummy.is_empty());
+        let reference = parse_db_reference("GO; GO:0016743; carboxyl; IEA:InterPro.").unwrap();
+        assert!(parse_go_annotation(&reference).is_err());
     }
 
     #[test]
     fn test_parse_db_reference_ip() {
-        let want = vec![String::from("IPR007031")];
-        let mut line = String::from("InterPro; IPR007031; Poxvirus_VLTF3.");
-        let mut target = Vec::new();
-        let mut _dummy = Vec::new();
-        parse_db_reference(&mut line, &mut _dummy, &mut target);
+        let got = parse_db_reference("InterPro; IPR007031; Poxvirus_VLTF3.").unwrap();
+
+        assert_eq!(got.database, "InterPro");
+        assert_eq!(got.id, "IPR007031");
+    }
+
+    #[test]
+    fn test_parse_db_reference_isoform() {
+        let got = parse_db_reference("RefSeq; NP_001005484.1; NM_001005484.1. [Q8NH21-1]").unwrap();
+
+        assert_eq!(got.id, "NP_001005484.1");
+        assert_eq!(got.properties, vec!["NM_001005484.1"]);
+        assert_eq!(got.isoform.as_deref(), Some("Q8NH21-1"));
+    }
+
+    #[test]
+    fn test_parse_features() {
+        let lines = [
+            "FT   CHAIN           1..256",
+            "FT                   /note=\"Putative transcription factor 001R\"",
+            "FT                   /id=\"PRO_0000410512\"",
+            "FT   VARIANT         29",
+            "FT                   /note=\"L -> P (in a breast cancer sample; somatic",
+            "FT                   mutation)\"",
+            "FT                   /evidence=\"ECO:0000269|PubMed:16959974\"",
+        ];
+        let got = parse_features(&lines.join("\n")).unwrap();
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].key, "CHAIN");
+        assert_eq!(got[0].start(), Some(1));
+        assert_eq!(got[0].end(), Some(256));
+        assert_eq!(got[0].qualifier("id"), Some("PRO_0000410512"));
+        assert_eq!(got[1].start(), Some(29));
+        assert_eq!(got[1].end(), Some(29));
+        assert_eq!(
+            got[1].qualifier("note"),
+            Some("L -> P (in a breast cancer sample; somatic mutation)")
+        );
+        assert_eq!(
+            got[1].qualifier("evidence"),
+            Some("ECO:0000269|PubMed:16959974")
+        );
+    }
 
-        assert_eq!(target, want);
-        assert!(_dummy.is_empty());
+    #[test]
+    fn test_parse_sequence_info() {
+        let lines = get_example_entry();
+        let got = parse_sequence_info(lines[43]).unwrap();
+
+        assert_eq!(got.length, 256);
+        assert_eq!(got.molecular_weight, 29735);
+        assert_eq!(got.crc64, "B4840739BF7D4121");
     }
 
     #[test]
     fn test_parse_sequence() {
         let want = "MAFSAEDVLKEYDRRRRMEALLLSLYYPNDRKLLDYKEWSPPRVQVECPKAPVEWNNPPSEKGLIVGHFSGIKYKGEKAQASEVDVNKMCCWVSKFKDAMRRYQGIQTCKIPGKVLSDLD";
-        let mut lines = get_example_entry();
-        let got = parse_sequence(&mut lines, 0);
+        let lines = get_example_entry();
+        let got = parse_sequence(&lines[44..].join("\n"), 0);
         assert_eq!(got, want);
     }
 
     #[test]
-    fn test_read_until_metadata() {
-        let want = "Alanine racemase";
-        let mut line = String::from(format!(
-            "RecName: Full={want} {{ECO:0000255|HAMAP-Rule:MF_01201}};"
-        ));
-        let got = read_until_metadata(&mut line, ORGANISM_RECOMMENDED_NAME_PREFIX_LEN);
-        assert_eq!(got, want);
+    fn test_from_bytes() {
+        let data = get_example_entry().join("\n");
+        let entry = UniProtDATEntry::from_bytes(data.as_bytes()).unwrap();
+
+        assert_eq!(entry.accession_number(), "P9WPY2");
+        assert_eq!(entry.secondary_accessions().len(), 3);
+        assert_eq!(entry.version(), 44);
+        assert_eq!(entry.name(), "Putative transcription factor 001R");
+        assert!(entry.ec_numbers().is_empty());
+        assert_eq!(entry.genes()[0].orf_names, vec!["FV3-001R"]);
+        assert_eq!(
+            entry.organism().name,
+            "Frog virus 3 (isolate Goorha) (FV-3)"
+        );
+        assert_eq!(entry.organism().lineage.len(), 10);
+        assert_eq!(entry.organism().hosts.len(), 4);
+        assert_eq!(entry.taxon_id(), 654924);
+        assert_eq!(
+            entry.keywords(),
+            vec![
+                "Activator",
+                "Reference proteome",
+                "Transcription",
+                "Transcription regulation"
+            ]
+        );
+        assert_eq!(entry.go_references(), vec!["GO:0046782", "GO:0016743"]);
+        assert_eq!(entry.interpro_references(), vec!["IPR007031", "IPR000308"]);
+        assert_eq!(entry.db_reference_ids("Proteomes"), vec!["UP000008770"]);
+        assert_eq!(entry.features().len(), 1);
+        assert_eq!(entry.sequence_info().crc64, "B4840739BF7D4121");
+        assert_eq!(entry.crc64(), "B4840739BF7D4121");
+        assert_eq!(entry.molecular_weight(), 29735);
     }
 
     #[test]
-    fn test_read_until_metadata_with_bracket() {
-        let want = "Alanine racemase{text between brackets}";
-        let mut line = String::from(format!(
-            "RecName: Full={want} {{ECO:0000255|HAMAP-Rule:MF_01201}};"
-        ));
-        let target = read_until_metadata(&mut line, ORGANISM_RECOMMENDED_NAME_PREFIX_LEN);
-        assert_eq!(target, want);
+    fn test_from_bytes_at_sequence_check() {
+        // The example entry only contains the first 120 of 256 residues
+        let data = get_example_entry().join("\n");
+
+        assert!(UniProtDATEntry::from_bytes_at(data.as_bytes(), 0, SequenceCheck::Off).is_ok());
+        assert!(UniProtDATEntry::from_bytes_at(data.as_bytes(), 0, SequenceCheck::Warn).is_ok());
+
+        let error =
+            UniProtDATEntry::from_bytes_at(data.as_bytes(), 0, SequenceCheck::Error).unwrap_err();
+        let invalid = error.downcast_ref::<InvalidEntryError>().unwrap();
+        assert_eq!(invalid.accession(), Some("P9WPY2"));
+        assert_eq!(
+            format!("{:#}", error),
+            "Invalid DAT entry P9WPY2 at byte offset 0: Sequence has length 120, but 256 was declared"
+        );
     }
 
     #[test]
-    fn test_read_until_metadata_none() {
-        let want = "Recommended Name";
-        let mut line = String::from(format!("RecName: Full={want};"));
-        let target = read_until_metadata(&mut line, ORGANISM_RECOMMENDED_NAME_PREFIX_LEN);
-        assert_eq!(target, want);
+    fn test_from_bytes_missing_taxon_id() {
+        let data = get_example_entry()
+            .into_iter()
+            .filter(|l| !l.starts_with("OX"))
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        assert!(UniProtDATEntry::from_bytes(data.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_with_terminator() {
+        let data = format!("{}\n//\n", get_example_entry().join("\n"));
+        let entry = UniProtDATEntry::from_bytes(data.as_bytes()).unwrap();
+
+        assert_eq!(entry.sequence().len(), 120);
+    }
+
+    #[test]
+    fn test_from_lines() {
+        let lines: Vec<String> = get_example_entry()
+            .into_iter()
+            .map(|l| l.to_string())
+            .collect();
+        let entry = UniProtDATEntry::from_lines(&lines).unwrap();
+
+        assert_eq!(entry.accession_number(), "P9WPY2");
+        assert_eq!(entry.sequence().len(), 120);
+    }
+
+    /// Parse both versions of the conformance corpus, which contains the same entries in DAT and XML form
+    fn get_conformance_entries() -> (Vec<UniProtDATEntry>, Vec<UniProtDATEntry>) {
+        let dat = include_str!("../../testdata/conformance.dat");
+        let xml = include_str!("../../testdata/conformance.xml");
+
+        let dat_entries = SequentialDATParser::new(dat.as_bytes())
+            .map(|e| e.unwrap())
+            .collect();
+        let xml_entries = uniprot::uniprot::SequentialParser::new(xml.as_bytes())
+            .map(|e| UniProtDATEntry::from_xml(&e.unwrap()).unwrap())
+            .collect();
+
+        (dat_entries, xml_entries)
+    }
+
+    #[test]
+    fn test_from_xml() {
+        let (dat_entries, xml_entries) = get_conformance_entries();
+        assert_eq!(dat_entries.len(), 3);
+        assert_eq!(xml_entries.len(), 3);
+
+        for (dat, xml) in dat_entries.iter().zip(&xml_entries) {
+            assert_eq!(dat.entry_name(), xml.entry_name());
+            assert_eq!(dat.status(), xml.status());
+            assert_eq!(dat.accessions(), xml.accessions());
+            assert_eq!(dat.dates(), xml.dates());
+            assert_eq!(dat.description(), xml.description());
+            assert_eq!(dat.genes(), xml.genes());
+            assert_eq!(dat.keywords(), xml.keywords());
+            assert_eq!(dat.protein_existence(), xml.protein_existence());
+            assert_eq!(dat.go_annotations(), xml.go_annotations());
+            assert_eq!(dat.sequence_info(), xml.sequence_info());
+            assert_eq!(dat.sequence(), xml.sequence());
+            xml.validate_sequence().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_from_xml_missing_taxon() {
+        let xml = include_str!("../../testdata/conformance.xml").replacen(
+            r#"<dbReference type="NCBI Taxonomy" id="9606"/>"#,
+            "",
+            1,
+        );
+        let entry = uniprot::uniprot::SequentialParser::new(xml.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+
+        let err = UniProtDATEntry::from_xml(&entry).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Missing NCBI taxonomy reference in entry P00505"
+        );
+    }
+
+    #[test]
+    fn test_xml_conformance() {
+        let (dat_entries, xml_entries) = get_conformance_entries();
+        let options = OutputOptions {
+            xrefs: vec!["GO", "InterPro", "Pfam", "EMBL"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            go_evidence_column: true,
+            ..OutputOptions::default()
+        };
+
+        let write_tsv = |entries: &[UniProtDATEntry]| {
+            let mut output = Vec::new();
+            let mut sink = TsvSink::new(&mut output, &options).unwrap();
+            for entry in entries {
+                sink.write(&entry.record(&options)).unwrap();
+            }
+            Box::new(sink).finish().unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let dat_tsv = write_tsv(&dat_entries);
+        assert_eq!(dat_tsv, write_tsv(&xml_entries));
+
+        // EC numbers come from the protein names in both formats, including those of components
+        let lines: Vec<&str> = dat_tsv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].contains("\tRNA-directed RNA polymerase\t210\t3.4.22.28;2.7.7.48\t"));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let data = get_example_entry().join("\n");
+        let blocks: Vec<(&str, &str)> = Blocks::new(&data).collect();
+        let codes: Vec<&str> = blocks.iter().map(|(code, _)| *code).collect();
+
+        assert_eq!(
+            codes,
+            vec![
+                "ID", "AC", "DT", "DE", "GN", "OS", "OC", "OX", "OH", "RN", "RP", "RX", "RA", "RT",
+                "RL", "CC", "DR", "PE", "KW", "FT", "SQ", "  "
+            ]
+        );
+        assert_eq!(blocks[2].1.lines().count(), 3);
+        assert!(blocks[21].1.starts_with("     MAFSAEDVLK"));
+    }
+
+    #[test]
+    fn test_split_outside_braces() {
+        let got = split_outside_braces("A {ECO:1, ECO:2}, B", ',');
+        assert_eq!(got, vec!["A {ECO:1, ECO:2}", " B"]);
+    }
+
+    #[test]
+    fn test_strip_metadata() {
+        let want = "Alanine racemase";
+        let line = format!("{want} {{ECO:0000255|HAMAP-Rule:MF_01201}};");
+        let got = strip_metadata(&line);
+        assert_eq!(got, want);
     }
 
     #[test]
-    fn test_drain_spaces() {
-        let want = "ABC";
-        let mut line = String::from(format!("    {want}"));
-        drain_leading_spaces(&mut line);
-        assert_eq!(line, want);
+    fn test_strip_metadata_with_bracket() {
+        let want = "Alanine racemase{text between brackets}";
+        let line = format!("{want} {{ECO:0000255|HAMAP-Rule:MF_01201}};");
+        let got = strip_metadata(&line);
+        assert_eq!(got, want);
     }
 
     #[test]
-    fn test_drain_spaces_none() {
-        let want = "This has no leading spaces";
-        let mut line = String::from(want);
-        drain_leading_spaces(&mut line);
-        assert_eq!(line, want);
+    fn test_strip_metadata_none() {
+        let want = "Recommended Name";
+        let line = format!("{want};");
+        let got = strip_metadata(&line);
+        assert_eq!(got, want);
     }
 }
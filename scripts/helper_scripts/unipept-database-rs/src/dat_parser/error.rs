@@ -0,0 +1,109 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::Error;
+
+/// Error raised when a single entry in a DAT file can not be parsed
+/// The raw data is kept, so invalid entries can be written elsewhere and the rest of the file can still be parsed
+#[derive(Debug)]
+pub struct InvalidEntryError {
+    accession: Option<String>,
+    offset: u64,
+    data: Vec<u8>,
+    source: Error,
+}
+
+impl InvalidEntryError {
+    /// Create a new error for the entry that starts at byte `offset` of the input
+    /// `data` contains the raw entry, without its "//" terminator
+    pub fn new(offset: u64, mut data: Vec<u8>, source: Error) -> Self {
+        while data.last() == Some(&b'\n') {
+            data.pop();
+        }
+
+        Self {
+            accession: find_accession(&data),
+            offset,
+            data,
+            source,
+        }
+    }
+
+    /// The primary accession number of the entry, if it could be found
+    pub fn accession(&self) -> Option<&str> {
+        self.accession.as_deref()
+    }
+
+    /// The offset of the first byte of the entry in the input
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The raw data of the entry, without its "//" terminator
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Display for InvalidEntryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid DAT entry {} at byte offset {}",
+            self.accession.as_deref().unwrap_or("(unknown accession)"),
+            self.offset
+        )
+    }
+}
+
+impl std::error::Error for InvalidEntryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Look for the primary accession number in the raw data of an entry,
+/// without relying on the rest of the entry being valid
+pub(crate) fn find_accession(data: &[u8]) -> Option<String> {
+    let line = data
+        .split(|b| *b == b'\n')
+        .find(|line| line.starts_with(b"AC   "))?;
+    let accessions = String::from_utf8_lossy(&line[5..]);
+    let accession = accessions.split(';').next()?.trim();
+
+    if accession.is_empty() {
+        None
+    } else {
+        Some(accession.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_entry_error() {
+        let data =
+            b"ID   001R_FRG3G   Reviewed;   256 AA.\nAC   P9WPY2; L0TBI1;\nOX   NCBI_TaxID=X;\n";
+        let error = InvalidEntryError::new(1234, data.to_vec(), Error::msg("Invalid taxon id"));
+
+        assert_eq!(error.accession(), Some("P9WPY2"));
+        assert_eq!(error.offset(), 1234);
+        assert!(error.data().ends_with(b"NCBI_TaxID=X;"));
+        assert_eq!(
+            error.to_string(),
+            "Invalid DAT entry P9WPY2 at byte offset 1234"
+        );
+    }
+
+    #[test]
+    fn test_invalid_entry_error_without_accession() {
+        let error = InvalidEntryError::new(0, b"garbage".to_vec(), Error::msg("Missing ID line"));
+
+        assert_eq!(error.accession(), None);
+        assert_eq!(
+            error.to_string(),
+            "Invalid DAT entry (unknown accession) at byte offset 0"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::dat_parser::entry::UniProtDATEntry;
+
+/// Filters that entries have to pass to be written, evaluated while parsing
+/// Empty lists and missing values don't filter anything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryFilter {
+    /// Only keep entries that belong to one of these proteomes (DR Proteomes lines)
+    pub proteomes: HashSet<String>,
+    /// Only keep entries that have all of these keywords (KW lines), compared case-insensitively
+    pub keywords: Vec<String>,
+    /// Only keep entries with at most this protein existence level (PE line), 1 being the strongest evidence
+    /// Entries without a PE line (eg. from FASTA files) are dropped when this is set
+    pub max_protein_existence: Option<u8>,
+    /// Only keep entries with a sequence of at least this length
+    pub min_length: Option<usize>,
+    /// Drop entries that are flagged as fragments
+    pub exclude_fragments: bool,
+}
+
+impl EntryFilter {
+    pub fn matches(&self, entry: &UniProtDATEntry) -> bool {
+        if !self.proteomes.is_empty()
+            && !entry
+                .db_reference_ids("Proteomes")
+                .iter()
+                .any(|id| self.proteomes.contains(*id))
+        {
+            return false;
+        }
+
+        if !self
+            .keywords
+            .iter()
+            .all(|k| entry.keywords().iter().any(|x| x.eq_ignore_ascii_case(k)))
+        {
+            return false;
+        }
+
+        if let Some(max) = self.max_protein_existence {
+            if entry.protein_existence().is_none_or(|pe| pe.level() > max) {
+                return false;
+            }
+        }
+
+        if self
+            .min_length
+            .is_some_and(|min| entry.sequence().len() < min)
+        {
+            return false;
+        }
+
+        !(self.exclude_fragments && entry.description().is_fragment())
+    }
+}
+
+/// Command line flags for an `EntryFilter`, shared by the parsers of the DAT and the XML files
+#[derive(Args, Debug, Clone, Default)]
+pub struct FilterArgs {
+    /// Only keep entries that belong to one of these proteomes (eg. "UP000005640")
+    #[clap(long, value_delimiter = ',')]
+    proteome: Vec<String>,
+    /// File with proteome ids to keep, one per line, in addition to --proteome
+    #[clap(long)]
+    proteome_file: Option<PathBuf>,
+    /// Only keep entries with this keyword (eg. "Reference proteome"), can be given multiple times
+    #[clap(long)]
+    keyword: Vec<String>,
+    /// Only keep entries with at most this protein existence level, from 1 (evidence at protein level) to 5 (uncertain)
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=5))]
+    max_protein_existence: Option<u8>,
+    /// Only keep entries with a sequence of at least this length
+    #[clap(long)]
+    min_length: Option<usize>,
+    /// Drop entries that are flagged as fragments
+    #[clap(long, default_value_t = false)]
+    exclude_fragments: bool,
+}
+
+impl FilterArgs {
+    /// Create the filter, after reading the proteome file if one was given
+    pub fn into_filter(self) -> Result<EntryFilter> {
+        let mut proteomes: HashSet<String> = self.proteome.into_iter().collect();
+        if let Some(pb) = &self.proteome_file {
+            proteomes.extend(read_proteome_ids(pb)?);
+        }
+
+        Ok(EntryFilter {
+            proteomes,
+            keywords: self.keyword,
+            max_protein_existence: self.max_protein_existence,
+            min_length: self.min_length,
+            exclude_fragments: self.exclude_fragments,
+        })
+    }
+}
+
+/// Read a list of proteome ids, with one id per line
+pub fn read_proteome_ids(path: &Path) -> Result<Vec<String>> {
+    let content = read_to_string(path)
+        .with_context(|| format!("Unable to read proteome list \"{}\"", path.display()))?;
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_example_entry(pe: u8, length: usize, fragment: bool) -> UniProtDATEntry {
+        let flags = if fragment {
+            "\nDE   Flags: Fragment;"
+        } else {
+            ""
+        };
+        let data = format!(
+            "ID   TEST_HUMAN   Unreviewed;   {length} AA.
+AC   A0A000;
+DT   28-JUN-2011, integrated into UniProtKB/TrEMBL.
+DT   19-JUL-2004, sequence version 1.
+DT   08-NOV-2023, entry version 3.
+DE   SubName: Full=Protein;{flags}
+OX   NCBI_TaxID=9606;
+DR   Proteomes; UP000005640; Chromosome 1.
+PE   {pe}: Predicted;
+KW   Kinase; Reference proteome.
+SQ   SEQUENCE   {length} AA;  1 MW;  0000000000000000 CRC64;
+     {}",
+            "A".repeat(length)
+        );
+
+        UniProtDATEntry::from_bytes(data.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_empty_filter() {
+        let filter = EntryFilter::default();
+        assert!(filter.matches(&get_example_entry(5, 1, true)));
+    }
+
+    #[test]
+    fn test_proteome_and_keyword_filter() {
+        let entry = get_example_entry(1, 10, false);
+
+        let mut filter = EntryFilter {
+            proteomes: HashSet::from(["UP000005640".to_string(), "UP000000589".to_string()]),
+            keywords: vec!["reference proteome".to_string()],
+            ..EntryFilter::default()
+        };
+        assert!(filter.matches(&entry));
+
+        filter.keywords.push("Transport".to_string());
+        assert!(!filter.matches(&entry));
+
+        filter.keywords.clear();
+        filter.proteomes = HashSet::from(["UP000000589".to_string()]);
+        assert!(!filter.matches(&entry));
+    }
+
+    #[test]
+    fn test_sequence_filters() {
+        let filter = EntryFilter {
+            max_protein_existence: Some(3),
+            min_length: Some(5),
+            exclude_fragments: true,
+            ..EntryFilter::default()
+        };
+
+        assert!(filter.matches(&get_example_entry(3, 5, false)));
+        assert!(!filter.matches(&get_example_entry(4, 5, false)));
+        assert!(!filter.matches(&get_example_entry(3, 4, false)));
+        assert!(!filter.matches(&get_example_entry(3, 5, true)));
+    }
+
+    #[test]
+    fn test_filter_args() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            filter: FilterArgs,
+        }
+
+        let cli = <Cli as clap::Parser>::try_parse_from([
+            "parser",
+            "--proteome",
+            "UP000005640,UP000000589",
+            "--keyword",
+            "Reference proteome",
+            "--max-protein-existence",
+            "2",
+            "--exclude-fragments",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cli.filter.into_filter().unwrap(),
+            EntryFilter {
+                proteomes: HashSet::from(["UP000005640".to_string(), "UP000000589".to_string()]),
+                keywords: vec!["Reference proteome".to_string()],
+                max_protein_existence: Some(2),
+                exclude_fragments: true,
+                ..EntryFilter::default()
+            }
+        );
+        assert!(
+            <Cli as clap::Parser>::try_parse_from(["parser", "--max-protein-existence", "6"])
+                .is_err()
+        );
+    }
+}
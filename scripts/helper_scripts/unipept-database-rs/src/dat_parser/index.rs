@@ -0,0 +1,617 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error, Result};
+use crossbeam_channel::bounded;
+
+use crate::dat_parser::entry::UniProtDATEntry;
+use crate::dat_parser::error::find_accession;
+use crate::dat_parser::producer::{Producer, RawBatch};
+use crate::dat_parser::sequence_check::SequenceCheck;
+use crate::dat_parser::threaded_parser::{DEFAULT_BATCH_SIZE, DEFAULT_BUFFER_SIZE};
+use crate::utils::bgzf::{block_offsets, read_at, read_fully, BgzfReader, BlockOffset};
+use crate::utils::files::Compression;
+
+// Layout of the index file, all integers are little-endian:
+//   magic, format version (u32), amount of files (u32)
+//   for every file: path length (u32), path, amount of BGZF blocks (u64), blocks (2x u64)
+//     plain files have no blocks
+//   amount of records (u64), followed by the records, sorted by accession
+const MAGIC: &[u8; 8] = b"UPDATIDX";
+const FORMAT_VERSION: u32 = 1;
+
+/// UniProtKB accession numbers are either 6 or 10 characters long
+const ACCESSION_LEN: usize = 10;
+const RECORD_LEN: usize = ACCESSION_LEN + 2 + 8 + 4;
+
+/// Location of a single entry, as it is stored in the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexRecord {
+    /// The accession, padded with zeroes
+    accession: [u8; ACCESSION_LEN],
+    file: u16,
+    offset: u64,
+    length: u32,
+}
+
+impl IndexRecord {
+    fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let mut bytes = [0; RECORD_LEN];
+        bytes[..10].copy_from_slice(&self.accession);
+        bytes[10..12].copy_from_slice(&self.file.to_le_bytes());
+        bytes[12..20].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[20..].copy_from_slice(&self.length.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; RECORD_LEN]) -> Self {
+        Self {
+            accession: bytes[..10].try_into().unwrap(),
+            file: u16::from_le_bytes(bytes[10..12].try_into().unwrap()),
+            offset: u64::from_le_bytes(bytes[12..20].try_into().unwrap()),
+            length: u32::from_le_bytes(bytes[20..].try_into().unwrap()),
+        }
+    }
+}
+
+fn pad_accession(accession: &str) -> Result<[u8; ACCESSION_LEN]> {
+    let mut padded = [0; ACCESSION_LEN];
+    padded
+        .get_mut(..accession.len())
+        .with_context(|| format!("Accession \"{accession}\" is too long to be indexed"))?
+        .copy_from_slice(accession.as_bytes());
+    Ok(padded)
+}
+
+/// The location of an entry in one of the indexed files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub file: PathBuf,
+    /// Offset of the first byte of the entry in the (decompressed) file
+    pub offset: u64,
+    /// Length of the entry, including the "//" terminator
+    pub length: u32,
+}
+
+/// A DAT file that is part of an index
+struct IndexedFile {
+    path: PathBuf,
+    /// The blocks of a bgzip-compressed file, None for uncompressed files
+    blocks: Option<Vec<BlockOffset>>,
+}
+
+/// Amount of records that are sorted in memory before they are written to a temporary run
+/// Larger inputs are sorted by merging these runs, so memory use doesn't grow with the input
+const RUN_LEN: usize = 1 << 23;
+
+/// Create an index of the entries in `inputs`, which have to be uncompressed or compressed with bgzip
+/// Bgzip-compressed files are decompressed using `threads` threads
+/// Returns the amount of entries that were indexed
+pub fn build_index(inputs: &[PathBuf], output: &Path, threads: usize) -> Result<u64> {
+    build_index_with_run_len(inputs, output, threads, RUN_LEN)
+}
+
+fn build_index_with_run_len(
+    inputs: &[PathBuf],
+    output: &Path,
+    threads: usize,
+    run_len: usize,
+) -> Result<u64> {
+    if inputs.len() > u16::MAX as usize {
+        return Err(Error::msg("Too many input files for a single index"));
+    }
+
+    let mut files = Vec::with_capacity(inputs.len());
+    let mut sorter = RecordSorter::new(output, run_len);
+
+    for (i, input) in inputs.iter().enumerate() {
+        let file = index_file(input, i as u16, threads, &mut sorter)
+            .with_context(|| format!("Error indexing \"{}\"", input.display()))?;
+        files.push(file);
+    }
+
+    let count = sorter.count;
+    write_index(output, &files, sorter)
+        .with_context(|| format!("Error writing index \"{}\"", output.display()))?;
+
+    Ok(count)
+}
+
+/// Sorts index records by accession, using temporary files next to the index for inputs that don't fit in memory
+struct RecordSorter {
+    records: Vec<IndexRecord>,
+    run_len: usize,
+    /// Temporary files with sorted runs of records
+    runs: Vec<PathBuf>,
+    /// Prefix of the paths of the runs
+    prefix: PathBuf,
+    count: u64,
+}
+
+impl RecordSorter {
+    fn new(output: &Path, run_len: usize) -> Self {
+        Self {
+            records: Vec::new(),
+            run_len,
+            runs: Vec::new(),
+            prefix: output.to_path_buf(),
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, record: IndexRecord) -> Result<()> {
+        self.records.push(record);
+        self.count += 1;
+
+        if self.records.len() >= self.run_len {
+            self.write_run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Sort the records in memory and write them to a new run
+    fn write_run(&mut self) -> Result<()> {
+        self.records.sort_unstable_by_key(|r| r.accession);
+
+        let mut path = self.prefix.clone().into_os_string();
+        path.push(format!(".run{}", self.runs.len()));
+        let path = PathBuf::from(path);
+
+        let mut writer = BufWriter::new(
+            File::create(&path)
+                .with_context(|| format!("Failed to create \"{}\"", path.display()))?,
+        );
+        self.runs.push(path);
+
+        for record in self.records.drain(..) {
+            writer.write_all(&record.to_bytes())?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Write all records to `writer`, sorted by accession
+    fn write_sorted<W: Write>(mut self, writer: &mut W) -> Result<()> {
+        // Everything fit in memory, no need to merge runs
+        if self.runs.is_empty() {
+            self.records.sort_unstable_by_key(|r| r.accession);
+            for record in &self.records {
+                writer.write_all(&record.to_bytes())?;
+            }
+            return Ok(());
+        }
+
+        if !self.records.is_empty() {
+            self.write_run()?;
+        }
+
+        let mut readers = self
+            .runs
+            .iter()
+            .map(|path| File::open(path).map(BufReader::new))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        // The accession comes first in the bytes of a record, so they are ordered by accession
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (i, reader) in readers.iter_mut().enumerate() {
+            if let Some(bytes) = read_run_record(reader)? {
+                heap.push(Reverse((bytes, i)));
+            }
+        }
+
+        while let Some(Reverse((bytes, i))) = heap.pop() {
+            writer.write_all(&bytes)?;
+            if let Some(bytes) = read_run_record(&mut readers[i])? {
+                heap.push(Reverse((bytes, i)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for RecordSorter {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = std::fs::remove_file(run);
+        }
+    }
+}
+
+/// Read the next record of a run, or None at the end of the run
+fn read_run_record<R: Read>(reader: &mut R) -> Result<Option<[u8; RECORD_LEN]>> {
+    let mut bytes = [0; RECORD_LEN];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => Ok(Some(bytes)),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(Error::new(e).context("Error reading temporary run")),
+    }
+}
+
+/// Find the location of every entry in a single file, and add them to `sorter`
+fn index_file(
+    input: &Path,
+    file_index: u16,
+    threads: usize,
+    sorter: &mut RecordSorter,
+) -> Result<IndexedFile> {
+    // Store absolute paths, so the index can be used from any directory
+    let path = input.canonicalize()?;
+    let file = File::open(&path)?;
+
+    let (reader, blocks) = open_indexable(file, threads)?;
+
+    let (sender, receiver) = bounded::<RawBatch>(4);
+    let mut producer = Producer::new(reader);
+    producer.start(sender, None, DEFAULT_BATCH_SIZE, DEFAULT_BUFFER_SIZE);
+
+    for batch in receiver {
+        for raw in batch.entries {
+            let data = &batch.data[raw.range];
+            let accession = find_accession(data).with_context(|| {
+                format!("Missing accession in entry at byte offset {}", raw.offset)
+            })?;
+
+            if !raw.terminated {
+                return Err(Error::msg(format!(
+                    "Entry {accession} at byte offset {} has no \"//\" terminator",
+                    raw.offset
+                )));
+            }
+
+            // The record includes the "\n//" terminator
+            sorter.push(IndexRecord {
+                accession: pad_accession(&accession)?,
+                file: file_index,
+                offset: raw.offset,
+                length: (data.len() + 3) as u32,
+            })?;
+        }
+    }
+
+    producer.join()?;
+
+    Ok(IndexedFile { path, blocks })
+}
+
+/// A reader over the uncompressed data of a file, and the BGZF blocks of the file if it is compressed
+type IndexableReader = (Box<dyn BufRead + Send>, Option<Vec<BlockOffset>>);
+
+/// Create a reader for an uncompressed or bgzip-compressed file, along with its BGZF blocks
+/// Other compression formats don't support reading at random offsets
+fn open_indexable(mut file: File, threads: usize) -> Result<IndexableReader> {
+    let mut header = [0; 14];
+    let header_len = read_fully(&mut file, &mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    match Compression::detect(&header[..header_len]) {
+        Compression::None => Ok((Box::new(BufReader::new(file)), None)),
+        Compression::Bgzip => {
+            let blocks = block_offsets(&mut file)?;
+            file.seek(SeekFrom::Start(0))?;
+            let reader = BufReader::new(BgzfReader::new(file, threads));
+            Ok((Box::new(reader), Some(blocks)))
+        }
+        c => Err(Error::msg(format!(
+            "{c:?} files can't be read at random offsets, compress them with bgzip instead"
+        ))),
+    }
+}
+
+fn write_index(output: &Path, files: &[IndexedFile], records: RecordSorter) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(output)?);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&(files.len() as u32).to_le_bytes())?;
+
+    for file in files {
+        let path = file
+            .path
+            .to_str()
+            .context("File paths have to be valid UTF-8")?;
+        writer.write_all(&(path.len() as u32).to_le_bytes())?;
+        writer.write_all(path.as_bytes())?;
+
+        let blocks = file.blocks.as_deref().unwrap_or_default();
+        writer.write_all(&(blocks.len() as u64).to_le_bytes())?;
+        for block in blocks {
+            writer.write_all(&block.compressed.to_le_bytes())?;
+            writer.write_all(&block.uncompressed.to_le_bytes())?;
+        }
+    }
+
+    writer.write_all(&records.count.to_le_bytes())?;
+    records.write_sorted(&mut writer)?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// An index that maps accessions onto the location of their entry in one or more DAT files
+/// Only the file list is kept in memory, records are looked up with a binary search in the index file
+pub struct DatIndex {
+    index: File,
+    files: Vec<IndexedFile>,
+    records_offset: u64,
+    record_count: u64,
+}
+
+impl DatIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open index \"{}\"", path.display()))?;
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::msg(format!(
+                "\"{}\" is not a DAT index",
+                path.display()
+            )));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(Error::msg(format!(
+                "Unsupported index version {version}, expected {FORMAT_VERSION}"
+            )));
+        }
+
+        let file_count = read_u32(&mut reader)?;
+        let mut files = Vec::with_capacity(file_count as usize);
+
+        for _ in 0..file_count {
+            let mut path = vec![0; read_u32(&mut reader)? as usize];
+            reader.read_exact(&mut path)?;
+            let path = PathBuf::from(String::from_utf8(path).context("Invalid path in index")?);
+
+            let block_count = read_u64(&mut reader)?;
+            let blocks = if block_count == 0 {
+                None
+            } else {
+                let mut blocks = Vec::with_capacity(block_count as usize);
+                for _ in 0..block_count {
+                    blocks.push(BlockOffset {
+                        compressed: read_u64(&mut reader)?,
+                        uncompressed: read_u64(&mut reader)?,
+                    });
+                }
+                Some(blocks)
+            };
+
+            files.push(IndexedFile { path, blocks });
+        }
+
+        let record_count = read_u64(&mut reader)?;
+        let records_offset = reader.stream_position()?;
+
+        Ok(Self {
+            index: reader.into_inner(),
+            files,
+            records_offset,
+            record_count,
+        })
+    }
+
+    /// The amount of entries in the index
+    pub fn len(&self) -> u64 {
+        self.record_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.record_count == 0
+    }
+
+    /// Find the location of the entry with a primary accession number
+    pub fn find(&mut self, accession: &str) -> Result<Option<IndexEntry>> {
+        let Some(record) = self.find_record(accession)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(IndexEntry {
+            file: self.file(&record)?.path.clone(),
+            offset: record.offset,
+            length: record.length,
+        }))
+    }
+
+    /// Read the raw data of an entry, including its "//" terminator
+    pub fn fetch_raw(&mut self, accession: &str) -> Result<Option<Vec<u8>>> {
+        match self.find_record(accession)? {
+            Some(record) => self.read_data(&record).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read and parse an entry
+    pub fn fetch(&mut self, accession: &str) -> Result<Option<UniProtDATEntry>> {
+        let Some(record) = self.find_record(accession)? else {
+            return Ok(None);
+        };
+
+        let data = self.read_data(&record)?;
+        let data = data.strip_suffix(b"\n//").unwrap_or(&data);
+
+        UniProtDATEntry::from_bytes_at(data, record.offset, SequenceCheck::default()).map(Some)
+    }
+
+    /// Binary search for the record of an accession
+    fn find_record(&mut self, accession: &str) -> Result<Option<IndexRecord>> {
+        let Ok(key) = pad_accession(accession) else {
+            return Ok(None);
+        };
+
+        let (mut low, mut high) = (0, self.record_count);
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let record = self.read_record(middle)?;
+
+            match record.accession.cmp(&key) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return Ok(Some(record)),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn file(&self, record: &IndexRecord) -> Result<&IndexedFile> {
+        self.files
+            .get(record.file as usize)
+            .context("Invalid file in index record")
+    }
+
+    /// Read the data a record points to, decompressing only the blocks it is part of
+    fn read_data(&self, record: &IndexRecord) -> Result<Vec<u8>> {
+        let indexed = self.file(record)?;
+        let mut file = File::open(&indexed.path)
+            .with_context(|| format!("Failed to open \"{}\"", indexed.path.display()))?;
+
+        match &indexed.blocks {
+            Some(blocks) => read_at(&mut file, blocks, record.offset, record.length as usize),
+            None => {
+                let mut data = vec![0; record.length as usize];
+                file.seek(SeekFrom::Start(record.offset))?;
+                file.read_exact(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    fn read_record(&mut self, index: u64) -> Result<IndexRecord> {
+        let mut bytes = [0; RECORD_LEN];
+        self.index.seek(SeekFrom::Start(
+            self.records_offset + index * RECORD_LEN as u64,
+        ))?;
+        self.index
+            .read_exact(&mut bytes)
+            .context("Error reading index record")?;
+        Ok(IndexRecord::from_bytes(&bytes))
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader
+        .read_exact(&mut bytes)
+        .context("Unexpected end of index")?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader
+        .read_exact(&mut bytes)
+        .context("Unexpected end of index")?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::bgzf::compress;
+
+    fn get_example_input() -> String {
+        let mut input = String::new();
+
+        // Accessions are written in reverse, so they have to be sorted
+        for i in (0..300).rev() {
+            input.push_str(&format!(
+                "ID   TEST{i}_HUMAN   Reviewed;   {i} AA.
+AC   P{i:05}; Q{i:05};
+DT   28-JUN-2011, integrated into UniProtKB/Swiss-Prot.
+DT   19-JUL-2004, sequence version 1.
+DT   08-NOV-2023, entry version {i}.
+DE   RecName: Full=Protein {i};
+OX   NCBI_TaxID={i};
+PE   1: Evidence at protein level;
+SQ   SEQUENCE   {i} AA;  1 MW;  0000000000000000 CRC64;
+     {}
+//
+",
+                "A".repeat(i)
+            ));
+        }
+
+        input
+    }
+
+    fn test_index(name: &str, data: &[u8], run_len: usize) {
+        let dir = std::env::temp_dir().join(format!("unipept_test_index_{name}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.dat");
+        let output = dir.join("index.idx");
+        std::fs::write(&input, data).unwrap();
+
+        assert_eq!(
+            build_index_with_run_len(std::slice::from_ref(&input), &output, 2, run_len).unwrap(),
+            300
+        );
+
+        let mut index = DatIndex::open(&output).unwrap();
+        assert_eq!(index.len(), 300);
+
+        for i in [0, 1, 150, 299] {
+            let accession = format!("P{i:05}");
+            let raw = index.fetch_raw(&accession).unwrap().unwrap();
+            assert!(raw.starts_with(format!("ID   TEST{i}_HUMAN").as_bytes()));
+            assert!(raw.ends_with(b"\n//"));
+
+            let entry = index.fetch(&accession).unwrap().unwrap();
+            assert_eq!(entry.accession_number(), accession);
+            assert_eq!(entry.sequence().len(), i);
+        }
+
+        // Secondary accessions are not indexed
+        assert!(index.find("Q00001").unwrap().is_none());
+        assert!(index.find("P99999").unwrap().is_none());
+        assert!(index.find("A0A0A0A0A0A0").unwrap().is_none());
+
+        // The temporary runs are removed
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_plain() {
+        test_index("plain", get_example_input().as_bytes(), RUN_LEN);
+    }
+
+    #[test]
+    fn test_index_bgzip() {
+        test_index(
+            "bgzip",
+            &compress(get_example_input().as_bytes(), 500),
+            RUN_LEN,
+        );
+    }
+
+    #[test]
+    fn test_index_runs() {
+        // Sorted by merging runs that don't divide the amount of entries evenly
+        test_index("runs", get_example_input().as_bytes(), 7);
+    }
+
+    #[test]
+    fn test_record_bytes() {
+        let record = IndexRecord {
+            accession: pad_accession("A0A023GPI8").unwrap(),
+            file: 3,
+            offset: 1 << 40,
+            length: 12345,
+        };
+
+        assert_eq!(IndexRecord::from_bytes(&record.to_bytes()), record);
+        assert!(pad_accession("A0A023GPI8X").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{Context, Error, Result};
+
+use crate::dat_parser::entry::UniProtDATEntry;
+use crate::dat_parser::models::{Feature, IsoformSequence};
+
+/// Create an entry for every isoform of `entry` that is described by VAR_SEQ features
+/// The canonical ("Displayed") isoform is skipped, as well as isoforms with an external or unknown sequence
+/// Fails if the ALTERNATIVE PRODUCTS comment can't be parsed, every isoform can fail on its own as well
+pub fn expand_isoforms(
+    entry: &UniProtDATEntry,
+) -> Result<impl Iterator<Item = Result<UniProtDATEntry>> + '_> {
+    Ok(entry.isoforms()?.into_iter().filter_map(move |isoform| {
+        let IsoformSequence::Variations(variations) = &isoform.sequence else {
+            return None;
+        };
+        let accession = isoform.ids.first()?;
+
+        Some(
+            isoform_sequence(entry.sequence(), entry.features(), variations)
+                .map(|sequence| entry.isoform(accession, sequence))
+                .with_context(|| {
+                    format!(
+                        "Unable to create isoform {} of entry AC-{}",
+                        accession,
+                        entry.accession_number()
+                    )
+                }),
+        )
+    }))
+}
+
+/// Apply the VAR_SEQ features with the given ids (eg. "VSP_004370") to the canonical sequence
+pub fn isoform_sequence(
+    canonical: &str,
+    features: &[Feature],
+    variations: &[String],
+) -> Result<String> {
+    let mut changes = variations
+        .iter()
+        .map(|id| {
+            let feature = features
+                .iter()
+                .find(|f| f.key == "VAR_SEQ" && f.qualifier("id") == Some(id))
+                .with_context(|| format!("Missing VAR_SEQ feature {id}"))?;
+            parse_variation(feature)
+                .with_context(|| format!("Unable to parse VAR_SEQ feature {id}"))
+        })
+        .collect::<Result<Vec<Variation>>>()?;
+
+    // Apply the changes from back to front, so the positions of the remaining ones stay valid
+    changes.sort_by_key(|c| std::cmp::Reverse(c.start));
+
+    let mut sequence = canonical.to_string();
+    let mut previous_start = usize::MAX;
+
+    for change in changes {
+        if change.end >= previous_start {
+            return Err(Error::msg(format!(
+                "Overlapping VAR_SEQ features at position {}",
+                change.start
+            )));
+        }
+        previous_start = change.start;
+
+        let range = change.start - 1..change.end;
+        let original = canonical.get(range.clone()).with_context(|| {
+            format!(
+                "VAR_SEQ feature {}..{} is outside of the sequence",
+                change.start, change.end
+            )
+        })?;
+
+        if let Some(expected) = &change.original {
+            if expected != original {
+                return Err(Error::msg(format!(
+                    "VAR_SEQ feature expects \"{}\" at {}..{}, but the sequence contains \"{}\"",
+                    expected, change.start, change.end, original
+                )));
+            }
+        }
+
+        sequence.replace_range(range, &change.replacement);
+    }
+
+    Ok(sequence)
+}
+
+/// A single change to the canonical sequence, described by a VAR_SEQ feature
+struct Variation {
+    /// 1-based positions, both inclusive
+    start: usize,
+    end: usize,
+    /// The residues that are replaced, if the note mentions them
+    original: Option<String>,
+    replacement: String,
+}
+
+/// Parse a VAR_SEQ feature, whose note is either "Missing (in isoform 2)"
+/// or "MAS -> MNT (in isoform 2 and isoform 3)", see `Feature::sequence_change`
+fn parse_variation(feature: &Feature) -> Result<Variation> {
+    let start = feature.start().context("Unknown start position")?;
+    let end = feature.end().context("Unknown end position")?;
+    if start == 0 || end < start {
+        return Err(Error::msg(format!(
+            "Invalid location \"{}\"",
+            feature.location
+        )));
+    }
+
+    let change = feature
+        .sequence_change()
+        .with_context(|| format!("Unable to parse note of feature at {}", feature.location))?;
+    let replacement = change.replacements.into_iter().next().unwrap_or_default();
+    let original = change.original;
+
+    Ok(Variation {
+        start,
+        end,
+        original,
+        replacement,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = "ID   TEST_HUMAN   Reviewed;   12 AA.
+AC   P12345;
+DT   28-JUN-2011, integrated into UniProtKB/Swiss-Prot.
+DT   19-JUL-2004, sequence version 1.
+DT   08-NOV-2023, entry version 3.
+DE   RecName: Full=Protein;
+OX   NCBI_TaxID=9606;
+CC   -!- FUNCTION: Does things.
+CC   -!- ALTERNATIVE PRODUCTS:
+CC       Event=Alternative splicing; Named isoforms=4;
+CC       Name=1;
+CC         IsoId=P12345-1; Sequence=Displayed;
+CC       Name=2 {ECO:0000303|PubMed:1};
+CC         IsoId=P12345-2; Sequence=VSP_000001;
+CC       Name=3;
+CC         IsoId=P12345-3; Sequence=VSP_000001, VSP_000002;
+CC       Name=4;
+CC         IsoId=Q99999-1; Sequence=External;
+CC   -!- SIMILARITY: Belongs to a family.
+CC   ---------------------------------------------------------------------------
+DR   GO; GO:0005737; C:cytoplasm; IEA:UniProtKB-SubCell.
+PE   1: Evidence at protein level;
+FT   VAR_SEQ         1..3
+FT                   /note=\"Missing (in isoform 2 and isoform 3)\"
+FT                   /id=\"VSP_000001\"
+FT   VAR_SEQ         9..10
+FT                   /note=\"KL -> RRRRR (in isoform 3)\"
+FT                   /id=\"VSP_000002\"
+SQ   SEQUENCE   12 AA;  1 MW;  0000000000000000 CRC64;
+     MAFSAEDVKL YY";
+
+    #[test]
+    fn test_parse_isoforms() {
+        let entry = UniProtDATEntry::from_bytes(ENTRY.as_bytes()).unwrap();
+        let isoforms = entry.isoforms().unwrap();
+
+        assert_eq!(isoforms.len(), 4);
+        assert_eq!(isoforms[0].sequence, IsoformSequence::Displayed);
+        assert_eq!(isoforms[1].name, "2");
+        assert_eq!(isoforms[1].ids, vec!["P12345-2"]);
+        assert_eq!(
+            isoforms[2].sequence,
+            IsoformSequence::Variations(vec!["VSP_000001".to_string(), "VSP_000002".to_string()])
+        );
+        assert_eq!(isoforms[3].sequence, IsoformSequence::External);
+    }
+
+    #[test]
+    fn test_expand_isoforms() {
+        let entry = UniProtDATEntry::from_bytes(ENTRY.as_bytes()).unwrap();
+        let expanded: Vec<UniProtDATEntry> = expand_isoforms(&entry)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(expanded.len(), 2);
+        assert_eq!(expanded[0].accession_number(), "P12345-2");
+        assert_eq!(expanded[0].sequence(), "SAEDVKLYY");
+        assert_eq!(expanded[1].accession_number(), "P12345-3");
+        assert_eq!(expanded[1].sequence(), "SAEDVRRRRRYY");
+
+        // Isoforms inherit the taxon and annotations of the canonical entry
+        assert_eq!(expanded[1].taxon_id(), 9606);
+        assert_eq!(expanded[1].go_references(), vec!["GO:0005737"]);
+        assert_eq!(expanded[1].length(), 12);
+        assert!(expanded[1].validate_sequence().is_ok());
+    }
+
+    #[test]
+    fn test_malformed_alternative_products() {
+        // An IsoId before any Name doesn't invalidate the entry, only its isoforms
+        let data = ENTRY.replace(
+            "Named isoforms=4;",
+            "Named isoforms=4;\nCC         Comment=See also; IsoId=P12345-5;",
+        );
+        let entry = UniProtDATEntry::from_bytes(data.as_bytes()).unwrap();
+
+        assert!(entry.isoforms().is_err());
+        assert!(expand_isoforms(&entry).is_err());
+    }
+
+    #[test]
+    fn test_invalid_variation() {
+        let entry =
+            UniProtDATEntry::from_bytes(ENTRY.replace("KL -> ", "KV -> ").as_bytes()).unwrap();
+        let expanded: Vec<Result<UniProtDATEntry>> = expand_isoforms(&entry).unwrap().collect();
+
+        assert!(expanded[0].is_ok());
+        assert!(expanded[1].is_err());
+    }
+}
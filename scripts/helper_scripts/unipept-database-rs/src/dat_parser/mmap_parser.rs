@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+
+use anyhow::{Context, Error, Result};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use memchr::memmem;
+use memmap2::Mmap;
+
+use crate::dat_parser::entry::UniProtDATEntry;
+use crate::dat_parser::error::InvalidEntryError;
+use crate::dat_parser::sequence_check::SequenceCheck;
+
+/// Size of the byte ranges the input is split into by default
+/// Workers can't claim a range more than `RANGES_PER_THREAD` ranges per thread after the next one to yield,
+/// which bounds the parsed ranges that are buffered in ordered mode
+pub const DEFAULT_RANGE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Amount of ranges per thread that can be claimed before the next range to yield is finished
+const RANGES_PER_THREAD: usize = 2;
+
+/// Amount of entries a worker parses before sending them to the iterator
+const BATCH_SIZE: usize = 64;
+
+/// Parsed entries of a single range, along with their position in the input
+struct ParsedBatch {
+    range: usize,
+    batch: usize,
+    /// True if this is the last batch of its range
+    last: bool,
+    entries: Vec<Result<UniProtDATEntry>>,
+}
+
+/// A multi-threaded DAT parser for uncompressed files, which are memory-mapped
+/// The file is split into byte ranges that are aligned to the "\n//\n" terminators of the entries.
+/// Every worker claims the next range that hasn't been parsed yet and scans it for entries itself,
+/// so there is no single producer thread that reads the whole input.
+///
+/// Just like the `ThreadedDATParser`, entries are yielded in the order the workers finish them by default,
+/// and an ordered parser restores the order of the input.
+pub struct MmapDATParser {
+    data: Arc<Mmap>,
+    threads: usize,
+    range_size: usize,
+    ordered: bool,
+    sequence_check: SequenceCheck,
+    workers: Vec<JoinHandle<()>>,
+    r_parsed: Option<Receiver<ParsedBatch>>,
+    /// Permits for the workers to claim another range, one is returned for every range that is finished
+    s_permits: Option<Sender<()>>,
+    started: bool,
+    finished: bool,
+    // The range and batch that have to be yielded next in ordered mode
+    next_batch: (usize, usize),
+    pending: HashMap<(usize, usize), ParsedBatch>,
+    current: std::vec::IntoIter<Result<UniProtDATEntry>>,
+}
+
+impl MmapDATParser {
+    /// Memory-map `file` and parse it with `threads` worker threads
+    /// Passing 0 as the amount of threads uses the amount of (virtual) CPUs available in your machine
+    pub fn new(file: &File, threads: usize) -> Result<Self> {
+        Self::with_order(file, threads, false)
+    }
+
+    /// Create a new MmapDATParser that yields entries in the same order as they appear in the input
+    pub fn ordered(file: &File, threads: usize) -> Result<Self> {
+        Self::with_order(file, threads, true)
+    }
+
+    /// Validate the sequence of every entry against its SQ line
+    pub fn with_sequence_check(mut self, check: SequenceCheck) -> Self {
+        self.sequence_check = check;
+        self
+    }
+
+    /// Split the input into ranges of about `range_size` bytes, with at least one range per thread
+    pub fn with_range_size(mut self, range_size: usize) -> Self {
+        self.range_size = range_size.max(1);
+        self
+    }
+
+    fn with_order(file: &File, threads: usize, ordered: bool) -> Result<Self> {
+        // SAFETY: the file must not be modified while it is being parsed,
+        // which is the same assumption every other parser makes about its input
+        let data = unsafe { Mmap::map(file) }.context("Failed to memory-map input file")?;
+
+        Ok(Self {
+            data: Arc::new(data),
+            threads: if threads == 0 {
+                num_cpus::get()
+            } else {
+                threads
+            },
+            range_size: DEFAULT_RANGE_SIZE,
+            ordered,
+            sequence_check: SequenceCheck::default(),
+            workers: Vec::new(),
+            r_parsed: None,
+            s_permits: None,
+            started: false,
+            finished: false,
+            next_batch: (0, 0),
+            pending: HashMap::new(),
+            current: Vec::new().into_iter(),
+        })
+    }
+
+    /// Split the input into ranges and launch the workers, which claim the ranges in order
+    fn start(&mut self) {
+        let count = self.threads.max(self.data.len().div_ceil(self.range_size));
+        let ranges = Arc::new(split_ranges(&self.data, count));
+        let next_range = Arc::new(AtomicUsize::new(0));
+
+        let (s_parsed, r_parsed) = bounded::<ParsedBatch>(self.threads * 2);
+        let (s_permits, r_permits) = unbounded::<()>();
+        for _ in 0..self.threads * RANGES_PER_THREAD {
+            let _ = s_permits.send(());
+        }
+
+        for _ in 0..self.threads {
+            let data = Arc::clone(&self.data);
+            let ranges = Arc::clone(&ranges);
+            let next_range = Arc::clone(&next_range);
+            let sender = s_parsed.clone();
+            let permits = r_permits.clone();
+            let check = self.sequence_check;
+
+            // The permits only disconnect when the parser was dropped
+            self.workers.push(thread::spawn(move || loop {
+                if permits.recv().is_err() {
+                    break;
+                }
+
+                let index = next_range.fetch_add(1, Ordering::Relaxed);
+                let Some(range) = ranges.get(index) else {
+                    break;
+                };
+
+                // The receiver only disconnects when the parser was dropped,
+                // in which case there is no use in parsing any further
+                if parse_range(&data, index, range.clone(), check, &sender).is_err() {
+                    break;
+                }
+            }));
+        }
+
+        self.r_parsed = Some(r_parsed);
+        self.s_permits = Some(s_permits);
+        self.started = true;
+    }
+
+    /// Wait for all workers to finish
+    fn join(&mut self) -> Result<()> {
+        for worker in self.workers.drain(..) {
+            worker
+                .join()
+                .map_err(|_| Error::msg("Worker thread panicked"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Continue with the entries of a batch, and remember which batch comes after it in the input
+    /// The last batch of a range allows the workers to claim another range
+    fn take_batch(&mut self, batch: ParsedBatch) {
+        self.next_batch = if batch.last {
+            if let Some(permits) = &self.s_permits {
+                let _ = permits.send(());
+            }
+            (batch.range + 1, 0)
+        } else {
+            (batch.range, batch.batch + 1)
+        };
+        self.current = batch.entries.into_iter();
+    }
+}
+
+impl Iterator for MmapDATParser {
+    type Item = Result<UniProtDATEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.start();
+        }
+
+        loop {
+            if let Some(entry) = self.current.next() {
+                return Some(entry);
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            // The next batch might have been parsed already
+            if let Some(batch) = self.pending.remove(&self.next_batch) {
+                self.take_batch(batch);
+                continue;
+            }
+
+            let receiver = self.r_parsed.as_ref()?;
+
+            match receiver.recv() {
+                Ok(batch) => {
+                    if !self.ordered || (batch.range, batch.batch) == self.next_batch {
+                        self.take_batch(batch);
+                    } else {
+                        self.pending.insert((batch.range, batch.batch), batch);
+                    }
+                }
+                // The channel disconnects once all workers are done
+                Err(_) => {
+                    self.finished = true;
+                    return self.join().err().map(Err);
+                }
+            }
+        }
+    }
+}
+
+/// Split `data` into at most `count` ranges of about the same size,
+/// which all start at the beginning of an entry
+fn split_ranges(data: &[u8], count: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::with_capacity(count);
+    let mut start = 0;
+
+    for i in 1..=count {
+        if start >= data.len() {
+            break;
+        }
+
+        let target = (data.len() / count * i).max(start);
+        let end = if i == count {
+            data.len()
+        } else {
+            // Move the end of the range to right after the next terminator
+            memmem::find(&data[target..], b"\n//\n").map_or(data.len(), |pos| target + pos + 4)
+        };
+
+        ranges.push(start..end);
+        start = end;
+    }
+
+    ranges
+}
+
+/// Parse all entries in a range, and send them in batches
+/// Fails if the receiver disconnected
+fn parse_range(
+    data: &[u8],
+    index: usize,
+    range: Range<usize>,
+    check: SequenceCheck,
+    sender: &Sender<ParsedBatch>,
+) -> Result<(), ()> {
+    let mut batch = 0;
+    let mut entries = Vec::with_capacity(BATCH_SIZE);
+    let mut start = range.start;
+
+    while start < range.end {
+        let entry = match memmem::find(&data[start..range.end], b"\n//") {
+            Some(pos) => {
+                let entry =
+                    UniProtDATEntry::from_bytes_at(&data[start..start + pos], start as u64, check);
+
+                // Skip the terminator and the newline after it
+                start = (start + pos + 4).min(range.end);
+                entry
+            }
+            // Anything but whitespace after the last terminator is an incomplete entry
+            None if data[start..range.end]
+                .iter()
+                .all(|b| b.is_ascii_whitespace()) =>
+            {
+                break
+            }
+            None => {
+                let entry = Err(Error::new(InvalidEntryError::new(
+                    start as u64,
+                    data[start..range.end].to_vec(),
+                    Error::msg("Reached the end of the input before the \"//\" terminator"),
+                )));
+                start = range.end;
+                entry
+            }
+        };
+
+        entries.push(entry);
+
+        if entries.len() == BATCH_SIZE && start < range.end {
+            let parsed = ParsedBatch {
+                range: index,
+                batch,
+                last: false,
+                entries: std::mem::replace(&mut entries, Vec::with_capacity(BATCH_SIZE)),
+            };
+            sender.send(parsed).map_err(|_| ())?;
+            batch += 1;
+        }
+    }
+
+    // Always send a final batch, even if it is empty, so the iterator knows the range is done
+    sender
+        .send(ParsedBatch {
+            range: index,
+            batch,
+            last: true,
+            entries,
+        })
+        .map_err(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::{Cursor, Write};
+
+    use super::*;
+    use crate::dat_parser::sequential_parser::SequentialDATParser;
+
+    fn get_example_input(entries: usize) -> Vec<u8> {
+        let mut input = String::new();
+
+        for i in 0..entries {
+            let length = 10 + (i * 7919) % 3000;
+            input.push_str(&format!(
+                "ID   TEST{i}_HUMAN   Reviewed;   {length} AA.
+AC   P{i:05};
+DT   28-JUN-2011, integrated into UniProtKB/Swiss-Prot.
+DT   19-JUL-2004, sequence version 1.
+DT   08-NOV-2023, entry version {i}.
+DE   RecName: Full=Protein {i};
+OX   NCBI_TaxID={i};
+PE   1: Evidence at protein level;
+SQ   SEQUENCE   {length} AA;  1 MW;  0000000000000000 CRC64;
+     {}
+//
+",
+                "A".repeat(length)
+            ));
+        }
+
+        input.into_bytes()
+    }
+
+    fn temp_file(name: &str, data: &[u8]) -> File {
+        let path = std::env::temp_dir().join(format!(
+            "unipept-mmap-parser-{}-{name}.dat",
+            std::process::id()
+        ));
+        File::create(&path).unwrap().write_all(data).unwrap();
+
+        let file = File::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_split_ranges() {
+        let input = get_example_input(50);
+        let ranges = split_ranges(&input, 7);
+
+        assert!(!ranges.is_empty() && ranges.len() <= 7);
+        assert_eq!(ranges[0].start, 0);
+        assert_eq!(ranges.last().unwrap().end, input.len());
+
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert!(input[..pair[0].end].ends_with(b"\n//\n"));
+        }
+
+        // More ranges than entries
+        assert_eq!(split_ranges(&input[..200], 10).len(), 1);
+    }
+
+    #[test]
+    fn test_ordered() {
+        let input = get_example_input(500);
+        let file = temp_file("ordered", &input);
+
+        let want: Vec<String> = SequentialDATParser::new(Cursor::new(input))
+            .map(|e| e.unwrap().accession_number().to_string())
+            .collect();
+
+        for (threads, range_size) in [(2, DEFAULT_RANGE_SIZE), (3, 4096), (8, 1)] {
+            let got: Vec<String> = MmapDATParser::ordered(&file, threads)
+                .unwrap()
+                .with_range_size(range_size)
+                .map(|e| e.unwrap().accession_number().to_string())
+                .collect();
+
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn test_claim_window() {
+        let input = get_example_input(500);
+        let file = temp_file("window", &input);
+
+        // Ranges that wait for a slower one never exceed the permits of the workers
+        let mut parser = MmapDATParser::ordered(&file, 3)
+            .unwrap()
+            .with_range_size(4096);
+
+        let mut count = 0;
+        while let Some(entry) = parser.next() {
+            entry.unwrap();
+            let ranges: HashSet<usize> = parser.pending.keys().map(|&(range, _)| range).collect();
+            assert!(ranges.len() < 3 * RANGES_PER_THREAD);
+            count += 1;
+        }
+        assert_eq!(count, 500);
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let mut input = get_example_input(3);
+        let valid_length = input.len();
+
+        // Entry without a taxon id, followed by an unterminated entry
+        input.extend_from_slice(b"ID   BAD_HUMAN   Reviewed;   1 AA.\nAC   P99999;\n//\n");
+        input.extend_from_slice(b"ID   TRUNCATED");
+        let file = temp_file("invalid", &input);
+
+        let results: Vec<Result<UniProtDATEntry>> = MmapDATParser::new(&file, 2)
+            .unwrap()
+            .with_range_size(100)
+            .collect();
+        assert_eq!(results.len(), 5);
+
+        let mut errors: Vec<&InvalidEntryError> = results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .map(|e| e.downcast_ref::<InvalidEntryError>().unwrap())
+            .collect();
+        errors.sort_by_key(|e| e.offset());
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].accession(), Some("P99999"));
+        assert_eq!(errors[0].offset(), valid_length as u64);
+        assert_eq!(errors[1].accession(), None);
+        assert_eq!(errors[1].data(), b"ID   TRUNCATED");
+    }
+}
@@ -1,25 +1,69 @@
 use anyhow::Result;
 use std::io::BufRead;
+use std::path::PathBuf;
 
 mod consumer;
+pub mod diff;
 pub mod entry;
+pub mod error;
+pub mod filter;
+pub mod index;
+pub mod isoforms;
+pub mod mmap_parser;
+pub mod models;
 mod producer;
+pub mod sequence_check;
 pub mod sequential_parser;
+pub mod sink;
 pub mod threaded_parser;
 pub mod utils;
+pub mod variants;
 
 use self::entry::UniProtDATEntry;
+use self::mmap_parser::MmapDATParser;
+use self::sequence_check::SequenceCheck;
 use self::sequential_parser::SequentialDATParser;
 use self::threaded_parser::ThreadedDATParser;
+use crate::utils::files::{open_input, open_mappable};
 
 /// Create a SequentialParser or ThreadedParser based on the amount of threads passed
+/// If `ordered` is true, entries are always yielded in the same order as they appear in the input
+/// Sequences are validated against their SQ line according to `check`
 pub fn uniprot_dat_parser<B: BufRead + Send + 'static>(
     reader: B,
     threads: usize,
+    ordered: bool,
+    check: SequenceCheck,
 ) -> Box<dyn Iterator<Item = Result<UniProtDATEntry>>> {
     if threads == 1 {
-        Box::new(SequentialDATParser::new(reader))
+        Box::new(SequentialDATParser::new(reader).with_sequence_check(check))
+    } else if ordered {
+        Box::new(ThreadedDATParser::ordered(reader, threads).with_sequence_check(check))
     } else {
-        Box::new(ThreadedDATParser::new(reader, threads))
+        Box::new(ThreadedDATParser::new(reader, threads).with_sequence_check(check))
     }
 }
+
+/// Create a parser for a DAT file, or stdin if no file is given
+/// Uncompressed regular files are memory-mapped and split between the threads by an `MmapDATParser`,
+/// everything else is read through `open_input` and parsed by `uniprot_dat_parser`
+pub fn uniprot_dat_file_parser(
+    input: Option<&PathBuf>,
+    threads: usize,
+    ordered: bool,
+    check: SequenceCheck,
+) -> Result<Box<dyn Iterator<Item = Result<UniProtDATEntry>>>> {
+    if let (Some(pb), true) = (input, threads != 1) {
+        if let Some(file) = open_mappable(pb)? {
+            let parser = if ordered {
+                MmapDATParser::ordered(&file, threads)?
+            } else {
+                MmapDATParser::new(&file, threads)?
+            };
+            return Ok(Box::new(parser.with_sequence_check(check)));
+        }
+    }
+
+    let reader = open_input(input, threads)?;
+    Ok(uniprot_dat_parser(reader, threads, ordered, check))
+}
@@ -0,0 +1,388 @@
+use chrono::NaiveDate;
+use strum_macros::{Display, EnumString};
+
+/// Review status of an entry, as found on the ID line
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+pub enum ReviewStatus {
+    #[strum(serialize = "Reviewed")]
+    Reviewed,
+    #[strum(serialize = "Unreviewed")]
+    Unreviewed,
+}
+
+impl ReviewStatus {
+    /// The UniProtKB section entries with this status belong to, as used in the "Status" column
+    pub fn db_type(&self) -> &'static str {
+        match self {
+            ReviewStatus::Reviewed => "swissprot",
+            ReviewStatus::Unreviewed => "trembl",
+        }
+    }
+
+    /// Find the status that belongs to a UniProtKB section, if it is a known one
+    pub fn from_db_type(db_type: &str) -> Option<Self> {
+        match db_type {
+            "swissprot" => Some(ReviewStatus::Reviewed),
+            "trembl" => Some(ReviewStatus::Unreviewed),
+            _ => None,
+        }
+    }
+}
+
+/// Creation and modification dates of an entry (DT lines)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryDates {
+    /// Date this entry was integrated into UniProtKB
+    pub integrated: NaiveDate,
+    /// The section of UniProtKB this entry was integrated into, eg. "UniProtKB/Swiss-Prot"
+    pub integrated_into: String,
+    pub sequence_date: NaiveDate,
+    pub sequence_version: u32,
+    pub entry_date: NaiveDate,
+    pub entry_version: u32,
+}
+
+/// A single full name for a protein, along with its short names and EC numbers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProteinName {
+    pub full: String,
+    pub short: Vec<String>,
+    pub ec_numbers: Vec<String>,
+}
+
+/// All names of a protein (DE lines)
+/// Components ("Contains:") and domains ("Includes:") are described recursively
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProteinDescription {
+    pub recommended: Option<ProteinName>,
+    pub alternative: Vec<ProteinName>,
+    pub submitted: Vec<ProteinName>,
+    pub allergen: Option<String>,
+    pub biotech: Option<String>,
+    pub cd_antigens: Vec<String>,
+    pub inns: Vec<String>,
+    pub domains: Vec<ProteinDescription>,
+    pub components: Vec<ProteinDescription>,
+    /// Flags such as "Precursor" or "Fragment", only present on the top level
+    pub flags: Vec<String>,
+}
+
+impl ProteinDescription {
+    /// Iterate over all names in this description (excluding domains and components),
+    /// in the order they appear in the DAT file
+    pub fn names(&self) -> impl Iterator<Item = &ProteinName> {
+        self.recommended
+            .iter()
+            .chain(self.alternative.iter())
+            .chain(self.submitted.iter())
+    }
+
+    /// Resolve the name to use for this protein
+    /// In order of preference:
+    /// - Last recommended name of protein components
+    /// - Last recommended name of protein domains
+    /// - Recommended name of protein itself
+    /// - Last submitted name of protein components
+    /// - Last submitted name of protein domains
+    /// - Submitted name of protein itself
+    pub fn resolve_name(&self) -> Option<&str> {
+        fn recommended(d: &ProteinDescription) -> Option<&str> {
+            d.recommended.as_ref().map(|n| n.full.as_str())
+        }
+
+        fn submitted(d: &ProteinDescription) -> Option<&str> {
+            d.submitted.last().map(|n| n.full.as_str())
+        }
+
+        self.components
+            .iter()
+            .rev()
+            .find_map(recommended)
+            .or_else(|| self.domains.iter().rev().find_map(recommended))
+            .or_else(|| recommended(self))
+            .or_else(|| self.components.iter().rev().find_map(submitted))
+            .or_else(|| self.domains.iter().rev().find_map(submitted))
+            .or_else(|| submitted(self))
+    }
+
+    /// All unique EC numbers found in this description, including its domains and components,
+    /// in the order they appear in the DAT file
+    pub fn ec_numbers(&self) -> Vec<&str> {
+        let mut result: Vec<&str> = Vec::new();
+        self.collect_ec_numbers(&mut result);
+        result
+    }
+
+    fn collect_ec_numbers<'a>(&'a self, target: &mut Vec<&'a str>) {
+        for name in self.names() {
+            for ec in &name.ec_numbers {
+                // EC numbers sometimes appear multiple times, only keep the first one
+                if !target.contains(&ec.as_str()) {
+                    target.push(ec);
+                }
+            }
+        }
+
+        // Domains ("Includes:") always come before components ("Contains:")
+        for child in self.domains.iter().chain(self.components.iter()) {
+            child.collect_ec_numbers(target);
+        }
+    }
+
+    /// Check if this protein is flagged as a fragment
+    pub fn is_fragment(&self) -> bool {
+        self.flags
+            .iter()
+            .any(|f| f == "Fragment" || f == "Fragments")
+    }
+}
+
+/// A single gene (GN lines), multiple genes are separated by "and" lines
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Gene {
+    pub name: Option<String>,
+    pub synonyms: Vec<String>,
+    pub ordered_locus_names: Vec<String>,
+    pub orf_names: Vec<String>,
+}
+
+/// A host organism of a virus (OH lines)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrganismHost {
+    pub taxon_id: u32,
+    pub name: String,
+}
+
+/// Information about the source organism of an entry (OS, OG, OC, OX and OH lines)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Organism {
+    pub name: String,
+    pub organelles: Vec<String>,
+    pub lineage: Vec<String>,
+    pub taxon_id: u32,
+    pub hosts: Vec<OrganismHost>,
+}
+
+/// Level of evidence for the existence of a protein (PE line)
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display)]
+pub enum ProteinExistence {
+    #[strum(serialize = "Evidence at protein level")]
+    ProteinLevel = 1,
+    #[strum(serialize = "Evidence at transcript level")]
+    TranscriptLevel = 2,
+    #[strum(serialize = "Inferred from homology")]
+    Homology = 3,
+    #[strum(serialize = "Predicted")]
+    Predicted = 4,
+    #[strum(serialize = "Uncertain")]
+    Uncertain = 5,
+}
+
+impl ProteinExistence {
+    pub fn from_level(level: u8) -> Option<Self> {
+        match level {
+            1 => Some(Self::ProteinLevel),
+            2 => Some(Self::TranscriptLevel),
+            3 => Some(Self::Homology),
+            4 => Some(Self::Predicted),
+            5 => Some(Self::Uncertain),
+            _ => None,
+        }
+    }
+
+    pub fn level(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// A cross-reference to an external database (DR line)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbReference {
+    pub database: String,
+    pub id: String,
+    /// All remaining fields, their meaning depends on the database
+    pub properties: Vec<String>,
+    /// The isoform this reference applies to, if it only applies to one
+    pub isoform: Option<String>,
+}
+
+/// The ontology a GO term belongs to
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display)]
+pub enum GoAspect {
+    #[strum(serialize = "P")]
+    BiologicalProcess,
+    #[strum(serialize = "F")]
+    MolecularFunction,
+    #[strum(serialize = "C")]
+    CellularComponent,
+}
+
+impl GoAspect {
+    /// Find the aspect for its one-letter code, as used in front of GO term names
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "P" => Some(GoAspect::BiologicalProcess),
+            "F" => Some(GoAspect::MolecularFunction),
+            "C" => Some(GoAspect::CellularComponent),
+            _ => None,
+        }
+    }
+}
+
+/// A GO annotation, parsed out of a GO cross-reference
+/// eg. "GO; GO:0046782; P:regulation of viral transcription; IEA:InterPro."
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoAnnotation {
+    pub id: String,
+    pub aspect: GoAspect,
+    pub term: String,
+    /// The GO evidence code, eg. "IEA" or "IDA"
+    pub evidence: String,
+    /// The source of the annotation, eg. "InterPro" or "UniProtKB"
+    pub source: String,
+}
+
+/// Translate an Evidence and Conclusion Ontology (ECO) code, as used in the UniProtKB XML files,
+/// into the GO evidence code that is used in the DAT files
+pub fn go_evidence_from_eco(eco: &str) -> Option<&'static str> {
+    let code = match eco {
+        "ECO:0000269" => "EXP",
+        "ECO:0000314" => "IDA",
+        "ECO:0000353" => "IPI",
+        "ECO:0000315" => "IMP",
+        "ECO:0000316" => "IGI",
+        "ECO:0000270" => "IEP",
+        "ECO:0006056" => "HTP",
+        "ECO:0007005" => "HDA",
+        "ECO:0007001" => "HMP",
+        "ECO:0007003" => "HGI",
+        "ECO:0007007" => "HEP",
+        "ECO:0000250" => "ISS",
+        "ECO:0000266" => "ISO",
+        "ECO:0000247" => "ISA",
+        "ECO:0000255" => "ISM",
+        "ECO:0000317" => "IGC",
+        "ECO:0000318" => "IBA",
+        "ECO:0000319" => "IBD",
+        "ECO:0000320" => "IKR",
+        "ECO:0000321" => "IRD",
+        "ECO:0000245" => "RCA",
+        "ECO:0000304" => "TAS",
+        "ECO:0000303" => "NAS",
+        "ECO:0000305" => "IC",
+        "ECO:0000307" => "ND",
+        "ECO:0000501" | "ECO:0000256" | "ECO:0000265" | "ECO:0007669" => "IEA",
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// A sequence annotation (FT lines)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub key: String,
+    /// The raw location, eg. "1..256", "<1..?", "45" or "P12345-2:10..20"
+    pub location: String,
+    pub qualifiers: Vec<(String, String)>,
+}
+
+impl Feature {
+    /// Find the value of the first qualifier with a given name
+    pub fn qualifier(&self, name: &str) -> Option<&str> {
+        self.qualifiers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The first residue of this feature (1-based), if it is known
+    pub fn start(&self) -> Option<usize> {
+        let location = self.local_location()?;
+        let start = location.split("..").next()?;
+        parse_position(start)
+    }
+
+    /// The last residue of this feature (1-based), if it is known
+    pub fn end(&self) -> Option<usize> {
+        let location = self.local_location()?;
+        let end = location.split("..").last()?;
+        parse_position(end)
+    }
+
+    /// The change to the sequence described by the note of a VAR_SEQ, VARIANT, CONFLICT or MUTAGEN feature,
+    /// eg. "Missing (in isoform 2)", "V -> A (in dbSNP:rs123)" or "K->A,R: Loss of activity."
+    pub fn sequence_change(&self) -> Option<SequenceChange> {
+        let note = self.qualifier("note")?;
+        let change = note.split([':', '(']).next()?.trim();
+
+        // Long sequences span multiple lines, which are joined with spaces
+        let residues = |s: &str| s.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+
+        if change == "Missing" {
+            return Some(SequenceChange {
+                original: None,
+                replacements: vec![String::new()],
+            });
+        }
+
+        let (original, replacements) = change.split_once("->")?;
+        Some(SequenceChange {
+            original: Some(residues(original)),
+            replacements: replacements.split(',').map(residues).collect(),
+        })
+    }
+
+    /// The location, if it is not located on a different entry
+    fn local_location(&self) -> Option<&str> {
+        if self.location.contains(':') {
+            None
+        } else {
+            Some(&self.location)
+        }
+    }
+}
+
+/// A change to the residues at the location of a feature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceChange {
+    /// The residues that are replaced, or `None` if they are "Missing"
+    pub original: Option<String>,
+    /// One or more alternatives for the original residues, which are empty if they are missing
+    pub replacements: Vec<String>,
+}
+
+/// Parse a single position of a feature location, which can be uncertain ("?", "?12")
+/// or extend beyond the sequence ("<1", ">256")
+fn parse_position(position: &str) -> Option<usize> {
+    position.trim_start_matches(['<', '>']).parse().ok()
+}
+
+/// A named isoform, as described in the ALTERNATIVE PRODUCTS comment (CC lines)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Isoform {
+    pub name: String,
+    /// Isoform identifiers, eg. "P12345-2"
+    pub ids: Vec<String>,
+    pub sequence: IsoformSequence,
+}
+
+/// How the sequence of an isoform is described
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IsoformSequence {
+    /// The canonical sequence of the entry
+    Displayed,
+    /// The sequence is the canonical sequence of another entry
+    External,
+    NotDescribed,
+    /// The canonical sequence, with these VAR_SEQ features applied (eg. "VSP_004370")
+    Variations(Vec<String>),
+}
+
+/// Metadata about the sequence (SQ line)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SequenceInfo {
+    pub length: u32,
+    pub molecular_weight: u32,
+    pub crc64: String,
+}
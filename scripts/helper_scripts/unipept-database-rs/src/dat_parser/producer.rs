@@ -1,12 +1,42 @@
-use crossbeam_channel::Sender;
-use std::io::BufRead;
+use anyhow::{Context, Error, Result};
+use crossbeam_channel::{Receiver, Sender};
+use std::io::{BufRead, ErrorKind};
+use std::ops::Range;
 use std::thread;
 use std::thread::JoinHandle;
 
+/// A single entry in the data of a `RawBatch`
+pub struct RawEntry {
+    /// The offset of the first byte of this entry in the input
+    pub offset: u64,
+    /// The bytes of this entry in the data of its batch, without the "\n//" terminator
+    pub range: Range<usize>,
+    /// False if the input ended before the terminator of this entry was found
+    pub terminated: bool,
+}
+
+/// Consecutive entries of the input, which share a single buffer
+pub struct RawBatch {
+    /// The index of this batch in the input, so the original order can be restored
+    pub index: usize,
+    pub data: Vec<u8>,
+    pub entries: Vec<RawEntry>,
+}
+
+impl RawBatch {
+    fn new(index: usize, batch_size: usize) -> Self {
+        Self {
+            index,
+            data: Vec::new(),
+            entries: Vec::with_capacity(batch_size),
+        }
+    }
+}
+
 /// Struct that divides input data from `reader` up into separate chunks and sends them to worker threads
 pub struct Producer<B: BufRead + Send + 'static> {
     reader: Option<B>,
-    handle: Option<JoinHandle<()>>,
+    handle: Option<JoinHandle<Result<()>>>,
 }
 
 impl<B: BufRead + Send + 'static> Producer<B> {
@@ -17,20 +47,49 @@ impl<B: BufRead + Send + 'static> Producer<B> {
         }
     }
 
-    #[allow(clippy::comparison_chain)]
-    pub fn start(&mut self, sender: Sender<Vec<u8>>) {
+    /// Read the input in blocks of `buffer_size` bytes, and send the entries in batches of `batch_size`
+    /// If `permits` is given, a permit is taken from it before every batch is sent
+    pub fn start(
+        &mut self,
+        sender: Sender<RawBatch>,
+        permits: Option<Receiver<()>>,
+        batch_size: usize,
+        buffer_size: usize,
+    ) {
         let mut reader = self.reader.take().unwrap();
 
-        // Read batches of input data into a buffer, divide those into chunks
-        // and send completed chunks to a worker thread
+        // Read blocks of input data into a buffer, divide those into chunks
+        // and send batches of completed chunks to a worker thread
         self.handle = Some(thread::spawn(move || {
-            let mut buffer: [u8; 64 * 1024] = [0; 64 * 1024];
+            // The receivers only disconnect when the parser was dropped,
+            // in which case there is no use in reading any further
+            let send = |batch: RawBatch| {
+                permits.as_ref().is_none_or(|p| p.recv().is_ok()) && sender.send(batch).is_ok()
+            };
+
+            let mut buffer = vec![0; buffer_size];
 
             // Backup buffer is of variable size because we don't know how big an entry can get
             let mut backup_buffer = Vec::<u8>::new();
+            let mut batch = RawBatch::new(0, batch_size);
+
+            // Offsets in the input of the first byte in the buffer, and the first byte of the current chunk
+            let mut buffer_offset: u64 = 0;
+            let mut chunk_offset: u64 = 0;
+
+            // Set when a terminator was the last byte of the buffer, so its newline is in the next one
+            let mut skip_newline = false;
 
             loop {
-                let bytes_read = reader.read(&mut buffer).unwrap();
+                let bytes_read = match reader.read(&mut buffer) {
+                    Ok(n) => n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        return Err(Error::new(e).context(format!(
+                            "Error reading input at byte offset {buffer_offset}"
+                        )))
+                    }
+                };
 
                 // Reached EOF
                 if bytes_read == 0 {
@@ -39,45 +98,61 @@ impl<B: BufRead + Send + 'static> Producer<B> {
 
                 let mut start_index = 0;
 
+                if skip_newline {
+                    skip_newline = false;
+                    if buffer[0] == b'\n' {
+                        start_index = 1;
+                    }
+                    chunk_offset = buffer_offset + start_index as u64;
+                }
+
                 for i in 0..bytes_read {
                     // We found a slash: check if it is preceded by another slash and a newline
                     if buffer[i] == b'/' {
                         let backup_buffer_size = backup_buffer.len();
 
-                        // A slash is never in the beginning of the file, so we can safely look into the backup buffer
-                        // and assume it is not empty: if i == 0, then something must be in the backup buffer
-                        let previous_char = if i > 0 {
-                            buffer[i - 1]
-                        } else {
-                            backup_buffer[backup_buffer_size - 1]
-                        };
-
-                        let second_previous_char = if i > 1 {
-                            buffer[i - 2]
-                        } else if i == 1 {
-                            backup_buffer[backup_buffer_size - 1]
-                        } else {
-                            backup_buffer[backup_buffer_size - 2]
+                        // Look back into the backup buffer if the previous characters weren't in this buffer
+                        let char_before = |n: usize| {
+                            if i >= n {
+                                Some(buffer[i - n])
+                            } else {
+                                backup_buffer
+                                    .len()
+                                    .checked_sub(n - i)
+                                    .map(|idx| backup_buffer[idx])
+                            }
                         };
 
                         // Found a separator for a chunk!
-                        // Send it to the receivers
-                        if previous_char == b'/' && second_previous_char == b'\n' {
-                            let mut data = Vec::<u8>::with_capacity(
-                                backup_buffer_size + (i + 1 - start_index),
-                            );
+                        // Add it to the batch, and send the batch to the receivers once it is full
+                        if char_before(1) == Some(b'/') && char_before(2) == Some(b'\n') {
+                            let start = batch.data.len();
 
                             // Start out with backup buffer contents if they exist
                             if backup_buffer_size != 0 {
-                                data.extend_from_slice(&backup_buffer[..backup_buffer_size]);
+                                batch.data.extend_from_slice(&backup_buffer);
                                 backup_buffer.clear();
                             }
 
-                            data.extend_from_slice(&buffer[start_index..=i]);
-                            sender.send(data).unwrap();
+                            batch.data.extend_from_slice(&buffer[start_index..=i]);
+
+                            // Cut out the \n// at the end
+                            batch.entries.push(RawEntry {
+                                offset: chunk_offset,
+                                range: start..batch.data.len() - 3,
+                                terminated: true,
+                            });
+
+                            if batch.entries.len() >= batch_size {
+                                let next = RawBatch::new(batch.index + 1, batch_size);
+                                if !send(std::mem::replace(&mut batch, next)) {
+                                    return Ok(());
+                                }
+                            }
 
                             // The next chunk will start at offset i+2 because we skip the next newline as well
                             start_index = i + 2;
+                            chunk_offset = buffer_offset + start_index as u64;
                         }
                     }
                 }
@@ -86,15 +161,95 @@ impl<B: BufRead + Send + 'static> Producer<B> {
                 // If there is still some data left, copy the rest over into a different buffer for later
                 if start_index < bytes_read {
                     backup_buffer.extend_from_slice(&buffer[start_index..bytes_read]);
+                } else if start_index > bytes_read {
+                    skip_newline = true;
                 }
+
+                buffer_offset += bytes_read as u64;
+            }
+
+            // Anything but whitespace after the last terminator is an incomplete entry
+            if !backup_buffer.iter().all(|b| b.is_ascii_whitespace()) {
+                let start = batch.data.len();
+                batch.data.extend_from_slice(&backup_buffer);
+                batch.entries.push(RawEntry {
+                    offset: chunk_offset,
+                    range: start..batch.data.len(),
+                    terminated: false,
+                });
+            }
+
+            if !batch.entries.is_empty() {
+                // Nothing to do if the receivers disconnected, same as above
+                send(batch);
             }
+
+            Ok(())
         }));
     }
 
-    pub fn join(&mut self) {
+    /// Wait for the producer to finish, returning the error that stopped it if there was one
+    pub fn join(&mut self) -> Result<()> {
         if let Some(h) = self.handle.take() {
-            h.join().unwrap();
-            self.handle = None;
+            h.join()
+                .map_err(|_| Error::msg("Producer thread panicked"))?
+                .context("Error in producer thread")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use std::io::Cursor;
+
+    const ENTRIES: [&str; 3] = [
+        "ID   FIRST\nAC   P1;",
+        "ID   SECOND\nAC   P2;",
+        "ID   THIRD",
+    ];
+
+    fn produce(input: &[u8], buffer_size: usize) -> Vec<(u64, Vec<u8>, bool)> {
+        let (sender, receiver) = unbounded();
+        let mut producer = Producer::new(Cursor::new(input.to_vec()));
+        producer.start(sender, None, 2, buffer_size);
+
+        let entries = receiver
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .entries
+                    .into_iter()
+                    .map(|e| (e.offset, batch.data[e.range].to_vec(), e.terminated))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        producer.join().unwrap();
+        entries
+    }
+
+    #[test]
+    fn test_buffer_boundaries() {
+        let input = format!("{}\n//\n{}\n//\n{}", ENTRIES[0], ENTRIES[1], ENTRIES[2]);
+        let first_terminator = ENTRIES[0].len() + 3;
+
+        // Includes a buffer that ends exactly at the first terminator, before its newline
+        for buffer_size in (1..=input.len()).chain([first_terminator]) {
+            let entries = produce(input.as_bytes(), buffer_size);
+            assert_eq!(entries.len(), 3, "buffer size {buffer_size}");
+
+            for (i, (offset, data, terminated)) in entries.into_iter().enumerate() {
+                assert_eq!(data, ENTRIES[i].as_bytes(), "buffer size {buffer_size}");
+                assert_eq!(
+                    &input.as_bytes()[offset as usize..offset as usize + data.len()],
+                    data
+                );
+                assert_eq!(terminated, i < 2);
+            }
         }
     }
 }
@@ -0,0 +1,188 @@
+use anyhow::{Error, Result};
+use lazy_static::lazy_static;
+use strum_macros::{Display, EnumString};
+
+use crate::dat_parser::entry::UniProtDATEntry;
+
+/// Reversed ISO 3309 polynomial, as used for the CRC64 checksums on the SQ line
+const CRC64_POLYNOMIAL: u64 = 0xD800_0000_0000_0000;
+
+lazy_static! {
+    static ref CRC64_TABLE: [u64; 256] = {
+        let mut table = [0; 256];
+
+        for (i, value) in table.iter_mut().enumerate() {
+            let mut part = i as u64;
+            for _ in 0..8 {
+                part = if part & 1 == 1 {
+                    (part >> 1) ^ CRC64_POLYNOMIAL
+                } else {
+                    part >> 1
+                };
+            }
+            *value = part;
+        }
+
+        table
+    };
+}
+
+/// Calculate the CRC64 checksum of a sequence, formatted like on the SQ line
+pub fn crc64(sequence: &str) -> String {
+    let crc = sequence.bytes().fold(0u64, |crc, b| {
+        CRC64_TABLE[((crc ^ b as u64) & 0xff) as usize] ^ (crc >> 8)
+    });
+
+    format!("{crc:016X}")
+}
+
+/// Average mass of water, which is added once to the masses of the residues
+const WATER_MASS: f64 = 18.01524;
+
+/// Average mass of an amino acid residue, as used for the molecular weight on the SQ line
+/// The ambiguous B, Z and J are the average of the residues they stand for, X is ignored
+fn residue_mass(residue: u8) -> f64 {
+    match residue {
+        b'A' => 71.0788,
+        b'R' => 156.1875,
+        b'N' => 114.1038,
+        b'D' => 115.0886,
+        b'C' => 103.1388,
+        b'E' => 129.1155,
+        b'Q' => 128.1307,
+        b'G' => 57.0519,
+        b'H' => 137.1411,
+        b'I' | b'L' | b'J' => 113.1594,
+        b'K' => 128.1741,
+        b'M' => 131.1926,
+        b'F' => 147.1766,
+        b'P' => 97.1167,
+        b'S' => 87.0782,
+        b'T' => 101.1051,
+        b'W' => 186.2132,
+        b'Y' => 163.1760,
+        b'V' => 99.1326,
+        b'U' => 150.0388,
+        b'O' => 237.3018,
+        b'B' => 114.5962,
+        b'Z' => 128.6231,
+        _ => 0.0,
+    }
+}
+
+/// Calculate the molecular weight of a sequence in Dalton, rounded like on the SQ line
+pub fn molecular_weight(sequence: &str) -> u32 {
+    if sequence.is_empty() {
+        return 0;
+    }
+
+    let mass: f64 = sequence.bytes().map(residue_mass).sum();
+    (mass + WATER_MASS).round() as u32
+}
+
+/// What to do when a sequence doesn't match the length and checksum declared on its SQ line
+/// This usually means that the input was truncated or corrupted
+/// The library defaults to `Off`, so that parsing records that were already checked (eg. index lookups) stays cheap,
+/// while the dat-parser binary defaults to `Error` because it reads untrusted downloads
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Display, EnumString)]
+pub enum SequenceCheck {
+    /// Don't validate sequences
+    #[default]
+    #[strum(serialize = "off")]
+    Off,
+    /// Log a warning, but keep the entry
+    #[strum(serialize = "warn")]
+    Warn,
+    /// Treat the entry as invalid
+    #[strum(serialize = "error")]
+    Error,
+}
+
+impl SequenceCheck {
+    /// Validate the sequence of a parsed entry
+    pub fn apply(&self, entry: UniProtDATEntry) -> Result<UniProtDATEntry> {
+        if *self == SequenceCheck::Off {
+            return Ok(entry);
+        }
+
+        match entry.validate_sequence() {
+            Ok(()) => Ok(entry),
+            Err(e) if *self == SequenceCheck::Warn => {
+                eprintln!(
+                    "Warning: entry AC-{} has an invalid sequence: {}",
+                    entry.accession_number(),
+                    e
+                );
+                Ok(entry)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Compare a sequence to the length and CRC64 checksum that were declared for it
+pub fn validate_sequence(sequence: &str, length: u32, checksum: &str) -> Result<()> {
+    if sequence.len() != length as usize {
+        return Err(Error::msg(format!(
+            "Sequence has length {}, but {} was declared",
+            sequence.len(),
+            length
+        )));
+    }
+
+    let actual = crc64(sequence);
+    if !actual.eq_ignore_ascii_case(checksum) {
+        return Err(Error::msg(format!(
+            "Sequence has CRC64 checksum {actual}, but {checksum} was declared"
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEQUENCE: &str = "MAFSAEDVLKEYDRRRRMEALLLSLYYPNDRKLLDYKEWSPPRVQVECPKAPVEWNNPPSEKGLIVGHFSGIKYKGEKAQASEVDVNKMCCWVSKFKDAMRRYQGIQTCKIPGKVLSDLDAKIKAYNLTVEGVEGFVRYSRVTKQHVAAFLKELRHSKQYENVNLIHYILTDKRVDIQHLEKDLVKDFKALVESAHRMRQGHMINVKYILYQLLKKHGHGPDGPDILTVKTGSKGVLYDDSFRKIYTDLGWKFTPL";
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(SEQUENCE), "B4840739BF7D4121");
+        assert_eq!(crc64(""), "0000000000000000");
+    }
+
+    #[test]
+    fn test_molecular_weight() {
+        assert_eq!(molecular_weight(SEQUENCE), 29735);
+        assert_eq!(molecular_weight(""), 0);
+    }
+
+    #[test]
+    fn test_validate_sequence() {
+        assert!(validate_sequence(SEQUENCE, 256, "B4840739BF7D4121").is_ok());
+        assert!(validate_sequence(SEQUENCE, 256, "b4840739bf7d4121").is_ok());
+
+        let truncated = &SEQUENCE[..120];
+        assert_eq!(
+            validate_sequence(truncated, 256, "B4840739BF7D4121")
+                .unwrap_err()
+                .to_string(),
+            "Sequence has length 120, but 256 was declared"
+        );
+
+        let mut mutated = SEQUENCE.to_string();
+        mutated.replace_range(0..1, "A");
+        assert!(validate_sequence(&mutated, 256, "B4840739BF7D4121").is_err());
+    }
+
+    #[test]
+    fn test_parse_sequence_check() {
+        assert_eq!(
+            "warn".parse::<SequenceCheck>().unwrap(),
+            SequenceCheck::Warn
+        );
+        assert_eq!(SequenceCheck::default(), SequenceCheck::Off);
+        assert!("sometimes".parse::<SequenceCheck>().is_err());
+    }
+}
@@ -1,40 +1,81 @@
-use std::io::{BufRead, Lines};
+use std::io::BufRead;
 
 use anyhow::{Error, Result};
 
 use crate::dat_parser::entry::UniProtDATEntry;
+use crate::dat_parser::error::InvalidEntryError;
+use crate::dat_parser::sequence_check::SequenceCheck;
 
 /// A simple single-threaded DAT parser
 pub struct SequentialDATParser<B: BufRead> {
-    lines: Lines<B>,
-    data: Vec<String>,
+    reader: B,
+    data: Vec<u8>,
+    // Offset in the input of the first byte of the next entry
+    offset: u64,
+    sequence_check: SequenceCheck,
 }
 
 impl<B: BufRead> SequentialDATParser<B> {
     pub fn new(reader: B) -> Self {
         Self {
-            lines: reader.lines(),
+            reader,
             data: Vec::new(),
+            offset: 0,
+            sequence_check: SequenceCheck::default(),
         }
     }
+
+    /// Validate the sequence of every entry against its SQ line
+    pub fn with_sequence_check(mut self, check: SequenceCheck) -> Self {
+        self.sequence_check = check;
+        self
+    }
 }
 
 impl<B: BufRead> Iterator for SequentialDATParser<B> {
     type Item = Result<UniProtDATEntry>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // A loop combined with Lines.next() is an alternative for a for-loop over Lines,
-        // as that takes ownership of the Lines variable (which we don't want/can't do)
+        // The buffer is reused for every entry, so lines are never allocated separately
+        self.data.clear();
+
         loop {
-            match self.lines.next() {
-                None => return None,
-                Some(Err(e)) => return Some(Err(Error::new(e).context("Error reading line"))),
-                Some(Ok(line)) if line == "//" => {
-                    let entry = UniProtDATEntry::from_lines(&mut self.data);
-                    self.data.clear();
-                    return Some(entry);
+            let line_start = self.data.len();
+
+            match self.reader.read_until(b'\n', &mut self.data) {
+                Err(e) => {
+                    let offset = self.offset + line_start as u64;
+                    return Some(Err(Error::new(e)
+                        .context(format!("Error reading line at byte offset {offset}"))));
+                }
+                // Anything but whitespace after the last terminator is an incomplete entry
+                Ok(0) if self.data.iter().all(|b| b.is_ascii_whitespace()) => return None,
+                Ok(0) => {
+                    let offset = self.offset;
+                    self.offset += self.data.len() as u64;
+
+                    return Some(Err(Error::new(InvalidEntryError::new(
+                        offset,
+                        std::mem::take(&mut self.data),
+                        Error::msg("Reached the end of the input before the \"//\" terminator"),
+                    ))));
+                }
+                Ok(_) => {
+                    let line = &self.data[line_start..];
+                    let line = line.strip_suffix(b"\n").unwrap_or(line);
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+                    if line == b"//" {
+                        let offset = self.offset;
+                        self.offset += self.data.len() as u64;
+
+                        return Some(UniProtDATEntry::from_bytes_at(
+                            &self.data[..line_start],
+                            offset,
+                            self.sequence_check,
+                        ));
+                    }
                 }
-                Some(Ok(line)) => self.data.push(line),
             }
         }
     }
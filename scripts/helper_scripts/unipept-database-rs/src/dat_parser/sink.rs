@@ -0,0 +1,548 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use arrow_array::builder::{ListBuilder, StringBuilder, UInt32Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use serde::Serialize;
+use strum_macros::{Display, EnumString};
+
+use crate::dat_parser::utils::{extra_xrefs, header_columns, OutputOptions};
+
+/// Amount of entries that are buffered before they are written as one Arrow record batch
+const BATCH_SIZE: usize = 8192;
+
+/// A single output row, containing the values of every column for one entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntryRecord<'a> {
+    pub accession: &'a str,
+    pub sequence: &'a str,
+    pub name: &'a str,
+    pub version: u32,
+    pub ec_numbers: Vec<&'a str>,
+    pub go_ids: Vec<&'a str>,
+    pub interpro_ids: Vec<&'a str>,
+    pub status: &'a str,
+    pub taxon_id: Option<u32>,
+    /// The ids for every database in `extra_xrefs(&options.xrefs)`, in the same order
+    #[serde(skip)]
+    pub extra_xrefs: Vec<Vec<&'a str>>,
+    /// The evidence codes of the GO annotations in `go_ids`, if they were requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub go_evidence: Option<Vec<&'a str>>,
+    /// The sequence variants, if they were requested (eg. "VARIANT:12:V>A")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variants: Option<Vec<String>>,
+}
+
+/// The formats entries can be written in
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+pub enum OutputFormat {
+    /// Tab-separated values with a header line, multiple values in a column are separated by ";"
+    #[strum(serialize = "tsv")]
+    Tsv,
+    /// One JSON object per line
+    #[strum(serialize = "jsonl")]
+    JsonLines,
+    /// An Apache Arrow IPC file
+    #[strum(serialize = "arrow")]
+    Arrow,
+    /// An Apache Parquet file
+    #[strum(serialize = "parquet")]
+    Parquet,
+}
+
+/// Destination for parsed entries
+pub trait EntrySink {
+    fn write(&mut self, record: &EntryRecord) -> Result<()>;
+
+    /// Write everything that is still buffered, this has to be called after the last entry
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Create a sink that writes entries in `format` to a file, or stdout if no file is given
+/// The columns are determined by `options`, the same way as for the TSV header
+pub fn open_sink(
+    format: OutputFormat,
+    output: Option<&PathBuf>,
+    options: &OutputOptions,
+) -> Result<Box<dyn EntrySink>> {
+    let file = match output {
+        Some(pb) => Some(BufWriter::new(File::create(pb).with_context(|| {
+            format!("Failed to create output file \"{}\"", pb.display())
+        })?)),
+        None => None,
+    };
+
+    let sink: Box<dyn EntrySink> = match (format, file) {
+        (OutputFormat::Tsv, Some(f)) => Box::new(TsvSink::new(f, options)?),
+        (OutputFormat::Tsv, None) => Box::new(TsvSink::new(stdout_lock(), options)?),
+        (OutputFormat::JsonLines, Some(f)) => Box::new(JsonLinesSink::new(f, options)),
+        (OutputFormat::JsonLines, None) => Box::new(JsonLinesSink::new(stdout_lock(), options)),
+        (OutputFormat::Arrow, Some(f)) => Box::new(ArrowSink::ipc(f, options)?),
+        (OutputFormat::Parquet, Some(f)) => Box::new(ArrowSink::parquet(f, options)?),
+        // The columnar writers have to be Send, which a stdout lock isn't
+        // They only write whole batches at a time, so locking stdout for every write is cheap
+        (OutputFormat::Arrow, None) => Box::new(ArrowSink::ipc(BufWriter::new(stdout()), options)?),
+        (OutputFormat::Parquet, None) => {
+            Box::new(ArrowSink::parquet(BufWriter::new(stdout()), options)?)
+        }
+    };
+
+    Ok(sink)
+}
+
+/// Lock stdout once for the whole run, instead of once per line like println! does
+fn stdout_lock() -> BufWriter<std::io::StdoutLock<'static>> {
+    BufWriter::new(stdout().lock())
+}
+
+/// Writes entries as tab-separated values, preceded by a header line
+pub struct TsvSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TsvSink<W> {
+    pub fn new(mut writer: W, options: &OutputOptions) -> Result<Self> {
+        writeln!(writer, "{}", header_columns(options).join("\t"))
+            .context("Error writing header")?;
+
+        Ok(Self { writer })
+    }
+}
+
+impl<W: Write> EntrySink for TsvSink<W> {
+    fn write(&mut self, record: &EntryRecord) -> Result<()> {
+        let w = &mut self.writer;
+
+        write!(
+            w,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t",
+            record.accession,
+            record.sequence,
+            record.name,
+            record.version,
+            record.ec_numbers.join(";"),
+            record.go_ids.join(";"),
+            record.interpro_ids.join(";"),
+            record.status,
+        )?;
+
+        if let Some(taxon_id) = record.taxon_id {
+            write!(w, "{taxon_id}")?;
+        }
+
+        for ids in &record.extra_xrefs {
+            write!(w, "\t{}", ids.join(";"))?;
+        }
+
+        if let Some(evidence) = &record.go_evidence {
+            write!(w, "\t{}", evidence.join(";"))?;
+        }
+
+        if let Some(variants) = &record.variants {
+            write!(w, "\t{}", variants.join(";"))?;
+        }
+
+        writeln!(w)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes every entry as a JSON object on its own line
+/// Extra cross-references are grouped in an "xrefs" object, keyed by their database
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+    databases: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    #[serde(flatten)]
+    record: &'a EntryRecord<'a>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    xrefs: BTreeMap<&'a str, &'a [&'a str]>,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W, options: &OutputOptions) -> Self {
+        Self {
+            writer,
+            databases: extra_xrefs(&options.xrefs).map(String::from).collect(),
+        }
+    }
+}
+
+impl<W: Write> EntrySink for JsonLinesSink<W> {
+    fn write(&mut self, record: &EntryRecord) -> Result<()> {
+        let json = JsonRecord {
+            record,
+            xrefs: self
+                .databases
+                .iter()
+                .map(|d| d.as_str())
+                .zip(record.extra_xrefs.iter().map(|ids| ids.as_slice()))
+                .collect(),
+        };
+
+        serde_json::to_writer(&mut self.writer, &json).context("Error serializing entry")?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The file formats that are written from Arrow record batches
+enum BatchWriter<W: Write + Send> {
+    Ipc(FileWriter<W>),
+    Parquet(ArrowWriter<W>),
+}
+
+/// Writes entries in a columnar format, using the same column names as the TSV header
+/// Columns with multiple values are stored as lists of strings
+pub struct ArrowSink<W: Write + Send> {
+    writer: BatchWriter<W>,
+    schema: SchemaRef,
+    builder: BatchBuilder,
+}
+
+impl<W: Write + Send> ArrowSink<W> {
+    /// Write entries to an Arrow IPC file
+    pub fn ipc(writer: W, options: &OutputOptions) -> Result<Self> {
+        let schema = arrow_schema(options);
+        let writer = FileWriter::try_new(writer, &schema).context("Error writing Arrow schema")?;
+
+        Ok(Self::new(BatchWriter::Ipc(writer), schema))
+    }
+
+    /// Write entries to a Parquet file
+    pub fn parquet(writer: W, options: &OutputOptions) -> Result<Self> {
+        let schema = arrow_schema(options);
+        let writer = ArrowWriter::try_new(writer, schema.clone(), None)
+            .context("Error creating Parquet writer")?;
+
+        Ok(Self::new(BatchWriter::Parquet(writer), schema))
+    }
+
+    fn new(writer: BatchWriter<W>, schema: SchemaRef) -> Self {
+        let extra_columns = schema.fields().len() - BASE_COLUMNS;
+
+        Self {
+            writer,
+            schema,
+            builder: BatchBuilder::new(extra_columns),
+        }
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        let batch = RecordBatch::try_new(self.schema.clone(), self.builder.finish())
+            .context("Error creating record batch")?;
+
+        match &mut self.writer {
+            BatchWriter::Ipc(w) => w.write(&batch).context("Error writing Arrow batch")?,
+            BatchWriter::Parquet(w) => w.write(&batch).context("Error writing Parquet batch")?,
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> EntrySink for ArrowSink<W> {
+    fn write(&mut self, record: &EntryRecord) -> Result<()> {
+        self.builder.append(record);
+
+        if self.builder.len == BATCH_SIZE {
+            self.write_batch()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.builder.len > 0 {
+            self.write_batch()?;
+        }
+
+        match self.writer {
+            BatchWriter::Ipc(mut w) => {
+                w.finish().context("Error finishing Arrow file")?;
+                w.into_inner()?.flush()?;
+            }
+            BatchWriter::Parquet(w) => {
+                w.close().context("Error finishing Parquet file")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Amount of columns that are always present
+const BASE_COLUMNS: usize = 9;
+
+fn list_type() -> DataType {
+    DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)))
+}
+
+/// The schema for the columns that are written with `options`
+fn arrow_schema(options: &OutputOptions) -> SchemaRef {
+    let columns = header_columns(options);
+
+    let fields: Vec<Field> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let (data_type, nullable) = match i {
+                3 => (DataType::UInt32, false),
+                4..=6 => (list_type(), false),
+                8 => (DataType::UInt32, true),
+                i if i >= BASE_COLUMNS => (list_type(), true),
+                _ => (DataType::Utf8, false),
+            };
+
+            Field::new(name, data_type, nullable)
+        })
+        .collect();
+
+    Arc::new(Schema::new(fields))
+}
+
+/// Builds the columns of one record batch
+struct BatchBuilder {
+    accession: StringBuilder,
+    sequence: StringBuilder,
+    name: StringBuilder,
+    version: UInt32Builder,
+    ec_numbers: ListBuilder<StringBuilder>,
+    go_ids: ListBuilder<StringBuilder>,
+    interpro_ids: ListBuilder<StringBuilder>,
+    status: StringBuilder,
+    taxon_id: UInt32Builder,
+    // The extra cross-references, followed by the GO evidence and variants if they were requested
+    extra: Vec<ListBuilder<StringBuilder>>,
+    len: usize,
+}
+
+impl BatchBuilder {
+    fn new(extra_columns: usize) -> Self {
+        Self {
+            accession: StringBuilder::new(),
+            sequence: StringBuilder::new(),
+            name: StringBuilder::new(),
+            version: UInt32Builder::new(),
+            ec_numbers: ListBuilder::new(StringBuilder::new()),
+            go_ids: ListBuilder::new(StringBuilder::new()),
+            interpro_ids: ListBuilder::new(StringBuilder::new()),
+            status: StringBuilder::new(),
+            taxon_id: UInt32Builder::new(),
+            extra: (0..extra_columns)
+                .map(|_| ListBuilder::new(StringBuilder::new()))
+                .collect(),
+            len: 0,
+        }
+    }
+
+    fn append(&mut self, record: &EntryRecord) {
+        fn append_list(builder: &mut ListBuilder<StringBuilder>, values: &[&str]) {
+            for v in values {
+                builder.values().append_value(v);
+            }
+            builder.append(true);
+        }
+
+        self.accession.append_value(record.accession);
+        self.sequence.append_value(record.sequence);
+        self.name.append_value(record.name);
+        self.version.append_value(record.version);
+        append_list(&mut self.ec_numbers, &record.ec_numbers);
+        append_list(&mut self.go_ids, &record.go_ids);
+        append_list(&mut self.interpro_ids, &record.interpro_ids);
+        self.status.append_value(record.status);
+        self.taxon_id.append_option(record.taxon_id);
+
+        let variants: Option<Vec<&str>> = record
+            .variants
+            .as_ref()
+            .map(|v| v.iter().map(String::as_str).collect());
+        let extra_values = record
+            .extra_xrefs
+            .iter()
+            .chain(&record.go_evidence)
+            .chain(&variants);
+        for (builder, values) in self.extra.iter_mut().zip(extra_values) {
+            append_list(builder, values);
+        }
+
+        self.len += 1;
+    }
+
+    fn finish(&mut self) -> Vec<ArrayRef> {
+        self.len = 0;
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.accession.finish()),
+            Arc::new(self.sequence.finish()),
+            Arc::new(self.name.finish()),
+            Arc::new(self.version.finish()),
+            Arc::new(self.ec_numbers.finish()),
+            Arc::new(self.go_ids.finish()),
+            Arc::new(self.interpro_ids.finish()),
+            Arc::new(self.status.finish()),
+            Arc::new(self.taxon_id.finish()),
+        ];
+
+        for builder in &mut self.extra {
+            columns.push(Arc::new(builder.finish()));
+        }
+
+        columns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::UInt32Type;
+    use arrow_ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn get_example_record() -> EntryRecord<'static> {
+        EntryRecord {
+            accession: "P12345",
+            sequence: "MAFSAEDVLK",
+            name: "Some protein",
+            version: 3,
+            ec_numbers: vec!["1.1.1.1"],
+            go_ids: vec!["GO:0046782", "GO:0016743"],
+            interpro_ids: vec![],
+            status: "swissprot",
+            taxon_id: Some(9606),
+            extra_xrefs: vec![vec!["PF04947"]],
+            go_evidence: Some(vec!["IEA", "IDA"]),
+            variants: None,
+        }
+    }
+
+    fn get_example_options() -> OutputOptions {
+        OutputOptions {
+            xrefs: vec!["GO".to_string(), "InterPro".to_string(), "Pfam".to_string()],
+            go_evidence_column: true,
+            ..OutputOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_tsv_sink() {
+        let mut output = Vec::new();
+        let mut sink = TsvSink::new(&mut output, &get_example_options()).unwrap();
+        sink.write(&get_example_record()).unwrap();
+        Box::new(sink).finish().unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("Organism ID\tCross-reference (Pfam)\tGene ontology evidence"));
+        assert_eq!(
+            lines[1],
+            "P12345\tMAFSAEDVLK\tSome protein\t3\t1.1.1.1\tGO:0046782;GO:0016743\t\tswissprot\t9606\tPF04947\tIEA;IDA"
+        );
+    }
+
+    #[test]
+    fn test_json_lines_sink() {
+        let mut output = Vec::new();
+        let mut sink = JsonLinesSink::new(&mut output, &get_example_options());
+        sink.write(&get_example_record()).unwrap();
+        Box::new(sink).finish().unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(json["accession"], "P12345");
+        assert_eq!(json["taxon_id"], 9606);
+        assert_eq!(json["go_ids"][1], "GO:0016743");
+        assert_eq!(json["go_evidence"][0], "IEA");
+        assert_eq!(json["xrefs"]["Pfam"][0], "PF04947");
+    }
+
+    #[test]
+    fn test_arrow_sink() {
+        let mut output = Vec::new();
+        let mut sink = ArrowSink::ipc(&mut output, &get_example_options()).unwrap();
+        for _ in 0..BATCH_SIZE + 1 {
+            sink.write(&get_example_record()).unwrap();
+        }
+        Box::new(sink).finish().unwrap();
+
+        let reader = FileReader::try_new(Cursor::new(output), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].num_rows(), 1);
+        assert_eq!(batches[0].num_columns(), 11);
+
+        let taxon_ids = batches[0]
+            .column_by_name("Organism ID")
+            .unwrap()
+            .as_primitive::<UInt32Type>();
+        assert_eq!(taxon_ids.value(0), 9606);
+
+        let go_ids = batches[0]
+            .column_by_name("Gene ontology IDs")
+            .unwrap()
+            .as_list::<i32>();
+        assert_eq!(go_ids.value(0).as_string::<i32>().value(1), "GO:0016743");
+    }
+
+    #[test]
+    fn test_parquet_sink() {
+        let path = std::env::temp_dir().join("unipept_test_parquet_sink.parquet");
+
+        let mut sink =
+            open_sink(OutputFormat::Parquet, Some(&path), &get_example_options()).unwrap();
+        sink.write(&get_example_record()).unwrap();
+        sink.finish().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(batches.len(), 1);
+        let accessions = batches[0]
+            .column_by_name("Entry")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!(accessions.value(0), "P12345");
+    }
+
+    #[test]
+    fn test_parse_output_format() {
+        assert_eq!("tsv".parse::<OutputFormat>().unwrap(), OutputFormat::Tsv);
+        assert_eq!(
+            "jsonl".parse::<OutputFormat>().unwrap(),
+            OutputFormat::JsonLines
+        );
+        assert!("csv".parse::<OutputFormat>().is_err());
+    }
+}
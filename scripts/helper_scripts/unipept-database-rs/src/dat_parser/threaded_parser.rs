@@ -1,28 +1,88 @@
+use std::collections::HashMap;
 use std::io::BufRead;
 
 use anyhow::Result;
-use crossbeam_channel::{bounded, Receiver};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
 use lazy_static::lazy_static;
 
-use crate::dat_parser::consumer::Consumer;
+use crate::dat_parser::consumer::{Consumer, ParsedBatch};
 use crate::dat_parser::entry::UniProtDATEntry;
-use crate::dat_parser::producer::Producer;
+use crate::dat_parser::producer::{Producer, RawBatch};
+use crate::dat_parser::sequence_check::SequenceCheck;
+
+/// Amount of entries that are sent to a worker thread at once by default
+pub const DEFAULT_BATCH_SIZE: usize = 64;
+
+/// Size of the buffer the input is read into by default
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
 
 /// A multi-threaded DAT parser
 /// This parser uses one thread to parse chunks of bytes from the `reader` input stream,
 /// and `threads` worker threads to parse those into `UniProtDATEntry`s
+/// Entries are passed between the threads in batches, to keep the channel overhead low for small entries.
+///
+/// By default, entries are yielded in the order the workers finish them.
+/// An ordered parser restores the order of the input instead, so its output is identical
+/// to that of the `SequentialDATParser`.
 pub struct ThreadedDATParser<B: BufRead + Send + 'static> {
     producer: Producer<B>,
     consumers: Vec<Consumer>,
     threads: usize,
-    r_parsed: Option<Receiver<Result<UniProtDATEntry>>>,
+    batch_size: usize,
+    channel_capacity: Option<usize>,
+    buffer_size: usize,
+    r_parsed: Option<Receiver<ParsedBatch>>,
+    /// Permits for the producer to send another batch, one is returned for every batch that is yielded
+    s_permits: Option<Sender<()>>,
     started: bool,
+    finished: bool,
+    ordered: bool,
+    sequence_check: SequenceCheck,
+    next_index: usize,
+    // Batches that were parsed before the ones that precede them in the input
+    // The producer needs a permit for every batch, so this holds less batches than there are permits
+    pending: HashMap<usize, Vec<Result<UniProtDATEntry>>>,
+    current: std::vec::IntoIter<Result<UniProtDATEntry>>,
 }
 
 impl<B: BufRead + Send + 'static> ThreadedDATParser<B> {
     /// Create a new ThreadedParser with `threads` consumer threads.
     /// Passing 0 as the amount of threads uses the amount of (virtual) CPUs available in your machine
-    pub fn new(reader: B, mut threads: usize) -> Self {
+    pub fn new(reader: B, threads: usize) -> Self {
+        Self::with_order(reader, threads, false)
+    }
+
+    /// Create a new ThreadedParser that yields entries in the same order as they appear in the input
+    pub fn ordered(reader: B, threads: usize) -> Self {
+        Self::with_order(reader, threads, true)
+    }
+
+    /// Validate the sequence of every entry against its SQ line
+    pub fn with_sequence_check(mut self, check: SequenceCheck) -> Self {
+        self.sequence_check = check;
+        self
+    }
+
+    /// Send the entries to the worker threads in batches of `batch_size` entries
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Amount of batches that can wait in each channel before the sending thread blocks,
+    /// which is twice the amount of threads by default
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = Some(capacity);
+        self
+    }
+
+    /// Read the input in blocks of `buffer_size` bytes
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    fn with_order(reader: B, mut threads: usize, ordered: bool) -> Self {
         if threads == 0 {
             lazy_static! {
                 static ref THREADS: usize = num_cpus::get();
@@ -41,31 +101,70 @@ impl<B: BufRead + Send + 'static> ThreadedDATParser<B> {
             producer,
             consumers,
             threads,
+            batch_size: DEFAULT_BATCH_SIZE,
+            channel_capacity: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
             r_parsed: None,
+            s_permits: None,
             started: false,
+            finished: false,
+            ordered,
+            sequence_check: SequenceCheck::default(),
+            next_index: 0,
+            pending: HashMap::new(),
+            current: Vec::new().into_iter(),
         }
     }
 
     /// Create communication channels for the producer and consumers,
     /// and launch them in threads
     fn start(&mut self) {
-        let (s_raw, r_raw) = bounded::<Vec<u8>>(self.threads * 2);
-        let (s_parsed, r_parsed) = bounded::<Result<UniProtDATEntry>>(self.threads * 2);
+        let capacity = self.channel_capacity.unwrap_or(self.threads * 2);
+        let (s_raw, r_raw) = bounded::<RawBatch>(capacity);
+        let (s_parsed, r_parsed) = bounded::<ParsedBatch>(capacity);
+
+        // The producer can't get further ahead of the next batch to yield than it takes to fill both channels
+        // and keep every consumer busy, which bounds the batches that wait for a slow one in ordered mode
+        let (s_permits, r_permits) = unbounded::<()>();
+        for _ in 0..2 * capacity + self.threads {
+            let _ = s_permits.send(());
+        }
 
-        self.producer.start(s_raw.clone());
+        self.producer.start(
+            s_raw.clone(),
+            Some(r_permits),
+            self.batch_size,
+            self.buffer_size,
+        );
 
         for consumer in &mut self.consumers {
-            consumer.start(r_raw.clone(), s_parsed.clone());
+            consumer.start(r_raw.clone(), s_parsed.clone(), self.sequence_check);
         }
 
         self.r_parsed = Some(r_parsed);
+        self.s_permits = Some(s_permits);
         self.started = true;
     }
 
-    fn join(&mut self) {
-        self.producer.join();
+    /// Wait for all threads to finish, returning the first error that occurred in one of them
+    fn join(&mut self) -> Result<()> {
+        let producer_result = self.producer.join();
+
         for consumer in self.consumers.iter_mut() {
-            consumer.join();
+            consumer.join()?;
+        }
+
+        producer_result
+    }
+
+    /// Yield the entries of the next batch, which allows the producer to send another one
+    fn advance(&mut self, entries: Vec<Result<UniProtDATEntry>>) {
+        self.next_index += 1;
+        self.current = entries.into_iter();
+
+        // The producer might have finished already
+        if let Some(permits) = &self.s_permits {
+            let _ = permits.send(());
         }
     }
 }
@@ -78,21 +177,172 @@ impl<B: BufRead + Send + 'static> Iterator for ThreadedDATParser<B> {
             self.start();
         }
 
-        match &self.r_parsed {
-            Some(receiver) => {
-                match receiver.recv() {
-                    Ok(entry) => Some(entry),
-                    // An error is raised when the channel becomes disconnected,
-                    // so we don't actually have to handle the error here
-                    // it's just a sign that we're done parsing
-                    Err(_) => {
-                        self.join();
-                        None
+        loop {
+            if let Some(entry) = self.current.next() {
+                return Some(entry);
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            // The next batch might have been parsed already
+            if let Some(entries) = self.pending.remove(&self.next_index) {
+                self.advance(entries);
+                continue;
+            }
+
+            // We never started (unreachable case in practice)
+            let receiver = self.r_parsed.as_ref()?;
+
+            match receiver.recv() {
+                Ok((index, entries)) => {
+                    if !self.ordered || index == self.next_index {
+                        self.advance(entries);
+                    } else {
+                        self.pending.insert(index, entries);
                     }
                 }
+                // An error is raised when the channel becomes disconnected,
+                // so we don't actually have to handle the error here
+                // it's just a sign that we're done parsing
+                // Errors that stopped one of the threads are passed on as a final item
+                Err(_) => {
+                    self.finished = true;
+                    return self.join().err().map(Err);
+                }
             }
-            // We never started (unreachable case in practice)
-            None => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::dat_parser::error::InvalidEntryError;
+    use crate::dat_parser::sequential_parser::SequentialDATParser;
+
+    /// Generate a DAT file with entries of different sizes, so workers finish them out of order
+    fn get_example_input(entries: usize) -> Vec<u8> {
+        let mut input = String::new();
+
+        for i in 0..entries {
+            let length = 10 + (i * 7919) % 3000;
+            input.push_str(&format!(
+                "ID   TEST{i}_HUMAN   Reviewed;   {length} AA.
+AC   P{i:05};
+DT   28-JUN-2011, integrated into UniProtKB/Swiss-Prot.
+DT   19-JUL-2004, sequence version 1.
+DT   08-NOV-2023, entry version {i}.
+DE   RecName: Full=Protein {i};
+OX   NCBI_TaxID={i};
+PE   1: Evidence at protein level;
+SQ   SEQUENCE   {length} AA;  1 MW;  0000000000000000 CRC64;
+     {}
+//
+",
+                "A".repeat(length)
+            ));
+        }
+
+        input.into_bytes()
+    }
+
+    #[test]
+    fn test_ordered() {
+        let input = get_example_input(500);
+
+        let want: Vec<String> = SequentialDATParser::new(Cursor::new(input.clone()))
+            .map(|e| e.unwrap().accession_number().to_string())
+            .collect();
+
+        for threads in [2, 3, 8] {
+            let got: Vec<String> = ThreadedDATParser::ordered(Cursor::new(input.clone()), threads)
+                .map(|e| e.unwrap().accession_number().to_string())
+                .collect();
+
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn test_settings() {
+        let input = get_example_input(200);
+
+        let want: Vec<String> = SequentialDATParser::new(Cursor::new(input.clone()))
+            .map(|e| e.unwrap().accession_number().to_string())
+            .collect();
+
+        // Buffers smaller than the "\n//" terminator make it span several reads
+        for (batch_size, capacity, buffer_size) in [(1, 1, 2), (7, 0, 100), (1000, 4, 4096)] {
+            let got: Vec<String> = ThreadedDATParser::ordered(Cursor::new(input.clone()), 3)
+                .with_batch_size(batch_size)
+                .with_channel_capacity(capacity)
+                .with_buffer_size(buffer_size)
+                .map(|e| e.unwrap().accession_number().to_string())
+                .collect();
+
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn test_reorder_window() {
+        let input = get_example_input(500);
+
+        // Batches that wait for a slower one never exceed the permits of the producer
+        let mut parser = ThreadedDATParser::ordered(Cursor::new(input), 4)
+            .with_batch_size(1)
+            .with_channel_capacity(1);
+        let window = 2 + 4;
+
+        let mut count = 0;
+        while let Some(entry) = parser.next() {
+            entry.unwrap();
+            assert!(parser.pending.len() < window);
+            count += 1;
+        }
+        assert_eq!(count, 500);
+    }
+
+    #[test]
+    fn test_unordered() {
+        let input = get_example_input(500);
+
+        let mut got: Vec<String> = ThreadedDATParser::new(Cursor::new(input), 4)
+            .map(|e| e.unwrap().accession_number().to_string())
+            .collect();
+        got.sort();
+
+        assert_eq!(got.len(), 500);
+        assert_eq!(got[0], "P00000");
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let mut input = get_example_input(3);
+        let valid_length = input.len();
+
+        // Entry without a taxon id, followed by an unterminated entry
+        input.extend_from_slice(b"ID   BAD_HUMAN   Reviewed;   1 AA.\nAC   P99999;\n//\n");
+        input.extend_from_slice(b"ID   TRUNCATED");
+
+        let results: Vec<Result<UniProtDATEntry>> =
+            ThreadedDATParser::ordered(Cursor::new(input), 2).collect();
+        assert_eq!(results.len(), 5);
+
+        let errors: Vec<&InvalidEntryError> = results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .map(|e| e.downcast_ref::<InvalidEntryError>().unwrap())
+            .collect();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].accession(), Some("P99999"));
+        assert_eq!(errors[0].offset(), valid_length as u64);
+        assert_eq!(errors[1].accession(), None);
+        assert_eq!(errors[1].data(), b"ID   TRUNCATED");
+    }
+}
@@ -1,4 +1,79 @@
+use crate::dat_parser::variants::VariantKind;
+
+/// Databases whose cross-references are extracted by default
+/// These have a column in the default header, rather than an extra column at the end
+pub const DEFAULT_XREFS: [&str; 2] = ["GO", "InterPro"];
+
+/// Name of the optional column with the evidence codes of the GO annotations
+const GO_EVIDENCE_COLUMN: &str = "Gene ontology evidence";
+
+/// Name of the optional column with the sequence variants, see `SequenceVariant`
+pub const VARIANTS_COLUMN: &str = "Sequence variants";
+
+/// Options that determine which columns are written, and what they contain
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    /// Value for the "Status" column, instead of deriving it from the ID line
+    pub db_type_override: Option<String>,
+    /// Databases to extract cross-references (DR lines) for
+    pub xrefs: Vec<String>,
+    /// Only GO annotations with an evidence code that passes this filter are written
+    pub go_evidence: GoEvidenceFilter,
+    /// Write the evidence codes of the GO annotations to an extra column, in the same order as their ids
+    pub go_evidence_column: bool,
+    /// Kinds of features to write to an extra column with sequence variants, no column is written if empty
+    pub variants: Vec<VariantKind>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            db_type_override: None,
+            xrefs: DEFAULT_XREFS.map(String::from).to_vec(),
+            go_evidence: GoEvidenceFilter::default(),
+            go_evidence_column: false,
+            variants: Vec::new(),
+        }
+    }
+}
+
+/// Warn once about the `count` entries whose ID line contradicts the overridden "Status" column
+pub fn warn_contradicting_status(count: u64, options: &OutputOptions) {
+    if let (true, Some(db_type)) = (count > 0, &options.db_type_override) {
+        eprintln!(
+            "Warning: the status of {count} entries was overridden as {db_type}, which contradicts their ID line"
+        );
+    }
+}
+
+/// Filter for GO annotations based on their evidence code (eg. "IEA")
+/// If `include` is not empty, only those codes are kept. Codes in `exclude` are never kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoEvidenceFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl GoEvidenceFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    pub fn allows(&self, evidence: &str) -> bool {
+        let matches = |codes: &[String]| codes.iter().any(|c| c.eq_ignore_ascii_case(evidence));
+
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+/// Write the default header line to stdout
 pub fn write_header() {
+    let options = OutputOptions::default();
+    println!("{}", header_columns(&options).join("\t"));
+}
+
+/// Names of the columns that are written with `options`, in order
+pub fn header_columns(options: &OutputOptions) -> Vec<String> {
     let fields: [&str; 9] = [
         "Entry",
         "Sequence",
@@ -11,6 +86,94 @@ pub fn write_header() {
         "Organism ID",
     ];
 
-    let result_string = fields.join("\t");
-    println!("{}", result_string);
+    let mut columns: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+    columns.extend(extra_xrefs(&options.xrefs).map(xref_column));
+
+    if options.go_evidence_column {
+        columns.push(GO_EVIDENCE_COLUMN.to_string());
+    }
+
+    if !options.variants.is_empty() {
+        columns.push(VARIANTS_COLUMN.to_string());
+    }
+
+    columns
+}
+
+/// The databases in `xrefs` that need an extra column at the end of every line
+pub fn extra_xrefs(xrefs: &[String]) -> impl Iterator<Item = &str> {
+    xrefs
+        .iter()
+        .map(|x| x.as_str())
+        .filter(|x| !DEFAULT_XREFS.contains(x))
+}
+
+/// The databases in `xrefs` without duplicates, in the order they were first given
+pub fn unique_xrefs(xrefs: &[String]) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::with_capacity(xrefs.len());
+    for xref in xrefs {
+        if !unique.contains(xref) {
+            unique.push(xref.clone());
+        }
+    }
+    unique
+}
+
+/// Name of the column that holds the cross-references to `database`
+pub fn xref_column(database: &str) -> String {
+    match database {
+        "GO" => "Gene ontology IDs".to_string(),
+        db => format!("Cross-reference ({db})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extra_xrefs() {
+        let xrefs: Vec<String> = ["GO", "Pfam", "InterPro", "KEGG"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+
+        assert_eq!(
+            extra_xrefs(&xrefs).collect::<Vec<&str>>(),
+            vec!["Pfam", "KEGG"]
+        );
+    }
+
+    #[test]
+    fn test_unique_xrefs() {
+        let xrefs: Vec<String> = ["EMBL", "GO", "EMBL", "Pfam", "GO"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+
+        assert_eq!(unique_xrefs(&xrefs), vec!["EMBL", "GO", "Pfam"]);
+    }
+
+    #[test]
+    fn test_go_evidence_filter() {
+        let all = GoEvidenceFilter::default();
+        assert!(all.allows("IEA"));
+
+        let without_iea = GoEvidenceFilter::new(vec![], vec!["IEA".to_string()]);
+        assert!(!without_iea.allows("IEA"));
+        assert!(without_iea.allows("IDA"));
+
+        let experimental =
+            GoEvidenceFilter::new(vec!["exp".to_string(), "IDA".to_string()], vec![]);
+        assert!(experimental.allows("EXP"));
+        assert!(experimental.allows("IDA"));
+        assert!(!experimental.allows("ISS"));
+    }
+
+    #[test]
+    fn test_xref_column() {
+        assert_eq!(xref_column("GO"), "Gene ontology IDs");
+        assert_eq!(xref_column("InterPro"), "Cross-reference (InterPro)");
+        assert_eq!(xref_column("eggNOG"), "Cross-reference (eggNOG)");
+    }
 }
@@ -0,0 +1,215 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{Context, Error, Result};
+use strum_macros::{Display, EnumString};
+
+use crate::dat_parser::entry::UniProtDATEntry;
+
+/// Feature keys that describe single sequence variants
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+pub enum VariantKind {
+    /// Natural variants, eg. from dbSNP
+    #[strum(serialize = "VARIANT")]
+    Variant,
+    /// Differences between the sources of the sequence
+    #[strum(serialize = "CONFLICT")]
+    Conflict,
+    /// Residues that were changed experimentally
+    #[strum(serialize = "MUTAGEN")]
+    Mutagen,
+    /// Removal of the initiator methionine, written as "INIT_MET:1:M>"
+    #[strum(serialize = "INIT_MET")]
+    InitMet,
+}
+
+/// A single variant of the canonical sequence, written as eg. "VARIANT:12:V>A" in the output
+/// Variants with multiple alternatives are split into one variant per alternative
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceVariant {
+    pub kind: VariantKind,
+    /// 1-based position of the first residue that is replaced
+    pub start: usize,
+    pub original: String,
+    /// Empty if the original residues are missing
+    pub replacement: String,
+}
+
+impl SequenceVariant {
+    /// Apply this variant to the canonical sequence
+    /// Returns `None` if the original residues don't match the sequence
+    pub fn apply(&self, sequence: &[u8]) -> Option<Vec<u8>> {
+        let range = self.start.checked_sub(1)?..self.start - 1 + self.original.len();
+        if sequence.get(range.clone())? != self.original.as_bytes() {
+            return None;
+        }
+
+        let mut result = Vec::with_capacity(sequence.len() + self.replacement.len());
+        result.extend_from_slice(&sequence[..range.start]);
+        result.extend_from_slice(self.replacement.as_bytes());
+        result.extend_from_slice(&sequence[range.end..]);
+        Some(result)
+    }
+}
+
+impl Display for SequenceVariant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}>{}",
+            self.kind, self.start, self.original, self.replacement
+        )
+    }
+}
+
+impl FromStr for SequenceVariant {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || -> Option<SequenceVariant> {
+            let mut parts = s.splitn(3, ':');
+            let kind = parts.next()?.parse().ok()?;
+            let start = parts.next()?.parse().ok()?;
+            let (original, replacement) = parts.next()?.split_once('>')?;
+
+            Some(SequenceVariant {
+                kind,
+                start,
+                original: original.to_string(),
+                replacement: replacement.to_string(),
+            })
+        };
+
+        parse().with_context(|| format!("Unable to parse sequence variant \"{s}\""))
+    }
+}
+
+/// The variants of an entry that are described by features with one of the given kinds
+/// Features that can't be applied to the canonical sequence (eg. because their note can't be parsed,
+/// or their original residues don't match the sequence) are skipped
+pub fn sequence_variants(entry: &UniProtDATEntry, kinds: &[VariantKind]) -> Vec<SequenceVariant> {
+    let sequence = entry.sequence();
+    let mut variants = Vec::new();
+
+    for feature in entry.features() {
+        let Some(kind) = kinds.iter().find(|k| feature.key == k.to_string()) else {
+            continue;
+        };
+        let (Some(start), Some(end)) = (feature.start(), feature.end()) else {
+            continue;
+        };
+        let Some(original) = start.checked_sub(1).and_then(|s| sequence.get(s..end)) else {
+            continue;
+        };
+
+        // The initiator methionine has no note that describes the change, it is always removed
+        if *kind == VariantKind::InitMet {
+            if original == "M" {
+                variants.push(SequenceVariant {
+                    kind: *kind,
+                    start,
+                    original: original.to_string(),
+                    replacement: String::new(),
+                });
+            }
+            continue;
+        }
+
+        let Some(change) = feature.sequence_change() else {
+            continue;
+        };
+
+        if change.original.as_ref().is_some_and(|o| o != original) {
+            continue;
+        }
+
+        for replacement in change.replacements {
+            variants.push(SequenceVariant {
+                kind: *kind,
+                start,
+                original: original.to_string(),
+                replacement,
+            });
+        }
+    }
+
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = "ID   TEST_HUMAN   Reviewed;   12 AA.
+AC   P12345;
+DT   28-JUN-2011, integrated into UniProtKB/Swiss-Prot.
+DT   19-JUL-2004, sequence version 1.
+DT   08-NOV-2023, entry version 3.
+DE   RecName: Full=Protein;
+OX   NCBI_TaxID=9606;
+PE   1: Evidence at protein level;
+FT   INIT_MET        1
+FT                   /note=\"Removed\"
+FT   VARIANT         4
+FT                   /note=\"S -> L (in dbSNP:rs123)\"
+FT                   /id=\"VAR_000001\"
+FT   VARIANT         6..7
+FT                   /note=\"Missing (in a patient)\"
+FT   CONFLICT        2
+FT                   /note=\"A -> G (in Ref. 2; AAA12345)\"
+FT   MUTAGEN         9
+FT                   /note=\"K->A,R: Loss of activity.\"
+FT   MUTAGEN         10
+FT                   /note=\"W->A: Does not match.\"
+SQ   SEQUENCE   12 AA;  1 MW;  0000000000000000 CRC64;
+     MAFSAEDVKL YY";
+
+    #[test]
+    fn test_sequence_variants() {
+        let entry = UniProtDATEntry::from_bytes(ENTRY.as_bytes()).unwrap();
+
+        let variants: Vec<String> = sequence_variants(
+            &entry,
+            &[
+                VariantKind::Variant,
+                VariantKind::Mutagen,
+                VariantKind::InitMet,
+            ],
+        )
+        .iter()
+        .map(|v| v.to_string())
+        .collect();
+
+        assert_eq!(
+            variants,
+            vec![
+                "INIT_MET:1:M>",
+                "VARIANT:4:S>L",
+                "VARIANT:6:ED>",
+                "MUTAGEN:9:K>A",
+                "MUTAGEN:9:K>R"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_variant() {
+        let variant: SequenceVariant = "VARIANT:6:ED>".parse().unwrap();
+        assert_eq!(variant.kind, VariantKind::Variant);
+        assert_eq!(variant.start, 6);
+        assert_eq!(variant.original, "ED");
+        assert_eq!(variant.replacement, "");
+        assert_eq!(variant.to_string(), "VARIANT:6:ED>");
+
+        assert!("VARIANT:6".parse::<SequenceVariant>().is_err());
+        assert!("OTHER:6:E>D".parse::<SequenceVariant>().is_err());
+    }
+
+    #[test]
+    fn test_apply_variant() {
+        let variant: SequenceVariant = "CONFLICT:2:AF>G".parse().unwrap();
+        assert_eq!(variant.apply(b"MAFSA"), Some(b"MGSA".to_vec()));
+        assert_eq!(variant.apply(b"MKFSA"), None);
+        assert_eq!(variant.apply(b"MA"), None);
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{Context, Error, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::dat_parser::models::{ProteinExistence, ReviewStatus};
+
+lazy_static! {
+    /// The tags that follow the protein name, eg. " OS=Homo sapiens OX=9606"
+    static ref TAG_REGEX: Regex = Regex::new(r"(?:^|\s)(OS|OX|GN|PE|SV)=").unwrap();
+}
+
+const FRAGMENT_SUFFIXES: [&str; 2] = [" (Fragment)", " (Fragments)"];
+
+/// The fields of a UniProt FASTA header, eg.
+/// ">sp|Q6GZX4|001R_FRG3G Putative transcription factor 001R OS=Frog virus 3 (isolate Goorhuis) OX=654924 GN=FV3-001R PE=4 SV=1"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FastaHeader {
+    /// "sp" for Swiss-Prot, "tr" for TrEMBL, or empty if the header has no database
+    pub database: String,
+    pub accession: String,
+    pub entry_name: String,
+    /// The protein name, including the "(Fragment)" suffix if there is one
+    pub name: String,
+    pub organism_name: Option<String>,
+    pub taxon_id: u32,
+    pub gene: Option<String>,
+    pub protein_existence: Option<ProteinExistence>,
+    pub sequence_version: Option<u32>,
+}
+
+impl FastaHeader {
+    /// Parse a header line, with or without the leading ">"
+    /// Headers without a "db|accession|entry name" identifier use their first word for both
+    /// the accession and the entry name. The OX tag is required, all other tags are optional
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.strip_prefix('>').unwrap_or(line).trim();
+        let (identifier, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        let (database, accession, entry_name) = match identifier.split('|').collect::<Vec<&str>>()[..]
+        {
+            [database, accession, entry_name] => (database, accession, entry_name),
+            [accession] => ("", accession, accession),
+            _ => {
+                return Err(Error::msg(format!(
+                    "Unable to parse identifier \"{identifier}\""
+                )))
+            }
+        };
+
+        if accession.is_empty() {
+            return Err(Error::msg("Missing accession number"));
+        }
+
+        let tags: Vec<(&str, usize, usize)> = TAG_REGEX
+            .captures_iter(description)
+            .map(|c| {
+                let (tag, full) = (c.get(1).unwrap(), c.get(0).unwrap());
+                (tag.as_str(), full.start(), full.end())
+            })
+            .collect();
+
+        let name_end = tags
+            .first()
+            .map_or(description.len(), |(_, start, _)| *start);
+
+        let mut header = FastaHeader {
+            database: database.to_string(),
+            accession: accession.to_string(),
+            entry_name: entry_name.to_string(),
+            name: description[..name_end].trim().to_string(),
+            organism_name: None,
+            taxon_id: 0,
+            gene: None,
+            protein_existence: None,
+            sequence_version: None,
+        };
+        let mut taxon_id = None;
+
+        for (i, (tag, _, value_start)) in tags.iter().enumerate() {
+            let value_end = tags
+                .get(i + 1)
+                .map_or(description.len(), |(_, start, _)| *start);
+            let value = description[*value_start..value_end].trim();
+
+            match *tag {
+                "OS" => header.organism_name = Some(value.to_string()),
+                "OX" => {
+                    taxon_id = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("Unable to parse taxon id \"{value}\""))?,
+                    )
+                }
+                "GN" => header.gene = Some(value.to_string()),
+                "PE" => {
+                    header.protein_existence = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .and_then(ProteinExistence::from_level)
+                            .with_context(|| {
+                                format!("Unable to parse protein existence \"{value}\"")
+                            })?,
+                    )
+                }
+                "SV" => {
+                    header.sequence_version = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("Unable to parse version \"{value}\""))?,
+                    )
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        header.taxon_id = taxon_id.context("Missing OX tag")?;
+        Ok(header)
+    }
+
+    /// Only "sp" entries are reviewed, custom databases are treated like TrEMBL
+    pub fn status(&self) -> ReviewStatus {
+        if self.database == "sp" {
+            ReviewStatus::Reviewed
+        } else {
+            ReviewStatus::Unreviewed
+        }
+    }
+
+    /// The protein name without its "(Fragment)" suffix, and whether the protein is a fragment
+    pub fn name_and_fragment(&self) -> (&str, bool) {
+        FRAGMENT_SUFFIXES
+            .iter()
+            .find_map(|suffix| self.name.strip_suffix(suffix))
+            .map_or((&self.name, false), |name| (name, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uniprot_header() {
+        let header = FastaHeader::parse(">sp|Q6GZX4|001R_FRG3G Putative transcription factor 001R OS=Frog virus 3 (isolate Goorhuis) OX=654924 GN=FV3-001R PE=4 SV=1").unwrap();
+
+        assert_eq!(header.database, "sp");
+        assert_eq!(header.accession, "Q6GZX4");
+        assert_eq!(header.entry_name, "001R_FRG3G");
+        assert_eq!(header.name, "Putative transcription factor 001R");
+        assert_eq!(
+            header.organism_name.as_deref(),
+            Some("Frog virus 3 (isolate Goorhuis)")
+        );
+        assert_eq!(header.taxon_id, 654924);
+        assert_eq!(header.gene.as_deref(), Some("FV3-001R"));
+        assert_eq!(header.protein_existence, Some(ProteinExistence::Predicted));
+        assert_eq!(header.sequence_version, Some(1));
+        assert_eq!(header.status(), ReviewStatus::Reviewed);
+    }
+
+    #[test]
+    fn test_parse_custom_header() {
+        let header = FastaHeader::parse("prot_1 Kinase (Fragment) OX=9606").unwrap();
+
+        assert_eq!(header.database, "");
+        assert_eq!(header.accession, "prot_1");
+        assert_eq!(header.entry_name, "prot_1");
+        assert_eq!(header.name_and_fragment(), ("Kinase", true));
+        assert_eq!(header.organism_name, None);
+        assert_eq!(header.gene, None);
+        assert_eq!(header.protein_existence, None);
+        assert_eq!(header.sequence_version, None);
+        assert_eq!(header.status(), ReviewStatus::Unreviewed);
+    }
+
+    #[test]
+    fn test_parse_invalid_header() {
+        assert!(FastaHeader::parse(">tr|A0A000|A0A000_HUMAN Protein OS=Homo sapiens").is_err());
+        assert!(FastaHeader::parse(">tr|A0A000 Protein OX=9606").is_err());
+        assert!(FastaHeader::parse(">tr|A0A000|A0A000_HUMAN Protein OX=9606 PE=9").is_err());
+    }
+}
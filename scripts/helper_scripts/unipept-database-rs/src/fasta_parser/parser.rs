@@ -0,0 +1,149 @@
+use std::io::BufRead;
+
+use anyhow::{Context, Error, Result};
+
+use crate::dat_parser::entry::UniProtDATEntry;
+use crate::fasta_parser::header::FastaHeader;
+
+/// A single-threaded parser for UniProt-style FASTA files
+/// Every record is mapped onto the same entry model as the DAT files, see `UniProtDATEntry::from_fasta`
+pub struct FastaParser<B: BufRead> {
+    reader: B,
+    line: String,
+    line_number: usize,
+    // Header of the record that is currently being read
+    header: Option<String>,
+}
+
+impl<B: BufRead> FastaParser<B> {
+    pub fn new(reader: B) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            header: None,
+        }
+    }
+}
+
+impl<B: BufRead> Iterator for FastaParser<B> {
+    type Item = Result<UniProtDATEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut sequence = String::new();
+
+        loop {
+            self.line.clear();
+            self.line_number += 1;
+
+            match self.reader.read_line(&mut self.line) {
+                Err(e) => {
+                    return Some(Err(
+                        Error::new(e).context(format!("Error reading line {}", self.line_number))
+                    ))
+                }
+                Ok(0) => return self.header.take().map(|h| parse_record(&h, sequence)),
+                Ok(_) => {
+                    let line = self.line.trim();
+
+                    if let Some(header) = line.strip_prefix('>') {
+                        if let Some(previous) = self.header.replace(header.to_string()) {
+                            return Some(parse_record(&previous, sequence));
+                        }
+                    } else if self.header.is_some() {
+                        sequence.push_str(line);
+                    } else if !line.is_empty() {
+                        return Some(Err(Error::msg(format!(
+                            "Found sequence data before the first header on line {}",
+                            self.line_number
+                        ))));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_record(header: &str, sequence: String) -> Result<UniProtDATEntry> {
+    let parsed = FastaHeader::parse(header)
+        .with_context(|| format!("Error parsing FASTA header \"{header}\""))?;
+    Ok(UniProtDATEntry::from_fasta(parsed, sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dat_parser::models::{ProteinExistence, ReviewStatus};
+    use crate::dat_parser::utils::OutputOptions;
+
+    const FASTA: &str = ">sp|Q6GZX4|001R_FRG3G Putative transcription factor 001R OS=Frog virus 3 (isolate Goorhuis) OX=654924 GN=FV3-001R PE=4 SV=1
+MAFSAEDVLKEYDRRRRMEALLLSLYYPNDRKLLDYKEWSPPRVQVECPKAPVEWNNPPSEKGLIVGHFSGIKYKGEKAQASEVDVNKMCCWVSKFKDAMRRYQGIQTCKIPGKVLSDLDAKIKAYNLTVEGVEGFVRYSRVTKQHVAAFLKELRHSKQYENVNLIHYILTDKRVDIQHLEKDLVKDFKALVESAHRMRQGHMINVKYILYQLLKKHGHGPDGPDILTVKTGSKGVLYDDSFRKIYTDLGWKFTPL
+
+>tr|A0A000|A0A000_HUMAN Uncharacterized protein (Fragment) OS=Homo sapiens OX=9606 SV=3
+MKLV
+AAGE
+";
+
+    #[test]
+    fn test_parse_fasta() {
+        let entries: Vec<UniProtDATEntry> = FastaParser::new(FASTA.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(entries.len(), 2);
+
+        let first = &entries[0];
+        assert_eq!(first.accession_number(), "Q6GZX4");
+        assert_eq!(first.entry_name(), "001R_FRG3G");
+        assert_eq!(first.status(), ReviewStatus::Reviewed);
+        assert_eq!(first.name(), "Putative transcription factor 001R");
+        assert_eq!(first.taxon_id(), 654924);
+        assert_eq!(first.organism().name, "Frog virus 3 (isolate Goorhuis)");
+        assert_eq!(first.genes()[0].name.as_deref(), Some("FV3-001R"));
+        assert_eq!(first.protein_existence(), Some(ProteinExistence::Predicted));
+        assert_eq!(first.version(), 1);
+        assert_eq!(first.length(), 256);
+        assert_eq!(first.crc64(), "B4840739BF7D4121");
+        assert_eq!(first.molecular_weight(), 29735);
+        assert!(first.validate_sequence().is_ok());
+        assert!(first.dates().is_none());
+
+        let second = &entries[1];
+        assert_eq!(second.sequence(), "MKLVAAGE");
+        assert_eq!(second.status(), ReviewStatus::Unreviewed);
+        assert_eq!(second.name(), "Uncharacterized protein");
+        assert!(second.description().is_fragment());
+        assert!(second.genes().is_empty());
+        assert_eq!(second.protein_existence(), None);
+        assert_eq!(second.version(), 3);
+    }
+
+    #[test]
+    fn test_fasta_record() {
+        let entry = FastaParser::new(FASTA.as_bytes()).nth(1).unwrap().unwrap();
+        let options = OutputOptions::default();
+        let record = entry.record(&options);
+
+        assert_eq!(record.accession, "A0A000");
+        assert_eq!(record.version, 3);
+        assert!(record.ec_numbers.is_empty());
+        assert!(record.go_ids.is_empty());
+        assert!(record.interpro_ids.is_empty());
+        assert_eq!(record.status, "trembl");
+        assert_eq!(record.taxon_id, Some(9606));
+    }
+
+    #[test]
+    fn test_parse_invalid_fasta() {
+        let mut parser =
+            FastaParser::new("MKLV\n>tr|A0A000|A0A000_HUMAN Protein OX=9606\nMKLV\n".as_bytes());
+        assert!(parser.next().unwrap().is_err());
+        assert!(parser.next().unwrap().is_ok());
+        assert!(parser.next().is_none());
+
+        let mut parser = FastaParser::new(">tr|A0A000|A0A000_HUMAN Protein\nMKLV\n".as_bytes());
+        assert!(parser.next().unwrap().is_err());
+        assert!(parser.next().is_none());
+    }
+}
@@ -1,5 +1,7 @@
 pub mod calculate_lcas;
+pub mod cluster_parser;
 pub mod dat_parser;
+pub mod fasta_parser;
 pub mod taxons_lineages;
 pub mod taxons_uniprots_tables;
 pub mod utils;
@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use anyhow::{Error, Result};
+
+/// The equivalence classes of the default equalization, which can't be told apart by mass spectrometry
+pub const DEFAULT_EQUALIZATION: &str = "LI";
+
+/// Ambiguous residues, and the two residues that each of them stands for
+const AMBIGUOUS_RESIDUES: [(u8, [u8; 2]); 3] = [(b'B', *b"DN"), (b'Z', *b"EQ"), (b'J', *b"IL")];
+
+/// A set of amino acid equivalence classes, written as eg. "LI,KQE,ND"
+/// Every residue of a class is replaced by the first residue of that class.
+/// Classes that share a residue are merged, so "KQ,QE" is the same as "KQE".
+/// B (D or N), Z (E or Q) and J (I or L) join the class that holds both residues they stand for, unless they
+/// are part of a class themselves. X can be any residue, so it is always kept and can't be part of a class.
+#[derive(Debug, Clone)]
+pub struct Equalization {
+    classes: String,
+    table: [u8; 256],
+}
+
+impl Equalization {
+    /// The set of classes, as it is recorded in the build output
+    pub fn name(&self) -> &str {
+        &self.classes
+    }
+
+    /// Replace every residue by the first residue of its class
+    pub fn apply(&self, sequence: &[u8]) -> Vec<u8> {
+        sequence.iter().map(|&x| self.table[x as usize]).collect()
+    }
+}
+
+impl Default for Equalization {
+    fn default() -> Self {
+        DEFAULT_EQUALIZATION.parse().unwrap()
+    }
+}
+
+impl FromStr for Equalization {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut groups: Vec<Vec<u8>> = Vec::new();
+
+        for class in s.split(',') {
+            let class = class.trim();
+            if class.len() < 2 || !class.bytes().all(|b| b.is_ascii_uppercase()) {
+                return Err(Error::msg(format!(
+                    "Invalid equivalence class \"{class}\" in \"{s}\", expected at least two residues"
+                )));
+            }
+            if class.contains('X') {
+                return Err(Error::msg(format!(
+                    "Invalid equivalence class \"{class}\" in \"{s}\", X can be any residue"
+                )));
+            }
+
+            // Merge the groups that share a residue with this class into the first of them
+            let mut merged: Vec<u8> = Vec::new();
+            groups.retain(|group| {
+                let overlaps = group.iter().any(|r| class.as_bytes().contains(r));
+                if overlaps {
+                    merged.extend(group);
+                }
+                !overlaps
+            });
+            for residue in class.bytes() {
+                if !merged.contains(&residue) {
+                    merged.push(residue);
+                }
+            }
+            groups.push(merged);
+        }
+
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        for group in &groups {
+            for &residue in group {
+                table[residue as usize] = group[0];
+            }
+        }
+
+        for (ambiguous, residues) in AMBIGUOUS_RESIDUES {
+            let explicit = groups.iter().any(|group| group.contains(&ambiguous));
+            if !explicit && table[residues[0] as usize] == table[residues[1] as usize] {
+                table[ambiguous as usize] = table[residues[0] as usize];
+            }
+        }
+
+        Ok(Equalization {
+            classes: s.trim().to_string(),
+            table,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn equalize(classes: &str, sequence: &str) -> String {
+        let equalization: Equalization = classes.parse().unwrap();
+        String::from_utf8(equalization.apply(sequence.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_default_equalization() {
+        assert_eq!(Equalization::default().apply(b"MILKIL"), b"MLLKLL".to_vec());
+
+        // J stands for I or L, the other ambiguous residues are kept
+        assert_eq!(Equalization::default().apply(b"JBZX"), b"LBZX".to_vec());
+    }
+
+    #[test]
+    fn test_ambiguous_residues() {
+        assert_eq!(equalize("LI,ND,QE", "JBZX"), "LNQX");
+        assert_eq!(equalize("LI,KQ,DN", "JBZ"), "LDZ");
+
+        // Ambiguous residues in a class are not moved to the class of their residues
+        assert_eq!(equalize("LI,BK", "JBK"), "LBB");
+    }
+
+    #[test]
+    fn test_equalization() {
+        assert_eq!(equalize("LIJ,KQ,ND,NB,EZ", "IJKQNDBEZX"), "LLKKNNNEEX");
+        assert_eq!(equalize("LI,KQ", "IKQ"), "LKK");
+
+        // Q is in both classes, so K, Q and E are all equivalent
+        assert_eq!(equalize("KQ,QE", "EQK"), "KKK");
+        assert_eq!(equalize("KQ,DN,QE", "EDN"), "KDD");
+    }
+
+    #[test]
+    fn test_parse_equalization() {
+        assert_eq!(" LI,KQ ".parse::<Equalization>().unwrap().name(), "LI,KQ");
+
+        for invalid in ["", "L", "LI,", "li", "L-I", "LIX"] {
+            assert!(invalid.parse::<Equalization>().is_err(), "{invalid}");
+        }
+    }
+}
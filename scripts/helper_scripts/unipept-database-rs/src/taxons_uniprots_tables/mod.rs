@@ -1,4 +1,6 @@
+pub mod equalization;
 pub mod models;
+pub mod protease;
 pub mod tab_parser;
 pub mod table_writer;
 pub mod taxon_list;
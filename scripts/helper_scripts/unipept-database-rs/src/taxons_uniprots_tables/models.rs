@@ -1,6 +1,14 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
 use anyhow::{Context, Result};
+use memchr::memmem;
 use strum_macros::{Display, EnumCount, EnumIter, EnumString};
 
+use crate::dat_parser::models::ReviewStatus;
+use crate::dat_parser::variants::{SequenceVariant, VariantKind};
+use crate::taxons_uniprots_tables::protease::Protease;
+
 #[derive(Debug)]
 pub struct Entry {
     pub min_length: u32,
@@ -18,6 +26,9 @@ pub struct Entry {
     pub ec_references: Vec<String>,
     pub go_references: Vec<String>,
     pub ip_references: Vec<String>,
+    pub variants: Vec<SequenceVariant>,
+    /// Whether the input has a variants column, which holds the INIT_MET annotations of reviewed entries
+    pub variants_annotated: bool,
 }
 
 impl Entry {
@@ -53,34 +64,309 @@ impl Entry {
             ec_references,
             go_references,
             ip_references,
+            variants: Vec::new(),
+            variants_annotated: false,
         })
     }
+
+    /// Whether the initiator methionine is removed from the mature protein
+    /// Reviewed entries are annotated with an INIT_MET variant if they do, when the input has a variants column.
+    /// For other entries it is predicted from the next residue, unless they have an INIT_MET variant.
+    pub fn removes_init_met(&self) -> bool {
+        let content = self.sequence.as_bytes();
+        if content.first() != Some(&b'M') {
+            return false;
+        }
+
+        if self.variants.iter().any(|v| v.kind == VariantKind::InitMet) {
+            return true;
+        }
+
+        let annotated = self.variants_annotated
+            && ReviewStatus::from_db_type(&self.type_) == Some(ReviewStatus::Reviewed);
+        !annotated
+            && content
+                .get(1)
+                .is_some_and(|r| INIT_MET_SMALL_RESIDUES.contains(r))
+    }
 }
 
-pub fn calculate_entry_digest(
-    sequence: &String,
+/// Residues with a small side chain, which allow methionine aminopeptidases to remove the methionine before them
+const INIT_MET_SMALL_RESIDUES: &[u8] = b"ACGPSTV";
+
+/// Which ends of a peptide have to be at a cleavage site (or a terminus of the protein)
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Display, EnumString)]
+pub enum DigestionMode {
+    /// Both ends
+    #[default]
+    #[strum(serialize = "specific")]
+    Specific,
+    /// At least one of the ends
+    #[strum(serialize = "semi-specific")]
+    SemiSpecific,
+    /// Neither end, every subsequence within the length bounds is a peptide
+    #[strum(serialize = "non-specific")]
+    NonSpecific,
+}
+
+/// Cleave a sequence into peptides with `protease`, keeping those within the length bounds
+/// Peptides with up to `missed_cleavages` missed cleavages are included, together with their amount of missed cleavages
+pub fn calculate_entry_digest<'a>(
+    sequence: &'a String,
+    protease: &dyn Protease,
+    missed_cleavages: usize,
     min_length: usize,
     max_length: usize,
-) -> Vec<&[u8]> {
-    let mut result = Vec::new();
+) -> Vec<(&'a [u8], usize)> {
+    digest(
+        sequence.as_bytes(),
+        protease,
+        DigestionMode::Specific,
+        missed_cleavages,
+        min_length,
+        max_length,
+    )
+    .collect()
+}
 
-    let mut start: usize = 0;
-    let length = sequence.len();
-    let content = sequence.as_bytes();
+/// Lazily cleave a sequence into the peptides of a digestion mode, keeping those within the length bounds
+/// The amount of missed cleavages of a peptide is the amount of cleavage sites inside of it.
+/// Non-specific peptides are not limited to `missed_cleavages`, since any subsequence is a peptide.
+pub fn digest<'a>(
+    content: &'a [u8],
+    protease: &dyn Protease,
+    mode: DigestionMode,
+    missed_cleavages: usize,
+    min_length: usize,
+    max_length: usize,
+) -> impl Iterator<Item = (&'a [u8], usize)> + 'a {
+    PeptideRanges::new(
+        content,
+        protease,
+        mode,
+        missed_cleavages,
+        min_length,
+        max_length,
+    )
+    .map(|(r, missed)| (&content[r], missed))
+}
 
-    for (i, c) in content.iter().enumerate() {
-        if (*c == b'K' || *c == b'R') && (i + 1 < length && content[i + 1] != b'P') {
-            if i + 1 - start >= min_length && i + 1 - start <= max_length {
-                result.push(&content[start..i + 1]);
+/// Iterator over the ranges of the peptides in a sequence, together with their amount of missed cleavages
+/// Peptides are yielded by start position, and by length for the same start position
+struct PeptideRanges {
+    mode: DigestionMode,
+    missed_cleavages: usize,
+    min_length: usize,
+    max_length: usize,
+    length: usize,
+    // The amount of cleavage sites up to every position of the sequence
+    sites: Vec<usize>,
+    start: usize,
+    end: usize,
+}
+
+impl PeptideRanges {
+    fn new(
+        content: &[u8],
+        protease: &dyn Protease,
+        mode: DigestionMode,
+        missed_cleavages: usize,
+        min_length: usize,
+        max_length: usize,
+    ) -> Self {
+        let length = content.len();
+        let mut sites = Vec::with_capacity(length + 1);
+        sites.push(0);
+        for i in 1..=length {
+            let site = i < length && protease.cleaves(content[i - 1], content[i]);
+            sites.push(sites[i - 1] + site as usize);
+        }
+
+        PeptideRanges {
+            mode,
+            missed_cleavages,
+            min_length: min_length.max(1),
+            max_length,
+            length,
+            sites,
+            start: 0,
+            end: min_length.max(1),
+        }
+    }
+
+    /// Whether a peptide can start or end at this position
+    fn is_site(&self, position: usize) -> bool {
+        position == 0 || position == self.length || self.sites[position] != self.sites[position - 1]
+    }
+
+    /// Whether a range of the sequence is one of the peptides this iterator yields
+    fn contains(&self, range: &Range<usize>) -> bool {
+        if range.len() < self.min_length || range.len() > self.max_length || range.end > self.length
+        {
+            return false;
+        }
+
+        let missed = self.sites[range.end - 1] - self.sites[range.start];
+        match self.mode {
+            DigestionMode::Specific => {
+                missed <= self.missed_cleavages
+                    && self.is_site(range.start)
+                    && self.is_site(range.end)
+            }
+            DigestionMode::SemiSpecific => {
+                missed <= self.missed_cleavages
+                    && (self.is_site(range.start) || self.is_site(range.end))
             }
+            DigestionMode::NonSpecific => true,
+        }
+    }
+}
+
+impl Iterator for PeptideRanges {
+    type Item = (Range<usize>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.start < self.length {
+            let start_site = self.is_site(self.start);
+            let last_end = (self.start + self.max_length).min(self.length);
+
+            if self.mode != DigestionMode::Specific || start_site {
+                while self.end <= last_end {
+                    let end = self.end;
+                    self.end += 1;
 
-            start = i + 1;
+                    // Longer peptides only contain more cleavage sites
+                    let missed = self.sites[end - 1] - self.sites[self.start];
+                    if self.mode != DigestionMode::NonSpecific && missed > self.missed_cleavages {
+                        break;
+                    }
+
+                    let valid = match self.mode {
+                        DigestionMode::Specific => self.is_site(end),
+                        DigestionMode::SemiSpecific => start_site || self.is_site(end),
+                        DigestionMode::NonSpecific => true,
+                    };
+
+                    if valid {
+                        return Some((self.start..end, missed));
+                    }
+                }
+            }
+
+            self.start += 1;
+            self.end = self.start + self.min_length;
         }
+
+        None
     }
+}
 
-    // Add last one
-    if length - start >= min_length && length - start <= max_length {
-        result.push(&content[start..length]);
+/// Calculate the N-terminal peptides of a sequence without its initiator methionine,
+/// which are not also peptides of the full sequence
+pub fn calculate_init_met_digest<'a>(
+    sequence: &'a String,
+    protease: &dyn Protease,
+    mode: DigestionMode,
+    missed_cleavages: usize,
+    min_length: usize,
+    max_length: usize,
+) -> Vec<(&'a [u8], usize)> {
+    let content = sequence.as_bytes();
+    if content.len() < 2 || content[0] != b'M' {
+        return Vec::new();
+    }
+
+    // The ends of the peptides that start right after the methionine in the full sequence
+    let existing: HashSet<usize> = PeptideRanges::new(
+        content,
+        protease,
+        mode,
+        missed_cleavages,
+        min_length,
+        max_length,
+    )
+    .take_while(|(r, _)| r.start <= 1)
+    .filter(|(r, _)| r.start == 1)
+    .map(|(r, _)| r.end)
+    .collect();
+
+    let mature = &content[1..];
+    PeptideRanges::new(
+        mature,
+        protease,
+        mode,
+        missed_cleavages,
+        min_length,
+        max_length,
+    )
+    .take_while(|(r, _)| r.start == 0)
+    .filter(|(r, _)| !existing.contains(&(r.end + 1)))
+    .map(|(r, missed)| (&mature[r], missed))
+    .collect()
+}
+
+/// Calculate the peptides that carry one of the variants of an entry
+/// Only peptides that overlap the changed residues and don't also occur in the reference sequence are returned,
+/// together with their amount of missed cleavages and the variant they were generated from
+/// INIT_MET variants are skipped, those peptides are calculated by `calculate_init_met_digest`
+pub fn calculate_variant_digest<'a>(
+    sequence: &String,
+    variants: &'a [SequenceVariant],
+    protease: &dyn Protease,
+    mode: DigestionMode,
+    missed_cleavages: usize,
+    min_length: usize,
+    max_length: usize,
+) -> Vec<(Vec<u8>, usize, &'a SequenceVariant)> {
+    let mut result = Vec::new();
+    if variants.is_empty() {
+        return result;
+    }
+
+    // Only the cleavage sites of the reference are kept, instead of all of its peptides,
+    // a variant peptide is looked up by checking where it occurs in the reference sequence
+    let reference_content = sequence.as_bytes();
+    let reference = PeptideRanges::new(
+        reference_content,
+        protease,
+        mode,
+        missed_cleavages,
+        min_length,
+        max_length,
+    );
+    let in_reference = |peptide: &[u8]| {
+        memmem::find_iter(reference_content, peptide)
+            .any(|start| reference.contains(&(start..start + peptide.len())))
+    };
+
+    for variant in variants.iter().filter(|v| v.kind != VariantKind::InitMet) {
+        let Some(content) = variant.apply(sequence.as_bytes()) else {
+            continue;
+        };
+
+        // The residues that were changed, a deletion still affects the peptide it occurs in
+        let changed = variant.start - 1..variant.start - 1 + variant.replacement.len();
+        let mut seen: HashSet<&[u8]> = HashSet::new();
+
+        let ranges = PeptideRanges::new(
+            &content,
+            protease,
+            mode,
+            missed_cleavages,
+            min_length,
+            max_length,
+        );
+
+        for (range, missed) in ranges {
+            if range.end < changed.start || range.start > changed.end {
+                continue;
+            }
+
+            let peptide = &content[range];
+            if seen.insert(peptide) && !in_reference(peptide) {
+                result.push((peptide.to_vec(), missed, variant));
+            }
+        }
     }
 
     result
@@ -146,3 +432,189 @@ impl Taxon {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxons_uniprots_tables::protease::Enzyme;
+
+    #[test]
+    fn test_calculate_entry_digest() {
+        let sequence = "MAKPLRGGKRAAAK".to_string();
+
+        let peptides: Vec<&[u8]> = calculate_entry_digest(&sequence, &Enzyme::Trypsin, 0, 2, 10)
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect();
+        assert_eq!(peptides, vec![&b"MAKPLR"[..], b"GGK", b"AAAK"]);
+    }
+
+    #[test]
+    fn test_missed_cleavages() {
+        let sequence = "MAKPLRGGKRAAAK".to_string();
+
+        let peptides: Vec<(&[u8], usize)> =
+            calculate_entry_digest(&sequence, &Enzyme::Trypsin, 2, 2, 10);
+        assert_eq!(
+            peptides,
+            vec![
+                (&b"MAKPLR"[..], 0),
+                (b"MAKPLRGGK", 1),
+                (b"MAKPLRGGKR", 2),
+                (b"GGK", 0),
+                (b"GGKR", 1),
+                (b"GGKRAAAK", 2),
+                (b"RAAAK", 1),
+                (b"AAAK", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_calculate_init_met_digest() {
+        let sequence = "MAKPLRGGK".to_string();
+        let init_met = |mode, missed| -> Vec<(&[u8], usize)> {
+            calculate_init_met_digest(&sequence, &Enzyme::Trypsin, mode, missed, 2, 10)
+        };
+
+        assert_eq!(
+            init_met(DigestionMode::Specific, 0),
+            vec![(&b"AKPLR"[..], 0)]
+        );
+        assert_eq!(
+            init_met(DigestionMode::Specific, 1),
+            vec![(&b"AKPLR"[..], 0), (b"AKPLRGGK", 1)]
+        );
+        // Peptides that start after the methionine are already part of a non-specific digest
+        assert_eq!(init_met(DigestionMode::NonSpecific, 0), vec![]);
+    }
+
+    #[test]
+    fn test_removes_init_met() {
+        let entry = |sequence: &str, type_: &str| {
+            Entry::new(
+                5,
+                50,
+                type_.to_string(),
+                "P12345".to_string(),
+                sequence.to_string(),
+                "Protein".to_string(),
+                "1".to_string(),
+                "9606".to_string(),
+                vec![],
+                vec![],
+                vec![],
+            )
+            .unwrap()
+        };
+
+        assert!(entry("MAKPLR", "swissprot").removes_init_met());
+        assert!(!entry("MKKPLR", "swissprot").removes_init_met());
+        assert!(!entry("AKPLR", "swissprot").removes_init_met());
+
+        // Annotated removals are used even when the prediction disagrees
+        let mut annotated = entry("MKKPLR", "swissprot");
+        annotated.variants = vec!["INIT_MET:1:M>".parse().unwrap()];
+        assert!(annotated.removes_init_met());
+
+        // Reviewed entries without an annotated removal keep their methionine if the input has annotations
+        let mut reviewed = entry("MAKPLR", "swissprot");
+        reviewed.variants_annotated = true;
+        assert!(!reviewed.removes_init_met());
+
+        let mut unreviewed = entry("MAKPLR", "trembl");
+        unreviewed.variants_annotated = true;
+        assert!(unreviewed.removes_init_met());
+    }
+
+    fn digest_strings(sequence: &str, mode: DigestionMode, min: usize, max: usize) -> Vec<String> {
+        digest(sequence.as_bytes(), &Enzyme::Trypsin, mode, 0, min, max)
+            .map(|(p, _)| String::from_utf8(p.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_semi_specific_digest() {
+        assert_eq!(
+            digest_strings("AKPLRGG", DigestionMode::SemiSpecific, 2, 4),
+            vec!["AK", "AKP", "AKPL", "KPLR", "PLR", "LR", "GG"]
+        );
+    }
+
+    #[test]
+    fn test_non_specific_digest() {
+        let peptides = digest_strings("MAKPLR", DigestionMode::NonSpecific, 2, 3);
+        assert_eq!(
+            peptides,
+            vec!["MA", "MAK", "AK", "AKP", "KP", "KPL", "PL", "PLR", "LR"]
+        );
+
+        // Every subsequence within the length bounds, so missed cleavages are only counted
+        let missed: Vec<usize> = digest(
+            b"AKRA",
+            &Enzyme::Trypsin,
+            DigestionMode::NonSpecific,
+            0,
+            4,
+            4,
+        )
+        .map(|(_, missed)| missed)
+        .collect();
+        assert_eq!(missed, vec![2]);
+    }
+
+    #[test]
+    fn test_calculate_variant_digest() {
+        let sequence = "MAKPLRGGKRAAAK".to_string();
+        let variants: Vec<SequenceVariant> = ["VARIANT:4:P>L", "MUTAGEN:8:G>R", "VARIANT:12:A>"]
+            .iter()
+            .map(|v| v.parse().unwrap())
+            .collect();
+
+        let peptides: Vec<(String, String)> = calculate_variant_digest(
+            &sequence,
+            &variants,
+            &Enzyme::Trypsin,
+            DigestionMode::Specific,
+            0,
+            2,
+            10,
+        )
+        .into_iter()
+        .map(|(p, _, v)| (String::from_utf8(p).unwrap(), v.to_string()))
+        .collect();
+
+        assert_eq!(
+            peptides,
+            vec![
+                ("MAK".to_string(), "VARIANT:4:P>L".to_string()),
+                ("LLR".to_string(), "VARIANT:4:P>L".to_string()),
+                ("GR".to_string(), "MUTAGEN:8:G>R".to_string()),
+                ("AAK".to_string(), "VARIANT:12:A>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variant_digest_reference_lookup() {
+        // The variant peptide GGK also occurs elsewhere in the reference digest, but GGKA only as a substring
+        let sequence = "GGKAGKRGGKA".to_string();
+        let variants: Vec<SequenceVariant> = ["VARIANT:5:G>A"]
+            .iter()
+            .map(|v| v.parse().unwrap())
+            .collect();
+
+        let peptides = |mode| -> Vec<String> {
+            calculate_variant_digest(&sequence, &variants, &Enzyme::Trypsin, mode, 1, 3, 4)
+                .into_iter()
+                .map(|(p, _, _)| String::from_utf8(p).unwrap())
+                .collect()
+        };
+
+        assert_eq!(peptides(DigestionMode::Specific), vec!["AAK", "AAKR"]);
+        assert_eq!(
+            peptides(DigestionMode::NonSpecific),
+            vec!["GKAA", "KAA", "KAAK", "AAK", "AAKR", "AKR", "AKRG"]
+        );
+    }
+}
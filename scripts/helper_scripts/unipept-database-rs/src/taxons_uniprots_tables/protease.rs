@@ -0,0 +1,254 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+use anyhow::{Context, Error, Result};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// An enzyme that cleaves protein sequences into peptides
+pub trait Protease: Send + Sync {
+    /// The name of this protease, as it is recorded in the build output
+    fn name(&self) -> String;
+
+    /// Whether the protease cleaves the bond between the residues `before` and `after`
+    fn cleaves(&self, before: u8, after: u8) -> bool;
+}
+
+/// Built-in proteases, with the specificity listed by Expasy PeptideCutter
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString, EnumIter)]
+#[strum(ascii_case_insensitive)]
+pub enum Enzyme {
+    /// Cleaves after K or R, unless followed by P
+    #[strum(serialize = "trypsin")]
+    Trypsin,
+    /// Cleaves after K, unless followed by P
+    #[strum(serialize = "lys-c")]
+    LysC,
+    /// Cleaves after R, unless followed by P
+    #[strum(serialize = "arg-c")]
+    ArgC,
+    /// Cleaves after E, unless followed by P
+    #[strum(serialize = "glu-c")]
+    GluC,
+    /// Cleaves before D
+    #[strum(serialize = "asp-n")]
+    AspN,
+    /// Cleaves after F, W or Y, unless followed by P (high specificity)
+    #[strum(serialize = "chymotrypsin")]
+    Chymotrypsin,
+}
+
+impl Enzyme {
+    /// The specificity of this enzyme as a cleavage rule
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Enzyme::Trypsin => "[KR]|{P}",
+            Enzyme::LysC => "[K]|{P}",
+            Enzyme::ArgC => "[R]|{P}",
+            Enzyme::GluC => "[E]|{P}",
+            Enzyme::AspN => "[X]|[D]",
+            Enzyme::Chymotrypsin => "[FWY]|{P}",
+        }
+    }
+}
+
+impl Protease for Enzyme {
+    fn name(&self) -> String {
+        self.to_string()
+    }
+
+    fn cleaves(&self, before: u8, after: u8) -> bool {
+        match self {
+            Enzyme::Trypsin => matches!(before, b'K' | b'R') && after != b'P',
+            Enzyme::LysC => before == b'K' && after != b'P',
+            Enzyme::ArgC => before == b'R' && after != b'P',
+            Enzyme::GluC => before == b'E' && after != b'P',
+            Enzyme::AspN => after == b'D',
+            Enzyme::Chymotrypsin => matches!(before, b'F' | b'W' | b'Y') && after != b'P',
+        }
+    }
+}
+
+/// A custom protease, described by cleavage rules in the notation of Expasy PeptideCutter
+/// Every rule has the form "P1|P1'", describing the residues before and after the cleaved bond.
+/// Both sides are either "[ABC]" (one of these residues), "{ABC}" (any residue but these) or "X" (any residue).
+/// Multiple rules are separated by commas, the protease cleaves a bond if any of them matches (eg. "[FWY]|{P},[L]|{P}").
+pub struct CleavageRule {
+    rule: String,
+    sites: Vec<([bool; 256], [bool; 256])>,
+}
+
+impl FromStr for CleavageRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_site = |site: &str| -> Option<([bool; 256], [bool; 256])> {
+            let (before, after) = site.trim().split_once('|')?;
+            Some((parse_residues(before)?, parse_residues(after)?))
+        };
+
+        let sites = s
+            .split(',')
+            .map(|site| {
+                parse_site(site).with_context(|| format!("Invalid cleavage site \"{}\"", site))
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Unable to parse cleavage rule \"{s}\""))?;
+
+        Ok(CleavageRule {
+            rule: s.trim().to_string(),
+            sites,
+        })
+    }
+}
+
+/// Parse one side of a cleavage site into a lookup table of the residues it matches
+fn parse_residues(s: &str) -> Option<[bool; 256]> {
+    let s = s.trim();
+    let (residues, negated) = if s == "X" {
+        ("X", false)
+    } else if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        (inner, false)
+    } else if let Some(inner) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+        (inner, true)
+    } else {
+        return None;
+    };
+
+    if residues.is_empty() || !residues.bytes().all(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+
+    let mut table = [negated; 256];
+    if residues.contains('X') {
+        table = [!negated; 256];
+    } else {
+        for residue in residues.bytes() {
+            table[residue as usize] = !negated;
+        }
+    }
+
+    Some(table)
+}
+
+impl Protease for CleavageRule {
+    fn name(&self) -> String {
+        self.rule.clone()
+    }
+
+    fn cleaves(&self, before: u8, after: u8) -> bool {
+        self.sites
+            .iter()
+            .any(|(b, a)| b[before as usize] && a[after as usize])
+    }
+}
+
+/// Parse the name of a built-in enzyme, or a custom cleavage rule
+pub fn parse_protease(s: &str) -> Result<Box<dyn Protease>> {
+    if let Ok(enzyme) = Enzyme::from_str(s) {
+        return Ok(Box::new(enzyme));
+    }
+
+    let rule: CleavageRule = s.parse().with_context(|| {
+        format!(
+            "\"{s}\" is neither a known protease ({}) nor a valid cleavage rule",
+            Enzyme::iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    })?;
+    Ok(Box::new(rule))
+}
+
+/// The ranges of the peptides a protease cleaves a sequence into, regardless of their length
+pub fn cleavage_ranges<'a>(
+    protease: &'a dyn Protease,
+    content: &'a [u8],
+) -> impl Iterator<Item = Range<usize>> + 'a {
+    let length = content.len();
+    let ends = (1..length)
+        .filter(move |&i| protease.cleaves(content[i - 1], content[i]))
+        .chain(std::iter::once(length));
+
+    let mut start = 0;
+    ends.map(move |end| {
+        let range = start..end;
+        start = end;
+        range
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(protease: &dyn Protease, sequence: &str) -> Vec<String> {
+        cleavage_ranges(protease, sequence.as_bytes())
+            .map(|r| sequence[r].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_enzymes() {
+        let sequence = "MAKPLRGDKEFWYPRAD";
+
+        assert_eq!(
+            digest(&Enzyme::Trypsin, sequence),
+            vec!["MAKPLR", "GDK", "EFWYPR", "AD"]
+        );
+        assert_eq!(
+            digest(&Enzyme::LysC, sequence),
+            vec!["MAKPLRGDK", "EFWYPRAD"]
+        );
+        assert_eq!(
+            digest(&Enzyme::ArgC, sequence),
+            vec!["MAKPLR", "GDKEFWYPR", "AD"]
+        );
+        assert_eq!(
+            digest(&Enzyme::GluC, sequence),
+            vec!["MAKPLRGDKE", "FWYPRAD"]
+        );
+        assert_eq!(
+            digest(&Enzyme::AspN, sequence),
+            vec!["MAKPLRG", "DKEFWYPRA", "D"]
+        );
+        assert_eq!(
+            digest(&Enzyme::Chymotrypsin, sequence),
+            vec!["MAKPLRGDKEF", "W", "YPRAD"]
+        );
+    }
+
+    #[test]
+    fn test_enzyme_rules() {
+        // Every built-in enzyme cleaves exactly the same bonds as its rule
+        for enzyme in Enzyme::iter() {
+            let rule: CleavageRule = enzyme.rule().parse().unwrap();
+
+            for before in b'A'..=b'Z' {
+                for after in b'A'..=b'Z' {
+                    assert_eq!(
+                        enzyme.cleaves(before, after),
+                        rule.cleaves(before, after),
+                        "{enzyme} {}{}",
+                        before as char,
+                        after as char
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_protease() {
+        assert_eq!(parse_protease("Lys-C").unwrap().name(), "lys-c");
+
+        let custom = parse_protease("[FWY]|{P}, [L]|X").unwrap();
+        assert_eq!(custom.name(), "[FWY]|{P}, [L]|X");
+        assert_eq!(digest(custom.as_ref(), "AFPLAWA"), vec!["AFPL", "AW", "A"]);
+
+        for invalid in ["pepsin", "[KR]", "[kr]|{P}", "[]|X", "[KR]|{P},"] {
+            assert!(parse_protease(invalid).is_err(), "{invalid}");
+        }
+    }
+}
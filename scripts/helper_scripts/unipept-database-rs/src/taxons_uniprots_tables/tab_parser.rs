@@ -2,6 +2,8 @@ use anyhow::{Context, Error, Result};
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Lines, Stdin};
 
+use crate::dat_parser::utils::VARIANTS_COLUMN;
+use crate::dat_parser::variants::SequenceVariant;
 use crate::taxons_uniprots_tables::models::Entry;
 use crate::utils::files::open_sin;
 
@@ -87,6 +89,23 @@ impl Iterator for TabParser {
             ip_references,
         );
 
+        // The variants column is optional, and can be missing from the line if it is the last one and empty
+        let variants: Result<Vec<SequenceVariant>> = self
+            .header_map
+            .get(VARIANTS_COLUMN)
+            .and_then(|&i| fields.get(i))
+            .map_or("", |x| x.trim())
+            .split(';')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse())
+            .collect();
+
+        let entry = entry.and_then(|mut entry| {
+            entry.variants = variants?;
+            entry.variants_annotated = self.header_map.contains_key(VARIANTS_COLUMN);
+            Ok(entry)
+        });
+
         if self.verbose {
             eprintln!("INFO VERBOSE: TSV line parsed: {}", line);
         }
@@ -6,7 +6,11 @@ use std::path::PathBuf;
 use anyhow::{Context, Result};
 use bit_vec::BitVec;
 
-use crate::taxons_uniprots_tables::models::{calculate_entry_digest, Entry};
+use crate::taxons_uniprots_tables::equalization::Equalization;
+use crate::taxons_uniprots_tables::models::{
+    calculate_init_met_digest, calculate_variant_digest, digest, DigestionMode, Entry,
+};
+use crate::taxons_uniprots_tables::protease::{Enzyme, Protease};
 use crate::taxons_uniprots_tables::taxon_list::parse_taxon_file_basic;
 use crate::taxons_uniprots_tables::utils::now_str;
 use crate::utils::files::open_write;
@@ -28,6 +32,13 @@ pub struct TableWriter {
     go_count: i64,
     ec_count: i64,
     ip_count: i64,
+
+    protease: Box<dyn Protease>,
+    digestion_mode: DigestionMode,
+    missed_cleavages: usize,
+    init_met_excision: bool,
+    variant_peptides: bool,
+    equalization: Equalization,
 }
 
 impl TableWriter {
@@ -54,9 +65,55 @@ impl TableWriter {
             go_count: 0,
             ec_count: 0,
             ip_count: 0,
+
+            protease: Box::new(Enzyme::Trypsin),
+            digestion_mode: DigestionMode::Specific,
+            missed_cleavages: 0,
+            init_met_excision: false,
+            variant_peptides: false,
+            equalization: Equalization::default(),
         })
     }
 
+    /// Digest the sequences with this protease instead of trypsin
+    pub fn with_protease(mut self, protease: Box<dyn Protease>) -> Self {
+        self.protease = protease;
+        self
+    }
+
+    /// Which ends of the peptides have to be at a cleavage site of the protease
+    pub fn with_digestion_mode(mut self, mode: DigestionMode) -> Self {
+        self.digestion_mode = mode;
+        self
+    }
+
+    /// Also write peptides with up to `missed_cleavages` missed cleavages
+    /// If this is not 0, an extra column with the amount of missed cleavages is added to the peptides table
+    pub fn with_missed_cleavages(mut self, missed_cleavages: usize) -> Self {
+        self.missed_cleavages = missed_cleavages;
+        self
+    }
+
+    /// Also write the N-terminal peptides without the initiator methionine, for entries that remove it
+    /// This adds an extra column to the peptides table that marks these peptides
+    pub fn with_init_met_excision(mut self, init_met_excision: bool) -> Self {
+        self.init_met_excision = init_met_excision;
+        self
+    }
+
+    /// Also write the peptides that carry one of the sequence variants of an entry
+    /// This adds an extra column to the peptides table with the variant, which is empty for reference peptides
+    pub fn with_variant_peptides(mut self, variant_peptides: bool) -> Self {
+        self.variant_peptides = variant_peptides;
+        self
+    }
+
+    /// Equivalence classes to equalize the peptides with, instead of only I and L
+    pub fn with_equalization(mut self, equalization: Equalization) -> Self {
+        self.equalization = equalization;
+        self
+    }
+
     // Store a complete entry in the database
     pub fn store(&mut self, entry: Entry) -> Result<()> {
         let id = self
@@ -81,7 +138,7 @@ impl TableWriter {
                 .context("Error writing Interpro ref")?;
         }
 
-        let go_ids = entry.go_references.into_iter();
+        let go_ids = entry.go_references.iter().cloned();
         let ec_ids = entry
             .ec_references
             .iter()
@@ -99,42 +156,80 @@ impl TableWriter {
             .collect::<Vec<String>>()
             .join(";");
 
-        for sequence in calculate_entry_digest(
-            &entry.sequence,
+        // Peptides are written as they are generated, as there can be a lot of them in non-specific mode
+        let peptides = digest(
+            entry.sequence.as_bytes(),
+            self.protease.as_ref(),
+            self.digestion_mode,
+            self.missed_cleavages,
             entry.min_length as usize,
             entry.max_length as usize,
-        ) {
-            self.write_peptide(
-                sequence
-                    .iter()
-                    .map(|&x| if x == b'I' { b'L' } else { x })
-                    .collect(),
-                id,
-                sequence,
-                &summary,
-                entry.taxon_id,
-            )
-            .context("Failed to write peptide")?;
+        );
+        for (sequence, missed) in peptides {
+            self.write_peptide(sequence, id, &summary, entry.taxon_id, missed, false, None)
+                .context("Failed to write peptide")?;
+        }
+
+        if self.init_met_excision && entry.removes_init_met() {
+            for (sequence, missed) in calculate_init_met_digest(
+                &entry.sequence,
+                self.protease.as_ref(),
+                self.digestion_mode,
+                self.missed_cleavages,
+                entry.min_length as usize,
+                entry.max_length as usize,
+            ) {
+                self.write_peptide(sequence, id, &summary, entry.taxon_id, missed, true, None)
+                    .context("Failed to write peptide without initiator methionine")?;
+            }
+        }
+
+        if self.variant_peptides {
+            for (sequence, missed, variant) in calculate_variant_digest(
+                &entry.sequence,
+                &entry.variants,
+                self.protease.as_ref(),
+                self.digestion_mode,
+                self.missed_cleavages,
+                entry.min_length as usize,
+                entry.max_length as usize,
+            ) {
+                self.write_peptide(
+                    &sequence,
+                    id,
+                    &summary,
+                    entry.taxon_id,
+                    missed,
+                    false,
+                    Some(&variant.to_string()),
+                )
+                .context("Failed to write variant peptide")?;
+            }
         }
 
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn write_peptide(
         &mut self,
-        sequence: Vec<u8>,
-        id: i64,
         original_sequence: &[u8],
+        id: i64,
         annotations: &String,
         taxon_id: i32,
+        missed_cleavages: usize,
+        init_met_excised: bool,
+        variant: Option<&str>,
     ) -> Result<()> {
         self.peptide_count += 1;
 
-        writeln!(
+        let equalized = self.equalization.apply(original_sequence);
+
+        write!(
             &mut self.peptides,
             "{}\t{}\t{}\t{}\t{}\t{}",
             self.peptide_count,
-            String::from_utf8_lossy(&sequence),
+            String::from_utf8_lossy(&equalized),
             String::from_utf8_lossy(original_sequence),
             id,
             annotations,
@@ -142,6 +237,22 @@ impl TableWriter {
         )
         .context("Error writing to TSV")?;
 
+        if self.missed_cleavages > 0 {
+            write!(&mut self.peptides, "\t{}", missed_cleavages).context("Error writing to TSV")?;
+        }
+
+        if self.init_met_excision {
+            write!(&mut self.peptides, "\t{}", init_met_excised as u8)
+                .context("Error writing to TSV")?;
+        }
+
+        if self.variant_peptides {
+            write!(&mut self.peptides, "\t{}", variant.unwrap_or_default())
+                .context("Error writing to TSV")?;
+        }
+
+        writeln!(&mut self.peptides).context("Error writing to TSV")?;
+
         Ok(())
     }
 
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::thread;
+
+use anyhow::{Context, Error, Result};
+use crossbeam_channel::{bounded, Receiver};
+use flate2::read::{GzDecoder, MultiGzDecoder};
+
+/// Length of the fixed part of a gzip header
+const GZIP_HEADER_LEN: usize = 12;
+/// Length of the gzip footer (CRC32 and uncompressed size)
+const GZIP_FOOTER_LEN: usize = 8;
+
+/// A reader for BGZF files (as written by bgzip), which consist of many small independent gzip members
+/// Every member stores its own compressed size, so the members can be split up without decompressing them,
+/// and decompressed by `threads` worker threads at the same time
+pub struct BgzfReader {
+    receiver: Receiver<(usize, Result<Vec<u8>>)>,
+    // Blocks that were decompressed before the ones that precede them in the input
+    pending: HashMap<usize, Result<Vec<u8>>>,
+    next_index: usize,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl BgzfReader {
+    /// Passing 0 as the amount of threads uses the amount of (virtual) CPUs available in your machine
+    pub fn new<R: Read + Send + 'static>(mut reader: R, mut threads: usize) -> Self {
+        if threads == 0 {
+            threads = num_cpus::get();
+        }
+
+        let (s_raw, r_raw) = bounded::<(usize, Vec<u8>)>(threads * 2);
+        let (s_decompressed, r_decompressed) = bounded::<(usize, Result<Vec<u8>>)>(threads * 2);
+
+        for _ in 0..threads {
+            let receiver = r_raw.clone();
+            let sender = s_decompressed.clone();
+
+            thread::spawn(move || {
+                for (index, block) in receiver {
+                    // The reader only disconnects when it was dropped, so there is no use in continuing
+                    if sender.send((index, decompress_block(&block))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        // Split the input into blocks and send them to the workers
+        // Errors are sent straight to the reader, as they end the stream
+        thread::spawn(move || {
+            let mut index: usize = 0;
+
+            loop {
+                match read_block(&mut reader) {
+                    Ok(Some(block)) => {
+                        if s_raw.send((index, block)).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        let _ = s_decompressed.send((index, Err(e)));
+                        break;
+                    }
+                }
+
+                index += 1;
+            }
+        });
+
+        Self {
+            receiver: r_decompressed,
+            pending: HashMap::new(),
+            next_index: 0,
+            current: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Get the next decompressed block in the order of the input
+    /// Returns None once all blocks have been read
+    fn next_block(&mut self) -> Option<Result<Vec<u8>>> {
+        loop {
+            if let Some(block) = self.pending.remove(&self.next_index) {
+                self.next_index += 1;
+                return Some(block);
+            }
+
+            let (index, block) = self.receiver.recv().ok()?;
+
+            if index == self.next_index {
+                self.next_index += 1;
+                return Some(block);
+            }
+
+            self.pending.insert(index, block);
+        }
+    }
+}
+
+impl Read for BgzfReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Blocks can be empty (eg. the EOF marker), so keep going until we find data
+        while self.position == self.current.len() {
+            match self.next_block() {
+                None => return Ok(0),
+                Some(Ok(block)) => {
+                    self.current = block;
+                    self.position = 0;
+                }
+                Some(Err(e)) => return Err(std::io::Error::other(format!("{e:#}"))),
+            }
+        }
+
+        let n = buf.len().min(self.current.len() - self.position);
+        buf[..n].copy_from_slice(&self.current[self.position..self.position + n]);
+        self.position += n;
+
+        Ok(n)
+    }
+}
+
+/// Read a single compressed block, using the BSIZE field in its header
+/// Returns None at the end of the input
+fn read_block<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let Some((mut block, block_size)) = read_header(reader)? else {
+        return Ok(None);
+    };
+
+    let header_len = block.len();
+    block.resize(block_size, 0);
+    reader
+        .read_exact(&mut block[header_len..])
+        .context("Error reading BGZF block")?;
+
+    Ok(Some(block))
+}
+
+/// Read the header of a block, including its extra field, and find the total size of the block
+/// Returns None at the end of the input
+fn read_header<R: Read>(reader: &mut R) -> Result<Option<(Vec<u8>, usize)>> {
+    let mut header = vec![0; GZIP_HEADER_LEN];
+
+    // Check for the end of the input before reading a full header
+    let first_read = read_fully(reader, &mut header).context("Error reading BGZF header")?;
+    if first_read == 0 {
+        return Ok(None);
+    }
+    if first_read < GZIP_HEADER_LEN {
+        return Err(Error::msg("Unexpected end of input in BGZF header"));
+    }
+
+    if header[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+        return Err(Error::msg("Invalid BGZF block header"));
+    }
+
+    let extra_len = u16::from_le_bytes([header[10], header[11]]) as usize;
+    header.resize(GZIP_HEADER_LEN + extra_len, 0);
+    reader
+        .read_exact(&mut header[GZIP_HEADER_LEN..])
+        .context("Error reading BGZF extra field")?;
+
+    let block_size =
+        find_block_size(&header[GZIP_HEADER_LEN..]).context("Missing BSIZE in BGZF extra field")?;
+
+    if block_size < header.len() + GZIP_FOOTER_LEN {
+        return Err(Error::msg(format!("Invalid BGZF block size {block_size}")));
+    }
+
+    Ok(Some((header, block_size)))
+}
+
+/// Position of a single block in a BGZF file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockOffset {
+    /// Offset of the block in the compressed file
+    pub compressed: u64,
+    /// Offset of the first byte of the block in the decompressed data
+    pub uncompressed: u64,
+}
+
+/// Find the offsets of every block in a BGZF file, without decompressing them
+pub fn block_offsets<R: Read + Seek>(reader: &mut R) -> Result<Vec<BlockOffset>> {
+    let mut blocks = Vec::new();
+    let mut compressed: u64 = reader.seek(SeekFrom::Start(0))?;
+    let mut uncompressed: u64 = 0;
+
+    while let Some((_, block_size)) = read_header(reader)? {
+        // The uncompressed size is stored in the last 4 bytes of the block
+        let mut size = [0; 4];
+        reader.seek(SeekFrom::Start(compressed + block_size as u64 - 4))?;
+        reader
+            .read_exact(&mut size)
+            .context("Error reading BGZF block size")?;
+
+        blocks.push(BlockOffset {
+            compressed,
+            uncompressed,
+        });
+
+        compressed += block_size as u64;
+        uncompressed += u32::from_le_bytes(size) as u64;
+    }
+
+    Ok(blocks)
+}
+
+/// Read `length` bytes of decompressed data, starting at decompressed `offset`
+/// Only the blocks that contain the requested data are decompressed
+pub fn read_at<R: Read + Seek>(
+    reader: &mut R,
+    blocks: &[BlockOffset],
+    offset: u64,
+    length: usize,
+) -> Result<Vec<u8>> {
+    // The last block that starts at or before the offset
+    let index = blocks
+        .partition_point(|b| b.uncompressed <= offset)
+        .checked_sub(1)
+        .context("Offset is not part of any BGZF block")?;
+    let block = blocks[index];
+
+    reader.seek(SeekFrom::Start(block.compressed))?;
+    let mut decoder = MultiGzDecoder::new(BufReader::new(reader));
+
+    // Skip the data in the first block that precedes the offset
+    let skip = offset - block.uncompressed;
+    let skipped = std::io::copy(&mut (&mut decoder).take(skip), &mut std::io::sink())?;
+
+    let mut data = vec![0; length];
+    if skipped < skip || read_fully(&mut decoder, &mut data)? < length {
+        return Err(Error::msg(format!(
+            "Unexpected end of BGZF data while reading {length} bytes at offset {offset}"
+        )));
+    }
+
+    Ok(data)
+}
+
+/// Find the total size of a block in the "BC" subfield of the extra field
+fn find_block_size(mut extra: &[u8]) -> Option<usize> {
+    while extra.len() >= 4 {
+        let subfield_len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+
+        if extra[..2] == *b"BC" && subfield_len == 2 && extra.len() >= 6 {
+            // BSIZE is the total block size minus one
+            return Some(u16::from_le_bytes([extra[4], extra[5]]) as usize + 1);
+        }
+
+        extra = extra.get(4 + subfield_len..)?;
+    }
+
+    None
+}
+
+fn decompress_block(block: &[u8]) -> Result<Vec<u8>> {
+    let footer = &block[block.len() - 4..];
+    let size = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]) as usize;
+
+    let mut result = Vec::with_capacity(size);
+    GzDecoder::new(block)
+        .read_to_end(&mut result)
+        .context("Error decompressing BGZF block")?;
+
+    Ok(result)
+}
+
+/// Read until the buffer is full or the end of the input is reached,
+/// returning the amount of bytes that were read
+pub fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+
+    while total < buf.len() {
+        match reader.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(total)
+}
+
+/// Write `data` as BGZF blocks of at most `block_size` bytes, used to test the reader
+#[cfg(test)]
+pub fn compress(data: &[u8], block_size: usize) -> Vec<u8> {
+    use flate2::write::DeflateEncoder;
+    use flate2::{Compression, Crc};
+    use std::io::Write;
+
+    let mut result = Vec::new();
+
+    // An empty block marks the end of the file
+    for chunk in data.chunks(block_size).chain(std::iter::once(&[][..])) {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(chunk).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut crc = Crc::new();
+        crc.update(chunk);
+
+        let total_size = GZIP_HEADER_LEN + 6 + compressed.len() + GZIP_FOOTER_LEN;
+        result.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0]);
+        result.extend_from_slice(b"BC");
+        result.extend_from_slice(&2u16.to_le_bytes());
+        result.extend_from_slice(&((total_size - 1) as u16).to_le_bytes());
+        result.extend_from_slice(&compressed);
+        result.extend_from_slice(&crc.sum().to_le_bytes());
+        result.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_bgzf_reader() {
+        let data: Vec<u8> = (0..200_000)
+            .map(|i| b"ACDEFGHIKLMNPQRSTVWY\n"[i % 21])
+            .collect();
+        let compressed = compress(&data, 1000);
+
+        let mut got = Vec::new();
+        BgzfReader::new(Cursor::new(compressed), 4)
+            .read_to_end(&mut got)
+            .unwrap();
+
+        assert_eq!(got, data);
+    }
+
+    #[test]
+    fn test_bgzf_reader_truncated() {
+        let compressed = compress(b"some data that gets cut off", 10);
+        let truncated = compressed[..compressed.len() - 40].to_vec();
+
+        let mut got = Vec::new();
+        let result = BgzfReader::new(Cursor::new(truncated), 2).read_to_end(&mut got);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_random_access() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut compressed = Cursor::new(compress(&data, 1000));
+
+        let blocks = block_offsets(&mut compressed).unwrap();
+        // 40 blocks with data, followed by an empty block that marks the end of the file
+        assert_eq!(blocks.len(), 41);
+        assert_eq!(blocks[1].uncompressed, 1000);
+        assert_eq!(blocks[40].uncompressed, 40_000);
+
+        // Ranges within a block, spanning multiple blocks, and at the end of the data
+        for (offset, length) in [(0, 10), (1500, 20), (999, 2002), (39_990, 10)] {
+            let got = read_at(&mut compressed, &blocks, offset, length).unwrap();
+            assert_eq!(got, &data[offset as usize..offset as usize + length]);
+        }
+
+        assert!(read_at(&mut compressed, &blocks, 39_990, 11).is_err());
+    }
+
+    #[test]
+    fn test_find_block_size() {
+        assert_eq!(find_block_size(&[b'B', b'C', 2, 0, 0x1b, 0]), Some(28));
+        assert_eq!(
+            find_block_size(&[b'X', b'Y', 1, 0, 9, b'B', b'C', 2, 0, 0x1b, 0]),
+            Some(28)
+        );
+        assert_eq!(find_block_size(&[b'X', b'Y', 1, 0, 9]), None);
+    }
+}
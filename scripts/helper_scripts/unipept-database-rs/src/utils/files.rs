@@ -1,8 +1,42 @@
 use anyhow::{Context, Result};
 use std::fs::{File, OpenOptions};
-use std::io::{stdin, BufReader, BufWriter, Stdin};
+use std::io::{stdin, BufRead, BufReader, BufWriter, Cursor, Read, Stdin};
 use std::path::PathBuf;
 
+use crate::utils::bgzf::{read_fully, BgzfReader};
+
+/// Amount of bytes needed to detect every supported compression format
+const MAGIC_BYTES_LEN: usize = 14;
+
+/// Compression formats that can be detected and decompressed by `open_input`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    /// Blocked gzip, as written by bgzip, which can be decompressed in parallel
+    Bgzip,
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    /// Detect the compression format based on the first bytes of the input
+    pub fn detect(header: &[u8]) -> Self {
+        match header {
+            // FEXTRA flag set, with a "BC" subfield as the first extra field
+            [0x1f, 0x8b, 0x08, flags, _, _, _, _, _, _, _, _, b'B', b'C', ..]
+                if flags & 0x04 != 0 =>
+            {
+                Compression::Bgzip
+            }
+            [0x1f, 0x8b, ..] => Compression::Gzip,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Compression::Zstd,
+            [0x04, 0x22, 0x4d, 0x18, ..] => Compression::Lz4,
+            _ => Compression::None,
+        }
+    }
+}
+
 /// Create a BufReader that reads from StdIn
 pub fn open_sin() -> BufReader<Stdin> {
     BufReader::new(stdin())
@@ -25,3 +59,144 @@ pub fn open_write(pb: &PathBuf) -> Result<BufWriter<File>> {
         .with_context(|| format!("Failed to open file \"{}\" for writing", pb.display()))?;
     Ok(BufWriter::new(file))
 }
+
+/// Create a BufReader for a file, or StdIn if no file is given,
+/// transparently decompressing gzip, bgzip, zstd and lz4 input
+/// Bgzip input is decompressed using `threads` threads
+pub fn open_input(pb: Option<&PathBuf>, threads: usize) -> Result<Box<dyn BufRead + Send>> {
+    match pb {
+        Some(pb) => {
+            let file = File::open(pb)
+                .with_context(|| format!("Failed to open file \"{}\" for reading", pb.display()))?;
+            decompress(file, threads)
+                .with_context(|| format!("Failed to read from file \"{}\"", pb.display()))
+        }
+        None => decompress(stdin(), threads).context("Failed to read from stdin"),
+    }
+}
+
+/// Open a file that can be memory-mapped, which has to be a regular, non-empty and uncompressed file
+/// Returns `None` for anything else (eg. compressed files or named pipes), which have to be read through `open_input`
+pub fn open_mappable(pb: &PathBuf) -> Result<Option<File>> {
+    let mut file = File::open(pb)
+        .with_context(|| format!("Failed to open file \"{}\" for reading", pb.display()))?;
+    let metadata = file
+        .metadata()
+        .with_context(|| format!("Failed to read metadata of \"{}\"", pb.display()))?;
+
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Ok(None);
+    }
+
+    let mut header = vec![0; MAGIC_BYTES_LEN];
+    let header_len = read_fully(&mut file, &mut header)
+        .with_context(|| format!("Failed to read from file \"{}\"", pb.display()))?;
+
+    Ok((Compression::detect(&header[..header_len]) == Compression::None).then_some(file))
+}
+
+/// Wrap a reader in a decoder for its compression format, detected from its first bytes
+pub fn decompress<R: Read + Send + 'static>(
+    mut reader: R,
+    threads: usize,
+) -> Result<Box<dyn BufRead + Send>> {
+    let mut header = vec![0; MAGIC_BYTES_LEN];
+    let header_len = read_fully(&mut reader, &mut header)?;
+    header.truncate(header_len);
+
+    let compression = Compression::detect(&header);
+
+    // Put the header back in front of the rest of the input
+    let reader = Cursor::new(header).chain(reader);
+
+    let result: Box<dyn BufRead + Send> = match compression {
+        Compression::None => Box::new(BufReader::new(reader)),
+        Compression::Gzip => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(reader))),
+        Compression::Bgzip => Box::new(BufReader::new(BgzfReader::new(reader, threads))),
+        Compression::Zstd => Box::new(BufReader::new(
+            zstd::Decoder::new(reader).context("Failed to initialize zstd decoder")?,
+        )),
+        Compression::Lz4 => Box::new(BufReader::new(lz4_flex::frame::FrameDecoder::new(reader))),
+    };
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const DATA: &[u8] = b"ID   001R_FRG3G   Reviewed;   256 AA.\nAC   Q6GZX4;\n//\n";
+
+    fn read_all(input: Vec<u8>) -> Vec<u8> {
+        let mut result = Vec::new();
+        decompress(Cursor::new(input), 2)
+            .unwrap()
+            .read_to_end(&mut result)
+            .unwrap();
+        result
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(DATA), Compression::None);
+        assert_eq!(Compression::detect(b""), Compression::None);
+        assert_eq!(Compression::detect(&gzip(DATA)), Compression::Gzip);
+        assert_eq!(
+            Compression::detect(&crate::utils::bgzf::compress(DATA, 16)),
+            Compression::Bgzip
+        );
+        assert_eq!(
+            Compression::detect(&zstd::encode_all(DATA, 0).unwrap()),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::detect(
+                &lz4_flex::frame::FrameEncoder::new(Vec::new())
+                    .finish()
+                    .unwrap()
+            ),
+            Compression::Lz4
+        );
+    }
+
+    #[test]
+    fn test_decompress_plain() {
+        assert_eq!(read_all(DATA.to_vec()), DATA);
+        assert_eq!(read_all(b"ID".to_vec()), b"ID");
+    }
+
+    #[test]
+    fn test_decompress_gzip_multi_member() {
+        let mut input = gzip(&DATA[..20]);
+        input.extend(gzip(&DATA[20..]));
+
+        assert_eq!(read_all(input), DATA);
+    }
+
+    #[test]
+    fn test_decompress_bgzip() {
+        assert_eq!(read_all(crate::utils::bgzf::compress(DATA, 16)), DATA);
+    }
+
+    #[test]
+    fn test_decompress_zstd() {
+        assert_eq!(read_all(zstd::encode_all(DATA, 0).unwrap()), DATA);
+    }
+
+    #[test]
+    fn test_decompress_lz4() {
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        encoder.write_all(DATA).unwrap();
+
+        assert_eq!(read_all(encoder.finish().unwrap()), DATA);
+    }
+}
@@ -1 +1,3 @@
+pub mod bgzf;
 pub mod files;
+pub mod xml;
@@ -0,0 +1,23 @@
+use std::io::BufRead;
+use std::num::NonZeroUsize;
+
+use anyhow::{Context, Error, Result};
+use uniprot::parser::{SequentialParser, ThreadedParser, UniprotDatabase};
+
+/// Create a sequential or threaded XML parser for a UniProt database, based on the amount of threads
+/// Zero threads uses all available CPUs
+pub fn xml_parser<D: UniprotDatabase + 'static, B: BufRead + Send + 'static>(
+    reader: B,
+    threads: usize,
+) -> Result<Box<dyn Iterator<Item = Result<D::Entry>>>> {
+    let parser: Box<dyn Iterator<Item = Result<D::Entry, uniprot::error::Error>>> = match threads {
+        0 => Box::new(ThreadedParser::<B, D>::new(reader)),
+        1 => Box::new(SequentialParser::<B, D>::new(reader)),
+        n => Box::new(ThreadedParser::<B, D>::with_threads(
+            reader,
+            NonZeroUsize::new(n).context("Error parsing number of threads as usize")?,
+        )),
+    };
+
+    Ok(Box::new(parser.map(|r| r.map_err(Error::new))))
+}